@@ -78,8 +78,6 @@ pub async fn g_create_folder(
         None => GOOGLE_FOLDER,
     };
 
-    dbg!(&save_path);
-
     let query = match parent.is_empty() {
         false => {
             format!(
@@ -139,10 +137,19 @@ pub async fn g_create_folder(
         .await?);
 }
 
-///Updates a file that already exists on google drive
+///Gets the name a file is uploaded under, the last component of `path`
+fn upload_name(path: &str) -> Result<&str> {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::FileNameError(path.to_string()))
+}
+
+///Updates a file that already exists on google drive, renaming it to the name of `path`
 pub async fn g_update(user_token: &UserToken, id: &str, path: &str) -> Result<String> {
     //Get file content
     let mut file = tokio::fs::File::open(path).await?;
+    let file_name = upload_name(path)?;
     let file_size = std::fs::metadata(path)?.len();
 
     let client = reqwest::Client::new();
@@ -155,11 +162,11 @@ pub async fn g_update(user_token: &UserToken, id: &str, path: &str) -> Result<St
         .patch(&url)
         .bearer_auth(&user_token.access_token)
         .header("X-Upload-Content-Type", "application/x-crypt")
+        .json(&serde_json::json!({ "name": file_name }))
         .send()
         .await?
         .error_for_status()?;
 
-    dbg!(&response);
     let session_uri = response
         .headers()
         .get(LOCATION)
@@ -175,7 +182,7 @@ pub async fn g_upload(user_token: &UserToken, path: &str, parent: &str) -> Resul
     //Get file content
     let mut file = File::open(path).await?;
     // let mut tmp; // to appease the compiler gods
-    let file_name = Path::new(path).file_name().unwrap().to_str().unwrap();
+    let file_name = upload_name(path)?;
 
     let file_size = std::fs::metadata(path)?.len();
 
@@ -370,7 +377,6 @@ pub async fn g_walk(user_token: &UserToken, name: &str) -> Result<DirInfo> {
     return Err(Error::FolderNotFoundError);
 }
 
-///
 pub async fn google_query_folders(
     user_token: &UserToken,
     folder_name: &str,
@@ -394,13 +400,10 @@ pub async fn google_query_folders(
 
     let resp = response.json::<Value>().await.unwrap();
 
-    dbg!(&resp);
-
     if let Some(files) = resp.get("files").and_then(Value::as_array) {
         let folders: Vec<(String, String)> = files
             .iter()
             .filter_map(|file| {
-                dbg!(&file);
                 if let (Some(id), Some(name)) = (
                     file.get("name").and_then(Value::as_str),
                     file.get("id").and_then(Value::as_str),
@@ -439,13 +442,10 @@ pub async fn google_query(
 
     let resp = response.json::<Value>().await.unwrap();
 
-    dbg!(&resp);
-
     if let Some(files) = resp.get("files").and_then(Value::as_array) {
         let folders: Vec<(String, String)> = files
             .iter()
             .filter_map(|file| {
-                dbg!(&file);
                 if let (Some(id), Some(name)) = (
                     file.get("name").and_then(Value::as_str),
                     file.get("id").and_then(Value::as_str),
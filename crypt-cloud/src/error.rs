@@ -37,6 +37,9 @@ pub enum Error {
     #[error("Error acessing root 'crypt' directory.")]
     RootDirectoryError,
 
+    #[error("Invalid file name: {0}")]
+    FileNameError(String),
+
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
 
@@ -6,7 +6,7 @@ use crypt_core::{
     filecrypt::{encrypt_file, FileCrypt},
     *,
 };
-use logfather::{info, logger};
+use logfather::{info, Logger};
 use rand::RngCore;
 
 #[cfg(target_os = "linux")]
@@ -37,7 +37,7 @@ static SHAKESPEARE_DECRYPT: &str = "benches\\files\\Shakespeare-decrypted.txt";
 
 // encrypt test with 850kb file
 pub fn enc_benchmark(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
     c.bench_function("full file encryption (dracula.txt)", |b| {
         b.iter(|| encrypt_file(DRACULA, &None))
@@ -46,7 +46,7 @@ pub fn enc_benchmark(c: &mut Criterion) {
 
 // encrypt test with 850kb file
 pub fn dracula_content_encryption(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
 
     // minumum setup needed to use encryption function
@@ -57,13 +57,13 @@ pub fn dracula_content_encryption(c: &mut Criterion) {
     let contents = get_vec_file_bytes(DRACULA);
 
     c.bench_function("encrypt contents of dracula", |b| {
-        b.iter(|| encryption::encrypt(&fc, &contents))
+        b.iter(|| encryption::encrypt(&fc, &contents, &[]))
     });
 }
 
 // encrypt test with 5mb file
 pub fn shakespeare_content_encryption(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
 
     // minumum setup needed to use encryption function
@@ -74,13 +74,13 @@ pub fn shakespeare_content_encryption(c: &mut Criterion) {
     let contents = get_vec_file_bytes(SHAKESPEARE);
 
     c.bench_function("encrypt contents of shakespeare", |b| {
-        b.iter(|| encryption::encrypt(&fc, &contents))
+        b.iter(|| encryption::encrypt(&fc, &contents, &[]))
     });
 }
 
 // encrypt test with 5mb file
 pub fn enc_benchmark_large(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
 
     c.bench_function("full file encryption (shakespeare)", |b| {
@@ -91,7 +91,8 @@ pub fn enc_benchmark_large(c: &mut Criterion) {
 // encrypt test with 850kb file
 // pub fn enc_many_files_benchmark(c: &mut Criterion) {
 //     {
-//         let mut config = get_config_write();
+//         let vault = default_vault().unwrap();
+//         let mut config = vault.config_write();
 //         config.retain = true;
 //     }
 
@@ -115,7 +116,7 @@ pub fn enc_benchmark_large(c: &mut Criterion) {
 
 // decrypt test with 850kb file
 pub fn dec_benchmark(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
 
     c.bench_function("decrypt dracula", |b| {
@@ -125,7 +126,7 @@ pub fn dec_benchmark(c: &mut Criterion) {
 
 // decrypt test with 5mb file
 pub fn dec_benchmark_large(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
 
     c.bench_function("decrypt Shakespeare", |b| {
@@ -135,7 +136,7 @@ pub fn dec_benchmark_large(c: &mut Criterion) {
 
 // test generating a hash
 pub fn test_compute_hash(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
 
     let contents: Vec<u8> = std::fs::read(DRACULA).unwrap();
@@ -147,7 +148,7 @@ pub fn test_compute_hash(c: &mut Criterion) {
 
 // test generation of a 26 digit uuid
 pub fn test_generate_uuid(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
 
     c.bench_function("generate 26 digit uuid", |b| {
@@ -171,7 +172,7 @@ pub fn test_generate_uuid(c: &mut Criterion) {
 }
 
 pub fn test_zip(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
 
     let contents = get_vec_file_bytes(DRACULA);
@@ -181,9 +182,9 @@ pub fn test_zip(c: &mut Criterion) {
 }
 
 pub fn test_zip_large(c: &mut Criterion) {
-    let mut logger = logger::new();
+    let mut logger = Logger::new();
     logger.terminal(false);
-    
+
     let contents = get_vec_file_bytes(SHAKESPEARE);
     c.bench_function("zip Shakespeare.txt", |b| {
         b.iter(|| crate::encryption::compress(contents.as_slice(), 3))
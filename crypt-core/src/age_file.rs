@@ -0,0 +1,218 @@
+use crate::{
+    error,
+    filecrypt::decrypt_payload,
+    header::Header,
+    prelude::*,
+    vault::{default_vault, Vault},
+};
+use ::age::{secrecy::SecretString, Decryptor, Encryptor, IdentityFile};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Write},
+    iter,
+    path::{Path, PathBuf},
+};
+
+/// Extension of files written in the age format.
+pub const AGE_EXTENSION: &str = "age";
+
+/// What `export_age` encrypts to.
+pub enum AgeTarget {
+    /// age X25519 recipient, `age1...`.
+    Recipient(String),
+
+    /// passphrase, using the age scrypt recipient.
+    Passphrase(String),
+}
+
+/// What `import_age` decrypts with.
+pub enum AgeIdentity {
+    /// age identity file holding one or more `AGE-SECRET-KEY-1...` lines.
+    File(PathBuf),
+
+    /// passphrase the file was encrypted with.
+    Passphrase(String),
+}
+
+/// `Vault::export_age` of the default vault.
+pub fn export_age<T: AsRef<Path>>(path: T, target: &AgeTarget) -> Result<PathBuf> {
+    default_vault()?.export_age(path, target)
+}
+
+/// `Vault::import_age` of the default vault.
+pub fn import_age<T: AsRef<Path>>(
+    path: T,
+    identity: &AgeIdentity,
+    output: &Option<String>,
+) -> Result<PathBuf> {
+    default_vault()?.import_age(path, identity, output)
+}
+
+impl Vault {
+    /// Re-encrypts a `.crypt` file to an age recipient or passphrase.
+    ///
+    /// The payload is decrypted with the key from the keeper and streamed straight into the age
+    /// writer, the plaintext never touches the disk. The result is written to
+    /// `<crypt folder>/exported/<original name>.age`.
+    ///
+    /// # Returns
+    ///
+    /// The path of the written `.age` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `IoError` of kind `AlreadyExists` if the `.age` file exists, an earlier export is
+    /// never overwritten.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::age_file::{export_age, AgeTarget};
+    /// let recipient = "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p".to_string();
+    /// export_age("crypt/report.crypt", &AgeTarget::Recipient(recipient))?;
+    /// ```
+    pub fn export_age<T: AsRef<Path>>(&self, path: T, target: &AgeTarget) -> Result<PathBuf> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let header = Header::read_from(&mut reader)?;
+        let fc = self.resolve_file_crypt(&header, || None)?;
+        let dictionary = self.header_dictionary(&header)?;
+
+        let encryptor = match target {
+            AgeTarget::Recipient(recipient) => {
+                let recipient: ::age::x25519::Recipient = recipient.parse().map_err(|_| {
+                    Error::EncryptionError(error::EncryptionError::InvalidRecipient(
+                        recipient.to_string(),
+                    ))
+                })?;
+                Encryptor::with_recipients(iter::once(&recipient as _))?
+            }
+            AgeTarget::Passphrase(passphrase) => {
+                Encryptor::with_user_passphrase(SecretString::from(passphrase.to_owned()))
+            }
+        };
+
+        let mut age_path = self.get_crypt_folder();
+        age_path.push("exported");
+        std::fs::create_dir_all(&age_path)?;
+        age_path.push(format!("{}{}.{}", fc.filename, fc.ext, AGE_EXTENSION));
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&age_path)?;
+        let mut writer = encryptor.wrap_output(BufWriter::new(file))?;
+        let res = decrypt_payload(&fc, &header, dictionary.as_ref(), reader, &mut writer).and_then(
+            |_| {
+                writer.finish()?.flush()?;
+                Ok(())
+            },
+        );
+
+        // a failed hash check means the export holds the wrong contents
+        if let Err(e) = res {
+            _ = std::fs::remove_file(&age_path);
+            return Err(e);
+        }
+        Ok(age_path)
+    }
+
+    /// Decrypts an age file and encrypts its contents into a keeper tracked `.crypt` file.
+    ///
+    /// The `.crypt` file is named after the age file without its `.age` extension, e.g.
+    /// `report.pdf.age` becomes the keeper entry `report.pdf`.
+    ///
+    /// # Returns
+    ///
+    /// The path of the written `.crypt` file.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::age_file::{import_age, AgeIdentity};
+    /// import_age("report.pdf.age", &AgeIdentity::File("key.txt".into()), &None)?;
+    /// ```
+    pub fn import_age<T: AsRef<Path>>(
+        &self,
+        path: T,
+        identity: &AgeIdentity,
+        output: &Option<String>,
+    ) -> Result<PathBuf> {
+        let path = path.as_ref();
+        let decryptor = Decryptor::new_buffered(BufReader::new(File::open(path)?))?;
+
+        let reader = match identity {
+            AgeIdentity::File(file) => {
+                let identities = IdentityFile::from_file(file.to_string_lossy().to_string())?
+                    .into_identities()?;
+                decryptor.decrypt(identities.iter().map(|i| i.as_ref() as _))?
+            }
+            AgeIdentity::Passphrase(passphrase) => {
+                let identity =
+                    ::age::scrypt::Identity::new(SecretString::from(passphrase.to_owned()));
+                decryptor.decrypt(iter::once(&identity as _))?
+            }
+        };
+
+        let original = match path.extension() {
+            Some(ext) if ext == AGE_EXTENSION => path.with_extension(""),
+            _ => path.to_path_buf(),
+        };
+        self.encrypt_reader(original, reader, output)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vault::TempVault;
+    use ::age::secrecy::ExposeSecret;
+    use std::fs;
+
+    /// Exports `plans.txt` from a fresh vault to `target` and imports it back with `identity`.
+    fn round_trip(name: &str, target: AgeTarget, identity: impl FnOnce(&Path) -> AgeIdentity) {
+        let vault = TempVault::unlocked(name);
+        let path = vault.dir().join("plans.txt");
+        let contents = b"age round trip".repeat(1000);
+        fs::write(&path, &contents).unwrap();
+        vault.encrypt_file(path.to_str().unwrap(), &None).unwrap();
+        let crypt_path = vault.root().join("plans.crypt");
+
+        let age_path = vault.export_age(&crypt_path, &target).unwrap();
+        assert_eq!(age_path, vault.root().join("exported/plans.txt.age"));
+        // an earlier export is never overwritten
+        let err = vault.export_age(&crypt_path, &target).unwrap_err();
+        assert!(matches!(err, Error::IoError(e) if e.kind() == std::io::ErrorKind::AlreadyExists));
+
+        fs::remove_file(&crypt_path).unwrap();
+        let identity = identity(vault.dir());
+        let imported = vault.import_age(&age_path, &identity, &None).unwrap();
+        assert_eq!(imported, crypt_path);
+
+        let mut plain = Vec::new();
+        let fc = vault
+            .decrypt_to_writer(File::open(&imported).unwrap(), &mut plain, || None)
+            .unwrap();
+        assert_eq!(format!("{}{}", fc.filename, fc.ext), "plans.txt");
+        assert_eq!(plain, contents);
+    }
+
+    #[test]
+    fn test_age_recipient_round_trip() {
+        let key = ::age::x25519::Identity::generate();
+        let recipient = key.to_public().to_string();
+        round_trip("age-recipient", AgeTarget::Recipient(recipient), |dir| {
+            let file = dir.join("key.txt");
+            fs::write(&file, key.to_string().expose_secret()).unwrap();
+            AgeIdentity::File(file)
+        });
+    }
+
+    #[test]
+    fn test_age_passphrase_round_trip() {
+        round_trip(
+            "age-passphrase",
+            AgeTarget::Passphrase("correct horse".to_string()),
+            |_| AgeIdentity::Passphrase("correct horse".to_string()),
+        );
+    }
+}
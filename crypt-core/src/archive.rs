@@ -0,0 +1,500 @@
+use crate::{common::get_full_file_path, error::FcError, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Largest index `read_index` and `Unpacker` accept.
+const MAX_INDEX_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Kind of an archive member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+}
+
+/// A member of an archive, as listed in its index.
+///
+/// An archive is the payload of a `.crypt` file flagged `FLAG_ARCHIVE`: the length of the index
+/// (u64 little endian), the index as a JSON list of `ArchiveEntry`, then the contents of every
+/// file member in index order.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ArchiveEntry {
+    /// path relative to the archived directory, `/` separated.
+    pub path: String,
+
+    pub kind: EntryKind,
+
+    /// unix permission bits. Elsewhere only the owner write bit is honored, as the read-only flag.
+    pub mode: u32,
+
+    /// size of the contents, 0 for directories.
+    pub size: u64,
+}
+
+impl ArchiveEntry {
+    /// Returns `true` if `member` names this entry or a directory containing it.
+    pub fn is_in(&self, member: &str) -> bool {
+        let member = normalize_member(member);
+        member.is_empty()
+            || self.path == member
+            || self
+                .path
+                .strip_prefix(member)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+/// Trims `./` prefixes and trailing slashes from a member path given by the user.
+fn normalize_member(member: &str) -> &str {
+    let mut member = member.trim_end_matches('/');
+    while let Some(rest) = member.strip_prefix("./") {
+        member = rest;
+    }
+    member
+}
+
+/// `Read` adapter that packs a directory into an archive, opening each file only once the
+/// previous one has been read.
+pub(crate) struct ArchiveReader {
+    index: Cursor<Vec<u8>>,
+    files: std::vec::IntoIter<(PathBuf, u64)>,
+    current: Option<io::Take<File>>,
+}
+
+/// Packs the directory at `path` into an archive: every file, directory and permission below it,
+/// hidden ones included. Unlike encrypting a directory, `Config::ignore_items` is not applied, the
+/// archive is the whole directory. Symbolic links are skipped.
+///
+/// # Errors
+///
+/// Returns `FcError::ArchiveMemberName` if a name below `path` is not valid UTF-8, as it could
+/// not be extracted under the same name.
+pub(crate) fn pack_directory<T: AsRef<Path>>(path: T) -> Result<ArchiveReader> {
+    let root = get_full_file_path(path);
+    let mut entries = Vec::new();
+    let mut files = Vec::new();
+
+    for entry in WalkDir::new(&root).min_depth(1).sort_by_file_name() {
+        let path = entry?.into_path();
+        let Ok(relative) = path.strip_prefix(&root) else {
+            continue;
+        };
+        let member = relative
+            .components()
+            .map(|c| c.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::FcError(FcError::ArchiveMemberName(path.display().to_string())))?
+            .join("/");
+
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            entries.push(ArchiveEntry {
+                path: member,
+                kind: EntryKind::Dir,
+                mode: file_mode(&metadata),
+                size: 0,
+            });
+        } else if metadata.is_file() {
+            entries.push(ArchiveEntry {
+                path: member,
+                kind: EntryKind::File,
+                mode: file_mode(&metadata),
+                size: metadata.len(),
+            });
+            files.push((path, metadata.len()));
+        }
+    }
+
+    let json = serde_json::to_vec(&entries).map_err(io::Error::other)?;
+    let mut index = (json.len() as u64).to_le_bytes().to_vec();
+    index.extend_from_slice(&json);
+
+    Ok(ArchiveReader {
+        index: Cursor::new(index),
+        files: files.into_iter(),
+        current: None,
+    })
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.index.read(buf)?;
+        if n > 0 || buf.is_empty() {
+            return Ok(n);
+        }
+
+        loop {
+            if let Some(file) = &mut self.current {
+                let n = file.read(buf)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+                // the index promised `size` bytes
+                if file.limit() > 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "file shrank while it was being archived",
+                    ));
+                }
+                self.current = None;
+            }
+            match self.files.next() {
+                Some((path, size)) => self.current = Some(File::open(path)?.take(size)),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// Reads the index at the start of an archive.
+pub(crate) fn read_index<R: Read>(reader: &mut R) -> io::Result<Vec<ArchiveEntry>> {
+    let mut len = [0u8; 8];
+    reader.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+    if len > MAX_INDEX_SIZE {
+        return Err(invalid_archive("index too large"));
+    }
+
+    let mut json = vec![0u8; len as usize];
+    reader.read_exact(&mut json)?;
+    parse_index(&json)
+}
+
+/// Parses an index, rejecting member paths that would escape the extraction folder.
+fn parse_index(json: &[u8]) -> io::Result<Vec<ArchiveEntry>> {
+    let entries: Vec<ArchiveEntry> =
+        serde_json::from_slice(json).map_err(|_| invalid_archive("malformed index"))?;
+
+    let unsafe_path = |p: &str| {
+        p.split('/')
+            .any(|c| c.is_empty() || c == "." || c == ".." || c.contains(['\\', ':']))
+    };
+    if entries.iter().any(|e| unsafe_path(&e.path)) {
+        return Err(invalid_archive("member path escapes the archive"));
+    }
+    Ok(entries)
+}
+
+fn invalid_archive(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Where `Unpacker` is in the archive.
+enum UnpackState {
+    /// reading the index length.
+    Length,
+
+    /// reading the index, of the given length.
+    Index(usize),
+
+    /// writing the contents of the file members.
+    Contents,
+}
+
+/// `Write` adapter that extracts an archive into a directory as it is written.
+///
+/// Only the members selected by `members` are written (see `ArchiveEntry::is_in`), all of them
+/// if it is empty. Permissions of directories are applied by `finish`, once their contents are
+/// in place.
+pub(crate) struct Unpacker {
+    dest: PathBuf,
+    members: Vec<String>,
+    state: UnpackState,
+    buf: Vec<u8>,
+
+    /// file members still to come.
+    files: std::vec::IntoIter<ArchiveEntry>,
+
+    /// member being written: its file if selected, its mode and the bytes left.
+    current: Option<(Option<File>, PathBuf, u32, u64)>,
+
+    /// extracted directories and their modes.
+    dirs: Vec<(PathBuf, u32)>,
+
+    /// number of extracted members.
+    extracted: usize,
+}
+
+impl Unpacker {
+    pub(crate) fn new<T: AsRef<Path>>(dest: T, members: &[String]) -> Self {
+        Self {
+            dest: dest.as_ref().to_path_buf(),
+            members: members.to_vec(),
+            state: UnpackState::Length,
+            buf: Vec::new(),
+            files: Vec::new().into_iter(),
+            current: None,
+            dirs: Vec::new(),
+            extracted: 0,
+        }
+    }
+
+    fn is_selected(&self, entry: &ArchiveEntry) -> bool {
+        self.members.is_empty() || self.members.iter().any(|m| entry.is_in(m))
+    }
+
+    fn member_path(&self, entry: &ArchiveEntry) -> PathBuf {
+        entry
+            .path
+            .split('/')
+            .fold(self.dest.clone(), |p, c| p.join(c))
+    }
+
+    /// Creates the selected directories and queues the file members.
+    fn start(&mut self, entries: Vec<ArchiveEntry>) -> io::Result<()> {
+        let mut files = Vec::new();
+        for entry in entries {
+            if !self.is_selected(&entry) {
+                if entry.kind == EntryKind::File {
+                    files.push(entry);
+                }
+                continue;
+            }
+            match entry.kind {
+                EntryKind::Dir => {
+                    let path = self.member_path(&entry);
+                    fs::create_dir_all(&path)?;
+                    self.dirs.push((path, entry.mode));
+                    self.extracted += 1;
+                }
+                EntryKind::File => files.push(entry),
+            }
+        }
+        self.files = files.into_iter();
+        self.state = UnpackState::Contents;
+        Ok(())
+    }
+
+    /// Moves on to the next file member, creating it if it is selected. Empty members are
+    /// completed right away.
+    fn next_file(&mut self) -> io::Result<bool> {
+        let Some(entry) = self.files.next() else {
+            return Ok(false);
+        };
+        let path = self.member_path(&entry);
+        let file = match self.is_selected(&entry) {
+            true => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                self.extracted += 1;
+                Some(File::create(&path)?)
+            }
+            false => None,
+        };
+        self.current = Some((file, path, entry.mode, entry.size));
+        self.complete_file()?;
+        Ok(true)
+    }
+
+    /// Closes the current member once all of its contents have been written.
+    fn complete_file(&mut self) -> io::Result<()> {
+        if !matches!(self.current, Some((_, _, _, 0))) {
+            return Ok(());
+        }
+        if let Some((Some(file), path, mode, _)) = self.current.take() {
+            drop(file);
+            set_mode(&path, mode)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the whole archive was written and applies the permissions of the extracted
+    /// directories.
+    ///
+    /// # Returns
+    ///
+    /// The number of extracted members.
+    pub(crate) fn finish(mut self) -> Result<usize> {
+        // empty members at the very end have no contents to trigger them
+        while self.current.is_none() && self.next_file()? {}
+        if !matches!(self.state, UnpackState::Contents) || self.current.is_some() {
+            return Err(Error::IoError(invalid_archive("archive is truncated")));
+        }
+
+        // deepest first, so read-only directories are locked after their children
+        for (path, mode) in self.dirs.iter().rev() {
+            set_mode(path, *mode)?;
+        }
+        Ok(self.extracted)
+    }
+}
+
+impl Write for Unpacker {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut rest = data;
+        while !rest.is_empty() {
+            match self.state {
+                UnpackState::Length => {
+                    let n = (8 - self.buf.len()).min(rest.len());
+                    self.buf.extend_from_slice(&rest[..n]);
+                    rest = &rest[n..];
+                    if self.buf.len() == 8 {
+                        let len = u64::from_le_bytes(self.buf[..].try_into().unwrap());
+                        if len > MAX_INDEX_SIZE {
+                            return Err(invalid_archive("index too large"));
+                        }
+                        self.buf.clear();
+                        self.state = UnpackState::Index(len as usize);
+                    }
+                }
+                UnpackState::Index(len) => {
+                    let n = (len - self.buf.len()).min(rest.len());
+                    self.buf.extend_from_slice(&rest[..n]);
+                    rest = &rest[n..];
+                    if self.buf.len() == len {
+                        let entries = parse_index(&std::mem::take(&mut self.buf))?;
+                        self.start(entries)?;
+                    }
+                }
+                UnpackState::Contents => {
+                    if self.current.is_none() && !self.next_file()? {
+                        return Err(invalid_archive("data after the last member"));
+                    }
+                    if let Some((file, _, _, left)) = &mut self.current {
+                        let n = (*left).min(rest.len() as u64) as usize;
+                        if let Some(file) = file {
+                            file.write_all(&rest[..n])?;
+                        }
+                        *left -= n as u64;
+                        rest = &rest[n..];
+                    }
+                    self.complete_file()?;
+                }
+            }
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    match (metadata.is_dir(), metadata.permissions().readonly()) {
+        (true, _) => 0o755,
+        (false, true) => 0o444,
+        (false, false) => 0o644,
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_archive_round_trip() {
+        let src = std::env::temp_dir().join(format!("crypt-archive-{}", std::process::id()));
+        let dest = src.with_extension("out");
+        _ = fs::remove_dir_all(&src);
+        _ = fs::remove_dir_all(&dest);
+        fs::create_dir_all(src.join("docs/empty")).unwrap();
+        fs::write(src.join("a.txt"), b"alpha").unwrap();
+        fs::write(src.join("docs/b.md"), vec![7u8; 100_000]).unwrap();
+        fs::write(src.join("docs/zero"), b"").unwrap();
+        // hidden and ignored names are archived too
+        fs::create_dir_all(src.join(".git")).unwrap();
+        fs::write(src.join(".git/HEAD"), b"ref").unwrap();
+        fs::write(src.join(".env"), b"KEY=1").unwrap();
+        fs::write(src.join("target.txt"), b"t").unwrap();
+
+        let mut packed = Vec::new();
+        pack_directory(&src)
+            .unwrap()
+            .read_to_end(&mut packed)
+            .unwrap();
+
+        let index = read_index(&mut packed.as_slice()).unwrap();
+        assert!(index
+            .iter()
+            .any(|e| e.path == "docs/empty" && e.kind == EntryKind::Dir));
+        assert!(index
+            .iter()
+            .any(|e| e.path == "docs/b.md" && e.size == 100_000));
+        for path in [".git/HEAD", ".env", "target.txt"] {
+            assert!(index.iter().any(|e| e.path == path), "{path}");
+        }
+
+        // written in odd sized pieces, like a decrypting stream would
+        let mut unpacker = Unpacker::new(&dest, &[]);
+        for chunk in packed.chunks(4093) {
+            unpacker.write_all(chunk).unwrap();
+        }
+        assert_eq!(unpacker.finish().unwrap(), index.len());
+        assert_eq!(fs::read(dest.join("a.txt")).unwrap(), b"alpha");
+        assert_eq!(
+            fs::read(dest.join("docs/b.md")).unwrap(),
+            vec![7u8; 100_000]
+        );
+        assert!(dest.join("docs/zero").is_file());
+        assert!(dest.join("docs/empty").is_dir());
+
+        // single members
+        fs::remove_dir_all(&dest).unwrap();
+        let mut unpacker = Unpacker::new(&dest, &["./docs/".to_string()]);
+        unpacker.write_all(&packed).unwrap();
+        unpacker.finish().unwrap();
+        assert!(dest.join("docs/b.md").is_file());
+        assert!(!dest.join("a.txt").exists());
+
+        // truncated archives are rejected
+        let mut unpacker = Unpacker::new(&dest, &[]);
+        unpacker.write_all(&packed[..packed.len() - 10]).unwrap();
+        assert!(unpacker.finish().is_err());
+
+        _ = fs::remove_dir_all(&src);
+        _ = fs::remove_dir_all(&dest);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_pack_rejects_non_utf8_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let src = std::env::temp_dir().join(format!("crypt-archive-utf8-{}", std::process::id()));
+        _ = fs::remove_dir_all(&src);
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join(OsStr::from_bytes(b"bad\xff")), b"x").unwrap();
+
+        assert!(matches!(
+            pack_directory(&src),
+            Err(Error::FcError(FcError::ArchiveMemberName(_)))
+        ));
+        _ = fs::remove_dir_all(&src);
+    }
+
+    #[test]
+    fn test_index_rejects_escaping_paths() {
+        for path in ["../evil", "a/../../evil", "/etc/passwd", "a//b"] {
+            let json = format!(r#"[{{"path":"{path}","kind":"file","mode":420,"size":0}}]"#);
+            assert!(parse_index(json.as_bytes()).is_err(), "{path}");
+        }
+    }
+}
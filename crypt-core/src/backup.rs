@@ -0,0 +1,194 @@
+use crate::{
+    common::get_full_file_path,
+    encryption::compute_file_hash,
+    prelude::*,
+    vault::{default_vault, Vault},
+};
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// `Vault::get_backup_folder` of the default vault.
+pub fn get_backup_folder() -> Result<PathBuf> {
+    Ok(default_vault()?.get_backup_folder())
+}
+
+/// Outcome of `verify_backup` for a single `.crypt` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupStatus {
+    /// the backup matches the `.crypt` file.
+    Ok,
+
+    /// the `.crypt` file has no backup.
+    Missing,
+
+    /// the backup differs from the `.crypt` file.
+    Mismatch,
+
+    /// the backup has no `.crypt` file left in the crypt folder.
+    Orphaned,
+}
+
+impl BackupStatus {
+    /// Returns `true` if the backup set does not match the crypt folder for this file.
+    pub fn is_failure(&self) -> bool {
+        *self != BackupStatus::Ok
+    }
+}
+
+impl fmt::Display for BackupStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupStatus::Ok => write!(f, "OK"),
+            BackupStatus::Missing => write!(f, "missing backup"),
+            BackupStatus::Mismatch => write!(f, "backup differs"),
+            BackupStatus::Orphaned => write!(f, "orphaned backup"),
+        }
+    }
+}
+
+/// `Vault::verify_backup` of the default vault.
+pub fn verify_backup() -> Result<Vec<(PathBuf, BackupStatus)>> {
+    default_vault()?.verify_backup()
+}
+
+impl Vault {
+    /// Gets the backup folder of the vault, `Config::backup_path`.
+    pub fn get_backup_folder(&self) -> PathBuf {
+        get_full_file_path(self.config().backup_path)
+    }
+
+    /// Gets the path the backup of the `.crypt` file at `crypt_path` is kept at, or `None` if the
+    /// file is not in the crypt folder.
+    fn backup_path_of(&self, crypt_path: &Path) -> Option<PathBuf> {
+        let relative = crypt_path.strip_prefix(self.get_crypt_folder()).ok()?;
+        Some(self.get_backup_folder().join(relative))
+    }
+
+    /// Copies the `.crypt` file at `crypt_path` into the backup folder, at the same place relative
+    /// to the folder, if `Config::backup` is set. Replaces the previous copy, only the latest version
+    /// is kept.
+    ///
+    /// The copy is written next to its destination and renamed over it, so a failed copy leaves the
+    /// previous backup intact.
+    pub(crate) fn backup_crypt_file(&self, crypt_path: &Path) -> Result<()> {
+        if !self.config().backup {
+            return Ok(());
+        }
+        let Some(dest) = self.backup_path_of(crypt_path) else {
+            return Ok(());
+        };
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let partial = dest.with_extension("partial");
+        if let Err(e) = std::fs::copy(crypt_path, &partial) {
+            _ = std::fs::remove_file(&partial);
+            return Err(Error::IoError(e));
+        }
+        std::fs::rename(&partial, &dest)?;
+        Ok(())
+    }
+
+    /// Removes the backup of the `.crypt` file at `crypt_path`, once the file itself was removed
+    /// or replaced under another name, if `Config::backup` is set.
+    pub(crate) fn remove_backup(&self, crypt_path: &Path) {
+        if !self.config().backup {
+            return;
+        }
+        if let Some(dest) = self.backup_path_of(crypt_path) {
+            _ = std::fs::remove_file(dest);
+        }
+    }
+
+    /// Compares the backup folder with the crypt folder by hash.
+    ///
+    /// # Returns
+    ///
+    /// The status of every `.crypt` file in the crypt folder, then every orphaned backup, with the
+    /// path of the `.crypt` file or backup.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::backup::verify_backup;
+    /// let failed = verify_backup()?.iter().filter(|(_, s)| s.is_failure()).count();
+    /// ```
+    pub fn verify_backup(&self) -> Result<Vec<(PathBuf, BackupStatus)>> {
+        let backup_folder = self.get_backup_folder();
+        let is_crypt = |p: &Path| p.extension().is_some_and(|e| e == "crypt");
+
+        let mut statuses = Vec::new();
+        let mut backed_up = HashSet::new();
+        let files = self.walk_crypt_folder()?.0.into_iter();
+        for file in files.filter(|p| is_crypt(p) && !p.starts_with(&backup_folder)) {
+            let Some(dest) = self.backup_path_of(&file) else {
+                continue;
+            };
+            let status = match dest.is_file() {
+                false => BackupStatus::Missing,
+                true if compute_file_hash(&file)? == compute_file_hash(&dest)? => BackupStatus::Ok,
+                true => BackupStatus::Mismatch,
+            };
+            backed_up.insert(dest);
+            statuses.push((file, status));
+        }
+
+        if backup_folder.is_dir() {
+            for entry in WalkDir::new(&backup_folder) {
+                let path = entry?.into_path();
+                if is_crypt(&path) && !backed_up.contains(&path) {
+                    statuses.push((path, BackupStatus::Orphaned));
+                }
+            }
+        }
+        Ok(statuses)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vault::TempVault;
+    use std::fs;
+
+    #[test]
+    fn test_verify_backup() {
+        let vault = TempVault::new("backup");
+        let dir = vault.dir();
+        {
+            let mut config = vault.config_write();
+            config.backup = true;
+            config.backup_path = dir.join("backup").display().to_string();
+        }
+
+        let root = vault.root();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for name in ["a.crypt", "b.crypt", "sub/c.crypt"] {
+            fs::write(root.join(name), name).unwrap();
+        }
+        for name in ["a.crypt", "b.crypt", "sub/c.crypt"] {
+            vault.backup_crypt_file(&root.join(name)).unwrap();
+        }
+        fs::remove_file(dir.join("backup/b.crypt")).unwrap();
+        fs::write(root.join("sub/c.crypt"), "changed").unwrap();
+        fs::write(dir.join("backup/gone.crypt"), "gone").unwrap();
+        let statuses = vault.verify_backup().unwrap();
+
+        let status_of = |name: &str| {
+            statuses
+                .iter()
+                .find(|(p, _)| p.ends_with(name))
+                .map(|(_, s)| *s)
+        };
+        assert_eq!(statuses.len(), 4);
+        assert_eq!(status_of("a.crypt"), Some(BackupStatus::Ok));
+        assert_eq!(status_of("b.crypt"), Some(BackupStatus::Missing));
+        assert_eq!(status_of("sub/c.crypt"), Some(BackupStatus::Mismatch));
+        assert_eq!(status_of("gone.crypt"), Some(BackupStatus::Orphaned));
+    }
+}
@@ -0,0 +1,337 @@
+use crate::{encryption::KEY_SIZE, error, prelude::*};
+use aes_gcm_siv::Aes256GcmSiv;
+use chacha20poly1305::{
+    aead::{
+        generic_array::GenericArray,
+        stream::{DecryptorBE32, EncryptorBE32},
+        Aead, KeyInit, OsRng, Payload,
+    },
+    ChaCha20Poly1305, XChaCha20Poly1305,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Bytes of the nonce taken by the STREAM counter and last-chunk flag.
+const STREAM_OVERHEAD: usize = 5;
+
+/// AEAD cipher used to encrypt a file.
+///
+/// The discriminant is the id stored in the `.crypt` header and in the keeper, so existing
+/// values must never change.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum Cipher {
+    /// ChaCha20-Poly1305 with a 96-bit nonce. Used by every file written before ciphers were
+    /// selectable.
+    #[serde(rename = "chacha20poly1305")]
+    ChaCha20Poly1305 = 1,
+
+    /// XChaCha20-Poly1305 with a 192-bit nonce, safe to generate at random for any number of
+    /// files.
+    #[default]
+    #[serde(rename = "xchacha20poly1305")]
+    XChaCha20Poly1305 = 2,
+
+    /// AES-256-GCM-SIV, nonce misuse resistant.
+    #[serde(rename = "aes256gcmsiv")]
+    Aes256GcmSiv = 3,
+}
+
+/// Runs `$body` with `$aead` bound to a `$cipher` instance keyed with `$key`.
+macro_rules! with_aead {
+    ($cipher:expr, $key:expr, |$aead:ident| $body:expr) => {
+        match $cipher {
+            Cipher::ChaCha20Poly1305 => {
+                let $aead = ChaCha20Poly1305::new(GenericArray::from_slice($key));
+                $body
+            }
+            Cipher::XChaCha20Poly1305 => {
+                let $aead = XChaCha20Poly1305::new(GenericArray::from_slice($key));
+                $body
+            }
+            Cipher::Aes256GcmSiv => {
+                let $aead = Aes256GcmSiv::new(GenericArray::from_slice($key));
+                $body
+            }
+        }
+    };
+}
+
+impl Cipher {
+    /// All supported ciphers.
+    pub const ALL: [Cipher; 3] = [
+        Cipher::ChaCha20Poly1305,
+        Cipher::XChaCha20Poly1305,
+        Cipher::Aes256GcmSiv,
+    ];
+
+    /// Nonce length in bytes.
+    pub fn nonce_size(self) -> usize {
+        match self {
+            Cipher::ChaCha20Poly1305 | Cipher::Aes256GcmSiv => 12,
+            Cipher::XChaCha20Poly1305 => 24,
+        }
+    }
+
+    /// Bytes of the nonce used as the STREAM nonce prefix.
+    pub fn stream_nonce_size(self) -> usize {
+        self.nonce_size() - STREAM_OVERHEAD
+    }
+
+    /// Generates a random nonce of `nonce_size` bytes.
+    pub fn generate_nonce(self) -> Vec<u8> {
+        let mut nonce = vec![0u8; self.nonce_size()];
+        OsRng.fill_bytes(&mut nonce);
+        nonce
+    }
+
+    /// Encrypts `payload` as a single AEAD message.
+    pub fn encrypt(self, key: &[u8; KEY_SIZE], nonce: &[u8], payload: Payload) -> Result<Vec<u8>> {
+        self.check_nonce(nonce, self.nonce_size())?;
+        with_aead!(self, key, |aead| aead
+            .encrypt(GenericArray::from_slice(nonce), payload))
+        .map_err(|_| Error::EncryptionError(error::EncryptionError::AeadError))
+    }
+
+    /// Decrypts a message produced by `encrypt`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EncryptionError::AeadError` if the message fails authentication.
+    pub fn decrypt(self, key: &[u8; KEY_SIZE], nonce: &[u8], payload: Payload) -> Result<Vec<u8>> {
+        self.check_nonce(nonce, self.nonce_size())?;
+        with_aead!(self, key, |aead| aead
+            .decrypt(GenericArray::from_slice(nonce), payload))
+        .map_err(|_| Error::EncryptionError(error::EncryptionError::AeadError))
+    }
+
+    /// Creates a STREAM encryptor using the first `stream_nonce_size` bytes of `nonce`.
+    pub(crate) fn stream_encryptor(
+        self,
+        key: &[u8; KEY_SIZE],
+        nonce: &[u8],
+    ) -> Result<StreamEncryptor> {
+        let prefix = self.stream_prefix(nonce)?;
+        Ok(with_aead!(self, key, |aead| StreamEncryptor::from(
+            EncryptorBE32::from_aead(aead, GenericArray::from_slice(prefix))
+        )))
+    }
+
+    /// Creates a STREAM decryptor using the first `stream_nonce_size` bytes of `nonce`.
+    pub(crate) fn stream_decryptor(
+        self,
+        key: &[u8; KEY_SIZE],
+        nonce: &[u8],
+    ) -> Result<StreamDecryptor> {
+        let prefix = self.stream_prefix(nonce)?;
+        Ok(with_aead!(self, key, |aead| StreamDecryptor::from(
+            DecryptorBE32::from_aead(aead, GenericArray::from_slice(prefix))
+        )))
+    }
+
+    fn stream_prefix(self, nonce: &[u8]) -> Result<&[u8]> {
+        self.check_nonce(nonce, self.stream_nonce_size())?;
+        Ok(&nonce[..self.stream_nonce_size()])
+    }
+
+    /// Makes sure `nonce` holds at least `len` bytes and is no longer than `nonce_size`.
+    fn check_nonce(self, nonce: &[u8], len: usize) -> Result<()> {
+        if nonce.len() < len || nonce.len() > self.nonce_size() {
+            return Err(Error::EncryptionError(error::EncryptionError::InvalidNonce));
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<u8> for Cipher {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        Cipher::ALL
+            .into_iter()
+            .find(|c| *c as u8 == value)
+            .ok_or(Error::FcError(error::FcError::UnknownCipher(value)))
+    }
+}
+
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cipher::ChaCha20Poly1305 => write!(f, "chacha20poly1305"),
+            Cipher::XChaCha20Poly1305 => write!(f, "xchacha20poly1305"),
+            Cipher::Aes256GcmSiv => write!(f, "aes256gcmsiv"),
+        }
+    }
+}
+
+impl FromStr for Cipher {
+    type Err = Error;
+
+    /// Parses the names printed by `Display`, ignoring case, `-` and `_`.
+    fn from_str(s: &str) -> Result<Self> {
+        let name: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_lowercase();
+        Cipher::ALL
+            .into_iter()
+            .find(|c| c.to_string() == name)
+            .ok_or(Error::EncryptionError(
+                error::EncryptionError::UnknownCipherName(s.to_string()),
+            ))
+    }
+}
+
+/// Declares an enum over the STREAM type `$stream` for every `Cipher`, dispatching `$method`s to
+/// the inner value.
+macro_rules! stream_enum {
+    ($name:ident, $stream:ident, $next:ident, $last:ident) => {
+        pub(crate) enum $name {
+            ChaCha20Poly1305($stream<ChaCha20Poly1305>),
+            XChaCha20Poly1305($stream<XChaCha20Poly1305>),
+            // the AES key schedule is large, keep it off the stack
+            Aes256GcmSiv(Box<$stream<Aes256GcmSiv>>),
+        }
+
+        impl $name {
+            pub(crate) fn $next<'m, 'a>(
+                &mut self,
+                payload: impl Into<Payload<'m, 'a>>,
+            ) -> chacha20poly1305::aead::Result<Vec<u8>> {
+                match self {
+                    Self::ChaCha20Poly1305(s) => s.$next(payload),
+                    Self::XChaCha20Poly1305(s) => s.$next(payload),
+                    Self::Aes256GcmSiv(s) => s.$next(payload),
+                }
+            }
+
+            pub(crate) fn $last<'m, 'a>(
+                self,
+                payload: impl Into<Payload<'m, 'a>>,
+            ) -> chacha20poly1305::aead::Result<Vec<u8>> {
+                match self {
+                    Self::ChaCha20Poly1305(s) => s.$last(payload),
+                    Self::XChaCha20Poly1305(s) => s.$last(payload),
+                    Self::Aes256GcmSiv(s) => (*s).$last(payload),
+                }
+            }
+        }
+
+        impl From<$stream<ChaCha20Poly1305>> for $name {
+            fn from(s: $stream<ChaCha20Poly1305>) -> Self {
+                Self::ChaCha20Poly1305(s)
+            }
+        }
+
+        impl From<$stream<XChaCha20Poly1305>> for $name {
+            fn from(s: $stream<XChaCha20Poly1305>) -> Self {
+                Self::XChaCha20Poly1305(s)
+            }
+        }
+
+        impl From<$stream<Aes256GcmSiv>> for $name {
+            fn from(s: $stream<Aes256GcmSiv>) -> Self {
+                Self::Aes256GcmSiv(Box::new(s))
+            }
+        }
+    };
+}
+
+stream_enum!(StreamEncryptor, EncryptorBE32, encrypt_next, encrypt_last);
+stream_enum!(StreamDecryptor, DecryptorBE32, decrypt_next, decrypt_last);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: [u8; KEY_SIZE] = [7u8; KEY_SIZE];
+
+    fn is_invalid_nonce<T>(res: Result<T>) -> bool {
+        matches!(
+            res,
+            Err(Error::EncryptionError(error::EncryptionError::InvalidNonce))
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for cipher in Cipher::ALL {
+            let nonce = cipher.generate_nonce();
+            assert_eq!(nonce.len(), cipher.nonce_size());
+
+            let payload = Payload {
+                msg: b"hello there",
+                aad: b"aad",
+            };
+            let sealed = cipher.encrypt(&KEY, &nonce, payload).unwrap();
+            let payload = Payload {
+                msg: &sealed,
+                aad: b"aad",
+            };
+            assert_eq!(
+                cipher.decrypt(&KEY, &nonce, payload).unwrap(),
+                b"hello there"
+            );
+
+            let payload = Payload {
+                msg: &sealed,
+                aad: b"other",
+            };
+            assert!(matches!(
+                cipher.decrypt(&KEY, &nonce, payload),
+                Err(Error::EncryptionError(error::EncryptionError::AeadError))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        for cipher in Cipher::ALL {
+            let nonce = cipher.generate_nonce();
+            let mut encryptor = cipher.stream_encryptor(&KEY, &nonce).unwrap();
+            let first = encryptor.encrypt_next(b"hello".as_slice()).unwrap();
+            let last = encryptor.encrypt_last(b"there".as_slice()).unwrap();
+
+            let mut decryptor = cipher.stream_decryptor(&KEY, &nonce).unwrap();
+            assert_eq!(decryptor.decrypt_next(first.as_slice()).unwrap(), b"hello");
+            assert_eq!(decryptor.decrypt_last(last.as_slice()).unwrap(), b"there");
+
+            // the last chunk is flagged, so it does not open as an inner one
+            let mut decryptor = cipher.stream_decryptor(&KEY, &nonce).unwrap();
+            decryptor.decrypt_next(first.as_slice()).unwrap();
+            assert!(decryptor.decrypt_next(last.as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_check_nonce() {
+        for cipher in Cipher::ALL {
+            let short = vec![0u8; cipher.nonce_size() - 1];
+            let long = vec![0u8; cipher.nonce_size() + 1];
+            for nonce in [&short, &long] {
+                assert!(is_invalid_nonce(cipher.encrypt(
+                    &KEY,
+                    nonce,
+                    b"".as_slice().into()
+                )));
+                assert!(is_invalid_nonce(cipher.decrypt(
+                    &KEY,
+                    nonce,
+                    b"".as_slice().into()
+                )));
+            }
+
+            // STREAM only takes the prefix, so a full nonce or the bare prefix both work
+            let prefix = vec![0u8; cipher.stream_nonce_size()];
+            assert!(cipher.stream_encryptor(&KEY, &prefix).is_ok());
+            assert!(cipher.stream_decryptor(&KEY, &short).is_ok());
+            let too_short = &prefix[..prefix.len() - 1];
+            assert!(is_invalid_nonce(cipher.stream_encryptor(&KEY, too_short)));
+            assert!(is_invalid_nonce(cipher.stream_decryptor(&KEY, too_short)));
+            assert!(is_invalid_nonce(cipher.stream_encryptor(&KEY, &long)));
+            assert!(is_invalid_nonce(cipher.stream_decryptor(&KEY, &long)));
+        }
+    }
+}
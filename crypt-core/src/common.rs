@@ -4,12 +4,20 @@ use std::{
     io::{self, BufReader, Write},
     path::{Path, PathBuf},
     process::Command,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
     time::SystemTime,
 };
 use walkdir::WalkDir;
 
-use crate::{config, error, prelude::*};
+use crate::{
+    config::Config,
+    error,
+    prelude::*,
+    vault::{default_vault, Vault},
+};
 use ansi_term::Color;
+use serde::Serialize;
 use serde_json::Value;
 
 /// given a path, dissect and return a struct containing the full path, is_dir, parent path, and name.
@@ -44,7 +52,8 @@ impl PathInfo {
 /// File(FileInfo),
 /// Directory(DirInfo),
 ///```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum FsNode {
     File(FileInfo),
     Directory(DirInfo),
@@ -111,7 +120,7 @@ impl FsNode {
 ///     path: String, //Path or ID of the file
 /// }
 ///```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FileInfo {
     pub name: String,
     pub path: String,
@@ -133,7 +142,7 @@ impl FileInfo {
 ///     contents: Vec<FsNode>, //Contents within the directory
 /// }
 /// ```
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct DirInfo {
     /// name of the directory
     pub name: String,
@@ -407,6 +416,38 @@ pub fn write_contents_to_file<T: AsRef<Path>>(file: T, contents: Vec<u8>) -> Res
     Ok(())
 }
 
+/// Overwrites the contents of the file at `path` with zeros, flushes them to disk and deletes
+/// the file.
+///
+/// The overwrite only reaches the old blocks on filesystems that update files in place.
+/// Copy-on-write filesystems, snapshots and SSD wear leveling may keep the old contents around.
+///
+/// # Errors
+///
+/// Returns `FcError::FileDeletionError` if the file cannot be overwritten or deleted.
+pub fn shred_file<T: AsRef<Path>>(path: T) -> Result<()> {
+    let path = path.as_ref();
+    let shred = || -> io::Result<()> {
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        let mut left = file.metadata()?.len();
+        let zeros = [0u8; 64 * 1024];
+        while left > 0 {
+            let n = left.min(zeros.len() as u64) as usize;
+            file.write_all(&zeros[..n])?;
+            left -= n as u64;
+        }
+        file.sync_all()?;
+        drop(file);
+        std::fs::remove_file(path)
+    };
+    shred().map_err(|e| {
+        Error::FcError(error::FcError::FileDeletionError(
+            e,
+            path.display().to_string(),
+        ))
+    })
+}
+
 /// Performs a system command to get user home path.
 /// if system is a windows machine, performs a powershell call. Otherwise, we assume it is linux
 /// and
@@ -435,6 +476,11 @@ pub fn get_config_folder() -> PathBuf {
     path
 }
 
+/// `Vault::get_crypt_folder` of the default vault.
+pub fn get_crypt_folder() -> Result<PathBuf> {
+    Ok(default_vault()?.get_crypt_folder())
+}
+
 /// performs a process command to query user profile.
 /// if on windows, we use `cmd`. If on Linux, we use `sh`
 /// returns a `PathBuf` of the home path with "crypt"
@@ -443,7 +489,7 @@ pub fn get_config_folder() -> PathBuf {
 /// # Example
 /// assuming user profile name is ryan
 /// ```rust ignore
-/// let path = get_crypt_folder();
+/// let path = home_crypt_folder();
 /// // for windows
 /// assert_eq(path, "C:\\users\\ryan\\crypt");
 /// // for linux
@@ -453,7 +499,7 @@ pub fn get_config_folder() -> PathBuf {
 ///
 /// function can panic if either the process fails,
 /// or the conversion from `Vec<u8>` to String fails.
-pub fn get_crypt_folder() -> PathBuf {
+pub fn home_crypt_folder() -> PathBuf {
     let output = if cfg!(target_os = "windows") {
         Command::new("cmd")
             .args(["/C", "echo %userprofile%"])
@@ -543,135 +589,9 @@ pub fn get_filenames_from_subdirectories<T: AsRef<Path>>(
     Ok((filenames, folders))
 }
 
-/// Displays a menu for choosing files and folders from the users `crypt` folder.
-///
-/// # Arguments
-///
-/// * `item`: A string representing the filename or part of it to filter the list.
-///
-/// # Returns
-///
-/// Returns a `PathBuf` representing the user's selection. Returns an empty `PathBuf` if the user chooses to abort.
-///
+/// `Vault::chooser` of the default vault.
 pub fn chooser(item: &str) -> Result<PathBuf> {
-    let (mut files, mut folders) = walk_crypt_folder().unwrap_or_else(|_| (Vec::new(), Vec::new()));
-
-    if files.is_empty() {
-        return Err(Error::CommonError(error::CommonError::CryptFolderIsEmpty));
-    }
-
-    files.sort();
-    folders.sort();
-
-    let mut count = 1;
-
-    if !item.is_empty() {
-        // Filter files based on item
-        files.retain(|p| {
-            p.file_stem()
-                .map(|stem| stem.to_ascii_lowercase().to_string_lossy().contains(item))
-                .unwrap_or(false)
-                || p.file_name()
-                    .map(|name| name.to_ascii_lowercase() == item)
-                    .unwrap_or(false)
-        });
-
-        // Filter folders based on item
-        folders.retain(|p| {
-            p.file_stem()
-                .map(|stem| stem.to_ascii_lowercase().to_string_lossy().contains(item))
-                .unwrap_or(false)
-                || p.file_name()
-                    .map(|name| name.to_ascii_lowercase() == item)
-                    .unwrap_or(false)
-        });
-    }
-
-    println!("\nplease choose from the following: (or 0 to abort)");
-    println!("{: <3} {: <45} {: <14}", "#", "files", "last modified");
-    println!("----------------------------------------------------------------");
-    for item in &files {
-        let item_str = item.to_string_lossy();
-
-        let partal_path = if let Some(found) = item_str.find(r#"\crypt"#) {
-            // split at "found" + 6 to get rid of '\crypt' to save space
-            item_str.split_at(found + 6).1
-        } else {
-            &item_str
-        };
-
-        let cropped_str = Path::new(partal_path).to_string_lossy().to_string();
-
-        // since our main width is 45 characters, crop path so we look nice and neat.
-        let display_path = match cropped_str.len() > 45 {
-            true => &cropped_str[cropped_str.len() - 45..],
-            false => cropped_str.as_ref(),
-        };
-
-        println!(
-            "{: <3} {: <45} {: <14}",
-            count,
-            display_path,
-            get_sys_time_timestamp(item.metadata().unwrap().modified().unwrap())
-        );
-
-        count += 1;
-    }
-
-    if !folders.is_empty() {
-        println!("----------------------------------------------------------------\n");
-        println!("{: <3} {: <45} ", "#", "folders",);
-        println!("----------------------------------------------------------------");
-
-        for i in folders {
-            if i.display().to_string() == std::path::MAIN_SEPARATOR_STR {
-                continue;
-            }
-            let item_str = i.to_string_lossy();
-
-            let partal_path = if let Some(found) = item_str.find(r#"\crypt"#) {
-                // split at "found" + 6 to get rid of '\crypt' to save space
-                item_str.split_at(found).1
-            } else {
-                &item_str
-            };
-
-            // convert to string for easier length checking and cropping if needed.
-            let cropped_str = Path::new(partal_path).to_string_lossy().to_string();
-
-            // since our main width is 45 characters, crop path so we look nice and neat.
-            let display_path = match cropped_str.len() > 45 {
-                true => &cropped_str[cropped_str.len() - 45..],
-                false => cropped_str.as_ref(),
-            };
-            println!("{: <3} {: <45}", count, display_path);
-
-            // add folder to files vector for easier picking later.
-            files.push(i.to_path_buf());
-
-            count += 1;
-        }
-        println!("----------------------------------------------------------------");
-    }
-    // Get choice from user
-    loop {
-        let mut number = String::new();
-        if io::stdin().read_line(&mut number).is_err() {
-            println!("Error reading input. Please try again.");
-            continue;
-        }
-
-        let num: usize = number.trim().parse().unwrap_or_default();
-        if num == 0 {
-            return Err(Error::CommonError(error::CommonError::UserAbort));
-        }
-        if num > files.len() {
-            println!("invalid selection. please try again.");
-            continue;
-        }
-
-        return Ok(files[num - 1].to_owned());
-    }
+    default_vault()?.chooser(item)
 }
 
 /// Converts a `SystemTime` into a formatted "date : time" string.
@@ -776,7 +696,7 @@ where
     T::Item: Display,
 {
     for item in info {
-        println!("{}", item);
+        print_line(item);
     }
 }
 
@@ -785,6 +705,28 @@ pub fn send_information(info: Vec<String>) {
     print_information(info);
 }
 
+/// Set once the CLI prints JSON, see `set_json_output`.
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Switches to JSON output: `stdout` then only carries the JSON the CLI prints, messages for the
+/// user (`send_information`, the chooser) go to `stderr`.
+pub fn set_json_output(json: bool) {
+    JSON_OUTPUT.store(json, Ordering::Relaxed);
+}
+
+/// Returns `true` if the CLI prints JSON, see `set_json_output`.
+pub fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Prints a line for the user, on `stderr` when printing JSON.
+fn print_line<T: Display>(line: T) {
+    match json_output() {
+        true => eprintln!("{}", line),
+        false => println!("{}", line),
+    }
+}
+
 /// Takes in a path, and recursively walks the subdirectories and returns a `Vec<PathBuf>`
 /// The `filter_directories` parameter determines whether to filter entries based on the presence of a dot ('.')
 /// # Examples
@@ -808,10 +750,11 @@ pub fn walk_directory<T: AsRef<Path>>(
         false => get_full_file_path(path_in),
     };
 
+    let conf = default_vault()?.config();
     let walker = WalkDir::new(path).into_iter();
     let mut pathlist: Vec<PathBuf> = Vec::new();
 
-    for entry in walker.filter_entry(|e| !is_hidden(e)) {
+    for entry in walker.filter_entry(|e| !is_hidden(e, &conf)) {
         let entry = entry?;
 
         if !filter_directories || entry.path().display().to_string().find('.').is_some() {
@@ -822,56 +765,231 @@ pub fn walk_directory<T: AsRef<Path>>(
     Ok(pathlist)
 }
 
-/// Recursively walks the subdirectories of the crypt folder and returns a `Vec<PathBuf>`.
-///
-/// # Returns
-///
-/// Returns a Result containing a `Vec<PathBuf>` with paths to files within the crypt folder,
-/// excluding certain folders such as "logs" and "decrypted". If an error occurs during the
-/// walking process, an Err variant is returned with an associated error message.
-///
-/// # Errors
-///
-/// This function may return an error if there are issues with walking the directories or
-/// filtering entries.
-///
-/// # Examples
-///
-/// ```rust ignore  
-/// match walk_crypt_folder() {
-///     Ok(paths) => {
-///         for path in paths {
-///             println!("Found file: {}", path.display());
-///         }
-///     }
-///     Err(err) => eprintln!("Error: {}", err),
-/// }
-/// ```
+/// `Vault::walk_crypt_folder` of the default vault.
 pub fn walk_crypt_folder() -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
-    let crypt_folder = get_crypt_folder();
+    default_vault()?.walk_crypt_folder()
+}
+
+impl Vault {
+    /// Gets the crypt folder of the vault, see `Vault::root`, creating it if missing.
+    pub fn get_crypt_folder(&self) -> PathBuf {
+        let path = self.root().to_path_buf();
+        if !path.exists() {
+            _ = std::fs::create_dir_all(&path);
+        }
+        path
+    }
 
-    // folders to avoid
-    let log_folder = Path::new(&crypt_folder).join("logs");
-    let decrypted_folder = Path::new(&crypt_folder).join("decrypted");
+    /// Displays a menu for choosing files and folders from the users `crypt` folder.
+    ///
+    /// Files are listed and filtered by their original name when it can be resolved (see
+    /// `filecrypt::resolve_crypt_name`), so uuid and encrypted names stay usable.
+    ///
+    /// # Arguments
+    ///
+    /// * `item`: A string representing the filename or part of it to filter the list.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `PathBuf` representing the user's selection. Returns an empty `PathBuf` if the user chooses to abort.
+    ///
+    pub fn chooser(&self, item: &str) -> Result<PathBuf> {
+        let (mut files, mut folders) = self
+            .walk_crypt_folder()
+            .unwrap_or_else(|_| (Vec::new(), Vec::new()));
+
+        if files.is_empty() {
+            return Err(Error::CommonError(error::CommonError::CryptFolderIsEmpty));
+        }
 
-    let walker = WalkDir::new(crypt_folder).into_iter();
+        files.sort();
+        folders.sort();
+
+        // pair every file with its original name, falling back to the name on disk
+        let mut files: Vec<(PathBuf, Option<String>)> = files
+            .into_iter()
+            .map(|p| {
+                let name = self.resolve_crypt_name(&p);
+                (p, name)
+            })
+            .collect();
+
+        let mut count = 1;
+
+        if !item.is_empty() {
+            // Filter files based on item
+            files.retain(|(p, name)| match name {
+                Some(name) => name.to_ascii_lowercase().contains(item),
+                None => {
+                    p.file_stem()
+                        .map(|stem| stem.to_ascii_lowercase().to_string_lossy().contains(item))
+                        .unwrap_or(false)
+                        || p.file_name()
+                            .map(|name| name.to_ascii_lowercase() == item)
+                            .unwrap_or(false)
+                }
+            });
 
-    let (filenames, folders): (Vec<_>, Vec<_>) = walker
-        .filter_entry(|e| {
-            !is_hidden(e)
-                && !e.path().starts_with(&log_folder)
-                && !e.path().starts_with(&decrypted_folder)
-        })
-        .filter_map(|entry| {
-            let entry = entry.ok()?;
-            if entry.path().is_file() || entry.path().is_dir() {
-                Some(entry.path().to_owned())
+            // Filter folders based on item
+            folders.retain(|p| {
+                p.file_stem()
+                    .map(|stem| stem.to_ascii_lowercase().to_string_lossy().contains(item))
+                    .unwrap_or(false)
+                    || p.file_name()
+                        .map(|name| name.to_ascii_lowercase() == item)
+                        .unwrap_or(false)
+            });
+        }
+
+        print_line("\nplease choose from the following: (or 0 to abort)");
+        print_line(format!(
+            "{: <3} {: <45} {: <14}",
+            "#", "files", "last modified"
+        ));
+        print_line("----------------------------------------------------------------");
+        for (item, name) in &files {
+            let item_str = item.to_string_lossy();
+
+            let partal_path = if let Some(found) = item_str.find(r#"\crypt"#) {
+                // split at "found" + 6 to get rid of '\crypt' to save space
+                item_str.split_at(found + 6).1
             } else {
-                None
+                &item_str
+            };
+
+            let cropped_str = match name {
+                Some(name) => Path::new(partal_path).with_file_name(name),
+                None => PathBuf::from(partal_path),
             }
-        })
-        .partition(|entry| entry.is_file());
-    Ok((filenames, folders))
+            .to_string_lossy()
+            .to_string();
+
+            // since our main width is 45 characters, crop path so we look nice and neat.
+            let display_path = match cropped_str.len() > 45 {
+                true => &cropped_str[cropped_str.len() - 45..],
+                false => cropped_str.as_ref(),
+            };
+
+            print_line(format!(
+                "{: <3} {: <45} {: <14}",
+                count,
+                display_path,
+                get_sys_time_timestamp(item.metadata().unwrap().modified().unwrap())
+            ));
+
+            count += 1;
+        }
+
+        if !folders.is_empty() {
+            print_line("----------------------------------------------------------------\n");
+            print_line(format!("{: <3} {: <45} ", "#", "folders",));
+            print_line("----------------------------------------------------------------");
+
+            for i in folders {
+                if i.display().to_string() == std::path::MAIN_SEPARATOR_STR {
+                    continue;
+                }
+                let item_str = i.to_string_lossy();
+
+                let partal_path = if let Some(found) = item_str.find(r#"\crypt"#) {
+                    // split at "found" + 6 to get rid of '\crypt' to save space
+                    item_str.split_at(found).1
+                } else {
+                    &item_str
+                };
+
+                // convert to string for easier length checking and cropping if needed.
+                let cropped_str = Path::new(partal_path).to_string_lossy().to_string();
+
+                // since our main width is 45 characters, crop path so we look nice and neat.
+                let display_path = match cropped_str.len() > 45 {
+                    true => &cropped_str[cropped_str.len() - 45..],
+                    false => cropped_str.as_ref(),
+                };
+                print_line(format!("{: <3} {: <45}", count, display_path));
+
+                // add folder to files vector for easier picking later.
+                files.push((i.to_path_buf(), None));
+
+                count += 1;
+            }
+            print_line("----------------------------------------------------------------");
+        }
+        // Get choice from user
+        loop {
+            let mut number = String::new();
+            if io::stdin().read_line(&mut number).is_err() {
+                print_line("Error reading input. Please try again.");
+                continue;
+            }
+
+            let num: usize = number.trim().parse().unwrap_or_default();
+            if num == 0 {
+                return Err(Error::CommonError(error::CommonError::UserAbort));
+            }
+            if num > files.len() {
+                print_line("invalid selection. please try again.");
+                continue;
+            }
+
+            return Ok(files[num - 1].0.to_owned());
+        }
+    }
+
+    /// Recursively walks the subdirectories of the crypt folder and returns a `Vec<PathBuf>`.
+    ///
+    /// # Returns
+    ///
+    /// Returns a Result containing a `Vec<PathBuf>` with paths to files within the crypt folder,
+    /// excluding certain folders such as "logs" and "decrypted". If an error occurs during the
+    /// walking process, an Err variant is returned with an associated error message.
+    ///
+    /// # Errors
+    ///
+    /// This function may return an error if there are issues with walking the directories or
+    /// filtering entries.
+    ///
+    /// # Examples
+    ///
+    /// ```rust ignore  
+    /// match walk_crypt_folder() {
+    ///     Ok(paths) => {
+    ///         for path in paths {
+    ///             println!("Found file: {}", path.display());
+    ///         }
+    ///     }
+    ///     Err(err) => eprintln!("Error: {}", err),
+    /// }
+    /// ```
+    pub fn walk_crypt_folder(&self) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+        let crypt_folder = self.get_crypt_folder();
+        let conf = self.config();
+
+        // folders to avoid
+        let log_folder = Path::new(&crypt_folder).join("logs");
+        let decrypted_folder = Path::new(&crypt_folder).join("decrypted");
+        let exported_folder = Path::new(&crypt_folder).join("exported");
+
+        let walker = WalkDir::new(crypt_folder).into_iter();
+
+        let (filenames, folders): (Vec<_>, Vec<_>) = walker
+            .filter_entry(|e| {
+                !is_hidden(e, &conf)
+                    && !e.path().starts_with(&log_folder)
+                    && !e.path().starts_with(&decrypted_folder)
+                    && !e.path().starts_with(&exported_folder)
+            })
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                if entry.path().is_file() || entry.path().is_dir() {
+                    Some(entry.path().to_owned())
+                } else {
+                    None
+                }
+            })
+            .partition(|entry| entry.is_file());
+        Ok((filenames, folders))
+    }
 }
 
 /// takes in a path, and recursively walks the subdirectories and returns a `vec<pathbuf>`
@@ -889,10 +1007,17 @@ pub fn walk_paths<T: AsRef<str>>(path_in: T) -> Vec<PathInfo> {
         return Vec::new();
     }
 
+    let conf = match default_vault() {
+        Ok(vault) => vault.config(),
+        Err(err) => {
+            eprintln!("Error loading config: {}", err);
+            return Vec::new();
+        }
+    };
     let walker = WalkDir::new(path).into_iter();
     let mut pathlist: Vec<PathInfo> = Vec::new();
 
-    for entry in walker.filter_entry(|e| !is_hidden(e)) {
+    for entry in walker.filter_entry(|e| !is_hidden(e, &conf)) {
         if let Ok(entry) = entry {
             let entry_path = entry.path().display().to_string();
             pathlist.push(PathInfo::new(entry_path.as_str()));
@@ -918,11 +1043,12 @@ pub fn get_full_file_path<T: AsRef<Path>>(path: T) -> PathBuf {
 ///
 /// This function examines the file name of a `DirEntry` and determines whether it
 /// should be considered hidden according to the configured items to ignore. The
-/// configuration is obtained using `config::get_config()`.
+/// configuration is `conf`.
 ///
 /// # Arguments
 ///
 /// * `entry`: A reference to a `DirEntry` representing a file or directory entry.
+/// * `conf`: The configuration listing the items to ignore.
 ///
 /// # Returns
 ///
@@ -937,7 +1063,7 @@ pub fn get_full_file_path<T: AsRef<Path>>(path: T) -> PathBuf {
 /// // Assuming you have a DirEntry, e.g., obtained during directory traversal
 /// let dir_entry = /* ... */;
 ///
-/// if is_hidden(&dir_entry) {
+/// if is_hidden(&dir_entry, &Config::default()) {
 ///     println!("The file is hidden.");
 /// } else {
 ///     println!("The file is not hidden.");
@@ -947,12 +1073,10 @@ pub fn get_full_file_path<T: AsRef<Path>>(path: T) -> PathBuf {
 /// # Note
 ///
 /// - If the file name is not a valid UTF-8 string, it is considered hidden.
-/// - The configuration, obtained through `config::get_config()`, specifies items
+/// - The configuration specifies items
 ///   to ignore, and any file name containing or starting with these items is
 ///   considered hidden.
-pub fn is_hidden(entry: &walkdir::DirEntry) -> bool {
-    let conf = config::get_config();
-
+pub fn is_hidden(entry: &walkdir::DirEntry, conf: &Config) -> bool {
     if let Some(s) = entry.file_name().to_str() {
         // Early return if the file name is not a valid UTF-8 string
         if s.is_empty() {
@@ -970,10 +1094,67 @@ pub fn is_hidden(entry: &walkdir::DirEntry) -> bool {
     true // Return true if the file name is not a valid UTF-8 string
 }
 
+/// Gets the number of worker threads to spread work over, one per core.
+pub fn worker_count() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Applies `f` to every item on a pool of `worker_count` threads.
+///
+/// Workers take the next item as soon as they are done with the previous one, so a few large
+/// items do not hold up the rest.
+///
+/// # Returns
+///
+/// The results, in the order of `items`.
+pub fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let workers = worker_count().min(items.len());
+
+    let mut results: Vec<(usize, R)> = thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        match items.get(i) {
+                            Some(item) => done.push((i, f(item))),
+                            None => return done,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("worker thread panicked"))
+            .collect()
+    });
+
+    results.sort_unstable_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_shred_file() {
+        let path = std::env::temp_dir().join(format!("crypt-shred-{}", std::process::id()));
+        std::fs::write(&path, vec![7u8; 100_000]).unwrap();
+        shred_file(&path).unwrap();
+        assert!(!path.exists());
+        assert!(shred_file(&path).is_err());
+    }
+
     #[test]
     // works locally, but for some reason fails in the CI test!
     #[ignore]
@@ -985,4 +1166,12 @@ mod tests {
             "encryption_benchmark.rs"
         );
     }
+
+    #[test]
+    fn test_parallel_map() {
+        let items: Vec<u64> = (0..1000).collect();
+        let squares = parallel_map(&items, |i| i * i);
+        assert_eq!(squares, items.iter().map(|i| i * i).collect::<Vec<_>>());
+        assert!(parallel_map(&[] as &[u64], |i| *i).is_empty());
+    }
 }
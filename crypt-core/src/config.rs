@@ -1,13 +1,21 @@
 use crate::{
+    cipher::Cipher,
     common::{self, get_machine_name, send_information},
-    db::{self},
+    encryption::KeyMode,
+    error,
+    padding::Padding,
     prelude::*,
+    vault::{default_log_path, default_vault},
 };
 use chrono::prelude::*;
 use lazy_static::lazy_static;
 use logfather::*;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::Path, sync::RwLock};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 lazy_static! {
     ///Config path pointing to default home
@@ -22,52 +30,25 @@ lazy_static! {
         path.push("config.toml");
         format!("{}", path.display())
     };
-
-    ///Config path pointing to default home
-    pub static ref CRYPT_PATH: String = {
-        let path = common::get_crypt_folder();
-        if !path.exists() {
-            _ = std::fs::create_dir(&path);
-        }
-        format!("{}", path.display())
-    };
-
-    ///Loads and holds config for session
-    static ref CONFIG: RwLock<Config> = RwLock::new({
-        match load_config() {
-            Ok(config) => config,
-            Err(err) => panic!("Failed to load config: {}", err),
-        }
-    });
-
-    static ref INTERFACE: RwLock<Interface> = RwLock::new(Interface::None);
-
-    pub static ref LOG_PATH: String = {
-        let mut path = common::get_config_folder();
-        path.push("logs");
-
-        if !path.exists() {
-            _ = std::fs::create_dir(&path);
-        }
-
-        let date = Local::now();
-
-        let current_date = format!("{}-{}.txt", date.year(), date.month());
-
-        path.push(current_date);
-        format!("{}", path.display())
-    };
 }
 
-pub fn init(interface: Interface) {
-    set_interface(&interface);
+pub fn init(interface: Interface) -> Result<()> {
     load_logger(&interface);
-    _ = get_config();
-    _ = db::get_keeper();
+    let vault = default_vault()?;
+    vault.set_interface(&interface);
+    _ = vault.get_keeper();
+    Ok(())
 }
 
 fn load_logger(interface: &Interface) {
-    let mut logger = Logger::new().file(true).path(LOG_PATH.as_str());
+    let log_path = default_log_path();
+    if let Some(parent) = log_path.parent() {
+        _ = std::fs::create_dir_all(parent);
+    }
+
+    let mut logger = Logger::new()
+        .file(true)
+        .path(&log_path.display().to_string());
     logger.log_format("[{timestamp} {level}] {module_path}: {message}");
     // logger.log_format("[{timestamp} {level}] <cyan>{module_path}</cyan>: {message}");
     logger.timezone(logfather::TimeZone::Utc);
@@ -89,29 +70,36 @@ pub enum Interface {
     GUI,
 }
 
-pub fn get_interface() -> Interface {
-    INTERFACE
-        .read()
-        .expect("Cannot read interface type")
-        .clone()
+/// `Vault::interface` of the default vault.
+pub fn get_interface() -> Result<Interface> {
+    Ok(default_vault()?.interface())
 }
 
-pub fn set_interface(interface_type: &Interface) {
-    let mut interface = INTERFACE.write().expect("Cannot write interface type");
-    *interface = interface_type.clone();
+/// `Vault::set_interface` of the default vault.
+pub fn set_interface(interface_type: &Interface) -> Result<()> {
+    default_vault()?.set_interface(interface_type);
+    Ok(())
 }
 
-pub fn get_config() -> Config {
-    CONFIG.read().expect("Cannot read config, locked").clone()
+/// Gets the log file of the folder `dir` for the current month, e.g. `logs/2024-5.txt`.
+pub(crate) fn log_file_in(dir: &Path) -> PathBuf {
+    let date = Local::now();
+    dir.join(format!("{}-{}.txt", date.year(), date.month()))
 }
 
-pub fn get_config_write() -> std::sync::RwLockWriteGuard<'static, Config> {
-    CONFIG.write().expect("Cannot write to config, locked")
+/// `Vault::config` of the default vault.
+pub fn get_config() -> Result<Config> {
+    Ok(default_vault()?.config())
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 ///Holds the configuration for the program
 pub struct Config {
+    /// file the setters save the config to. `None` keeps the config in memory only, as for a
+    /// `Config::default()`. Set by `load_config_from`.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+
     /// serves as the default location for the SQLite database path.
     pub database_path: String,
 
@@ -130,17 +118,100 @@ pub struct Config {
     /// option to retain both the original file after encryption,
     /// as well as the .crypt file after decryption.
     /// if true, retains original file and encrypted file.
-    /// if false, deletes files after encryption / decryption.
-    // pub retain: bool,
-
-    // /// option to retain a backup copy of all `*.crypt` files into a backup folder for
-    // /// redundant storage. This only keeps the LATEST version, to not take up too much
-    // /// space.
-    // pub backup: bool,
-
+    /// if false, deletes files after encryption / decryption. Originals are only deleted once
+    /// their `.crypt` file verifies, and are overwritten first. Default is `true`.
+    #[serde(default = "default_retain")]
+    pub retain: bool,
+
+    /// option to retain a backup copy of all `*.crypt` files into a backup folder for
+    /// redundant storage. This only keeps the LATEST version, to not take up too much
+    /// space.
+    #[serde(default)]
+    pub backup: bool,
+
+    /// backup folder, e.g. on a second disk. Default is `crypt_backup` next to the crypt folder.
+    #[serde(default = "default_backup_path")]
+    pub backup_path: String,
     /// zstd level is for file compression, from [fastest, least compression]
     /// to [slowest, highest compression] `-7 to 22`. Default compression level is 3.
     pub zstd_level: i32,
+
+    /// extensions of files that are always stored uncompressed, e.g. `["jpg", "mp4"]`. Other
+    /// files are stored uncompressed if their contents do not compress, see
+    /// `encryption::is_compressible`.
+    #[serde(default)]
+    pub no_compress: Vec<String>,
+
+    /// AEAD cipher used for newly encrypted files. Existing files keep the cipher they were
+    /// encrypted with. Default is `xchacha20poly1305`.
+    #[serde(default)]
+    pub cipher: Cipher,
+
+    /// how `.crypt` files are named in the crypt folder and in the cloud. Default is `plain`.
+    #[serde(default)]
+    pub name_mode: NameMode,
+
+    /// how the keys of newly encrypted files are produced. Existing files keep their key mode.
+    /// Default is `random`.
+    #[serde(default)]
+    pub key_mode: KeyMode,
+
+    /// padding applied to the payload of newly encrypted files to hide their length. Default is
+    /// `none`.
+    #[serde(default)]
+    pub padding: Padding,
+}
+
+fn default_retain() -> bool {
+    true
+}
+
+fn default_backup_path() -> String {
+    let backup_path = common::home_crypt_folder().with_file_name("crypt_backup");
+    format!("{}", backup_path.display())
+}
+
+/// Naming scheme for `.crypt` files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameMode {
+    /// `<filename>.crypt`, the original file name stays visible.
+    #[default]
+    Plain,
+
+    /// `<uuid>.crypt`, resolved back to the original name through the keeper.
+    Uuid,
+
+    /// The original name encrypted under the keeper master key, hex encoded. Passphrase
+    /// protected files are sealed with their own key, so their name only resolves with the
+    /// passphrase. Names over 95 bytes would not fit a file name once sealed, those files are
+    /// named like `NameMode::Uuid`.
+    Encrypted,
+}
+
+impl fmt::Display for NameMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameMode::Plain => write!(f, "plain"),
+            NameMode::Uuid => write!(f, "uuid"),
+            NameMode::Encrypted => write!(f, "encrypted"),
+        }
+    }
+}
+
+impl FromStr for NameMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "plain" => Ok(NameMode::Plain),
+            "uuid" => Ok(NameMode::Uuid),
+            "encrypted" => Ok(NameMode::Encrypted),
+            _ => Err(Error::CommonError(error::CommonError::UnknownNameMode(
+                s.to_string(),
+            ))),
+        }
+    }
 }
 
 ///Enum for storing each item in the config struct
@@ -151,6 +222,7 @@ pub struct Config {
 /// * `ConfigTask::Retain`
 /// * `ConfigTask::Backup`
 /// * `ConfigTask::ZstdLevel`
+/// * `ConfigTask::NoCompress`
 ///
 pub enum ConfigOptions {
     DatabasePath,
@@ -159,6 +231,14 @@ pub enum ConfigOptions {
     IgnoreItems,
     Hwid,
     ZstdLevel,
+    NoCompress,
+    Cipher,
+    NameMode,
+    KeyMode,
+    Padding,
+    Retain,
+    Backup,
+    BackupPath,
 }
 
 impl ToString for ConfigOptions {
@@ -169,7 +249,15 @@ impl ToString for ConfigOptions {
             Self::IgnoreItems => "ignore_items".to_string(),
             Self::Hwid => "hwid".to_string(),
             Self::ZstdLevel => "zstd_level".to_string(),
+            Self::NoCompress => "no_compress".to_string(),
             Self::CryptPath => "crypt_path".to_string(),
+            Self::Cipher => "cipher".to_string(),
+            Self::NameMode => "name_mode".to_string(),
+            Self::KeyMode => "key_mode".to_string(),
+            Self::Padding => "padding".to_string(),
+            Self::Retain => "retain".to_string(),
+            Self::Backup => "backup".to_string(),
+            Self::BackupPath => "backup_path".to_string(),
         }
     }
 }
@@ -183,6 +271,14 @@ impl ToString for ConfigOptions {
 /// ConfigTask::CryptPath
 /// ConfigTask::IgnoreItems(ItemTask, String)
 /// ConfigTask::ZstdLevel(i32)
+/// ConfigTask::NoCompress(ItemsTask, String)
+/// ConfigTask::Cipher(Option<Cipher>)
+/// ConfigTask::NameMode(Option<NameMode>)
+/// ConfigTask::KeyMode(Option<KeyMode>)
+/// ConfigTask::Padding(Option<Padding>)
+/// ConfigTask::Retain(Option<bool>)
+/// ConfigTask::Backup(Option<bool>)
+/// ConfigTask::BackupPath
 /// ConfigTask::LoadDefault
 ///```
 pub enum ConfigTask {
@@ -192,6 +288,14 @@ pub enum ConfigTask {
     IgnoreItems(ItemsTask, String),
     Hwid,
     ZstdLevel(i32),
+    NoCompress(ItemsTask, String),
+    Cipher(Option<Cipher>),
+    NameMode(Option<NameMode>),
+    KeyMode(Option<KeyMode>),
+    Padding(Option<Padding>),
+    Retain(Option<bool>),
+    Backup(Option<bool>),
+    BackupPath,
     LoadDefault,
 }
 
@@ -221,6 +325,14 @@ impl std::fmt::Display for Config {
         _ = writeln!(f, "  ignore_item: {:?}", self.ignore_items);
         _ = writeln!(f, "  hwid: {:?}", self.hwid);
         _ = writeln!(f, "  zstd_level: {}", self.zstd_level);
+        _ = writeln!(f, "  no_compress: {:?}", self.no_compress);
+        _ = writeln!(f, "  cipher: {}", self.cipher);
+        _ = writeln!(f, "  name_mode: {}", self.name_mode);
+        _ = writeln!(f, "  key_mode: {}", self.key_mode);
+        _ = writeln!(f, "  padding: {}", self.padding);
+        _ = writeln!(f, "  retain: {}", self.retain);
+        _ = writeln!(f, "  backup: {}", self.backup);
+        _ = writeln!(f, "  backup_path: {}", self.backup_path);
         std::fmt::Result::Ok(())
     }
 }
@@ -229,21 +341,31 @@ impl Default for Config {
     fn default() -> Self {
         let mut database_path = common::get_config_folder();
         database_path.push(".config/crypt_keeper.db");
-        let crypt_path = common::get_crypt_folder();
+        let crypt_path = common::home_crypt_folder();
         let hwid = get_machine_name();
 
         Config {
+            path: None,
             database_path: format!("{}", database_path.display()),
             crypt_path: format!("{}", crypt_path.display()),
             ignore_hidden: true,
             ignore_items: vec!["target".to_string()],
             hwid,
             zstd_level: 3,
+            no_compress: vec![],
+            cipher: Cipher::default(),
+            name_mode: NameMode::default(),
+            key_mode: KeyMode::default(),
+            padding: Padding::default(),
+            retain: true,
+            backup: false,
+            backup_path: default_backup_path(),
         }
     }
 }
 
 impl Config {
+    #[allow(clippy::too_many_arguments)]
     fn _new(
         database_path: String,
         crypt_path: String,
@@ -251,19 +373,39 @@ impl Config {
         ignore_items: Vec<String>,
         hwid: String,
         zstd_level: i32,
+        no_compress: Vec<String>,
+        cipher: Cipher,
+        name_mode: NameMode,
+        key_mode: KeyMode,
+        padding: Padding,
+        retain: bool,
+        backup: bool,
+        backup_path: String,
     ) -> Self {
         Self {
+            path: None,
             database_path,
             crypt_path,
             ignore_hidden,
             ignore_items,
             hwid,
             zstd_level,
+            no_compress,
+            cipher,
+            name_mode,
+            key_mode,
+            padding,
+            retain,
+            backup,
+            backup_path,
         }
     }
 
     pub fn restore_default(&mut self) -> bool {
-        *self = Config::default();
+        *self = Config {
+            path: self.path.take(),
+            ..Config::default()
+        };
 
         if save_config(self).is_err() {
             return false;
@@ -326,6 +468,36 @@ impl Config {
         }
     }
 
+    pub fn get_no_compress(&self) -> &[String] {
+        self.no_compress.as_ref()
+    }
+
+    pub fn set_no_compress(&mut self, extensions: Vec<String>) {
+        self.no_compress = extensions;
+        _ = save_config(self);
+    }
+
+    pub fn append_no_compress(&mut self, ext: &str) {
+        self.no_compress
+            .push(ext.trim_start_matches('.').to_lowercase());
+        _ = save_config(self);
+    }
+
+    pub fn remove_no_compress(&mut self, ext: &str) {
+        let ext = ext.trim_start_matches('.').to_lowercase();
+        if let Some(index) = self.no_compress.iter().position(|x| *x == ext) {
+            self.no_compress.remove(index);
+            _ = save_config(self);
+        }
+    }
+
+    /// Returns `true` if files with extension `ext` (with or without the leading `.`) are
+    /// always stored uncompressed.
+    pub fn skips_compression(&self, ext: &str) -> bool {
+        let ext = ext.trim_start_matches('.');
+        self.no_compress.iter().any(|x| x.eq_ignore_ascii_case(ext))
+    }
+
     pub fn get_zstd_level(&self) -> i32 {
         self.zstd_level
     }
@@ -343,15 +515,88 @@ impl Config {
             }
         }
     }
+
+    pub fn get_cipher(&self) -> Cipher {
+        self.cipher
+    }
+
+    pub fn set_cipher(&mut self, cipher: Cipher) {
+        self.cipher = cipher;
+        _ = save_config(self);
+    }
+
+    pub fn get_name_mode(&self) -> NameMode {
+        self.name_mode
+    }
+
+    pub fn set_name_mode(&mut self, name_mode: NameMode) {
+        self.name_mode = name_mode;
+        _ = save_config(self);
+    }
+
+    pub fn get_key_mode(&self) -> KeyMode {
+        self.key_mode
+    }
+
+    pub fn set_key_mode(&mut self, key_mode: KeyMode) {
+        self.key_mode = key_mode;
+        _ = save_config(self);
+    }
+
+    pub fn get_padding(&self) -> Padding {
+        self.padding
+    }
+
+    pub fn set_padding(&mut self, padding: Padding) {
+        self.padding = padding;
+        _ = save_config(self);
+    }
+
+    pub fn get_retain(&self) -> bool {
+        self.retain
+    }
+
+    pub fn set_retain(&mut self, retain: bool) {
+        self.retain = retain;
+        _ = save_config(self);
+    }
+
+    pub fn get_backup(&self) -> bool {
+        self.backup
+    }
+
+    pub fn set_backup(&mut self, backup: bool) {
+        self.backup = backup;
+        _ = save_config(self);
+    }
+
+    pub fn get_backup_path(&self) -> &str {
+        self.backup_path.as_ref()
+    }
+
+    pub fn set_backup_path(&mut self, path: &str) {
+        self.backup_path = path.to_owned();
+        _ = save_config(self);
+    }
 }
 
 ///Loads configuration file -- creates default if missing
 pub fn load_config() -> Result<Config> {
+    load_config_from(CONFIG_PATH.as_str())
+}
+
+/// Loads the configuration file at `path`, creating it with the defaults if missing. The setters
+/// of the returned config save it back to `path`.
+pub fn load_config_from<T: AsRef<Path>>(path: T) -> Result<Config> {
     info!("loading config");
-    let mut config: Config = Config::default();
+    let path = path.as_ref();
+    let mut config = Config {
+        path: Some(path.to_path_buf()),
+        ..Config::default()
+    };
 
     //If the file doesn't exist, re-create and load defaults
-    if !Path::new(CONFIG_PATH.as_str()).exists() {
+    if !path.exists() {
         warning!("No configuration found, reloading with defaults!");
         save_config(&config)?;
         return Ok(config);
@@ -359,9 +604,12 @@ pub fn load_config() -> Result<Config> {
 
     //Attempt to import config
     //TODO: handle more gracefully - ask user for desired change
-    let content = fs::read_to_string(CONFIG_PATH.as_str())?;
-    config = match toml::from_str(content.as_str()) {
-        core::result::Result::Ok(config) => config,
+    let content = fs::read_to_string(path)?;
+    config = match toml::from_str::<Config>(content.as_str()) {
+        core::result::Result::Ok(loaded) => Config {
+            path: config.path,
+            ..loaded
+        },
         Err(e) => {
             send_information(vec![format!(
                 "Error loading config: {}\nloading from default",
@@ -377,11 +625,14 @@ pub fn load_config() -> Result<Config> {
     Ok(config)
 }
 
-///Saves the configuration file
+///Saves the configuration file to `Config::path`, if it has one
 pub fn save_config(config: &Config) -> Result<()> {
+    let Some(path) = &config.path else {
+        return Ok(());
+    };
     info!("saving config");
     //Serialize config
     let serialized_config = toml::to_string_pretty(&config)?;
-    fs::write(CONFIG_PATH.as_str(), serialized_config)?;
+    fs::write(path, serialized_config)?;
     Ok(())
 }
@@ -1,40 +1,71 @@
 use crate::{
-    common::{get_config_folder, write_contents_to_file},
-    config::get_config,
-    encryption::{KEY_SIZE, NONCE_SIZE},
+    cipher::Cipher,
+    common::{get_config_folder, worker_count, write_contents_to_file},
+    encryption::{
+        derive_file_key, generate_seeds, open_blob, open_name, seal_blob, seal_name, unwrap_key,
+        wrap_key, KdfParams, KeyMode, KEY_SIZE, NONCE_SIZE,
+    },
+    error::{DatabaseError, EncryptionError},
     filecrypt::FileCrypt,
     prelude::*,
+    recipient::{Identity, RecipientKey, X25519_KEY_SIZE},
     token::{CloudService, UserToken},
+    vault::{default_vault, Vault},
 };
 use csv::{StringRecord, WriterBuilder};
-use lazy_static::lazy_static;
 use logfather::*;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
 use std::{
     fs,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
-//Connection pool maintains a single connection to db for life of program
-//TODO: increase pool size from 1 to allow for multithreading
-lazy_static! {
-    static ref KEEPER: Pool<SqliteConnectionManager> = {
-        info!("Initializing database");
-        let path;
-        {//Ensure to only borrow config and release asap
-            let config = get_config();
-            path = config.database_path.to_string();
-        }
-        let manager = SqliteConnectionManager::file(path);
-        let pool = Pool::new(manager).expect("Failed to generate pool");
+/// Keeper schema version stored in `PRAGMA user_version`.
+///
+/// * `0` - `crypt.key_seed` holds plaintext keys.
+/// * `1` - `crypt.key_seed` holds keys wrapped under the master key.
+/// * `2` - `crypt.cipher` records the cipher of each file.
+/// * `3` - `crypt.key_mode` records the key mode of each file, `keeper_meta.secret` holds the
+///   secret derived keys come from.
+/// * `4` - `crypt.size` and `crypt.mtime` record the size and modification time of each file
+///   when it was encrypted, and `crypt.full_path` is indexed.
+const KEEPER_VERSION: i32 = 4;
+
+/// Associated data used to wrap the master key verifier.
+const VERIFIER_AAD: &[u8] = b"crypt keeper";
+
+/// Associated data used to wrap the identity secret key.
+const IDENTITY_AAD: &[u8] = b"crypt identity";
 
-        init_keeper(&pool.get().unwrap()).expect("Failed to initialize keeper");
+/// Associated data used to wrap the key secret.
+const SECRET_AAD: &[u8] = b"crypt secret";
 
-        pool
-    };
+/// Associated data used to seal zstd dictionaries.
+const DICTIONARY_AAD: &[u8] = b"crypt dictionary";
+
+/// SQL run on every new keeper connection. WAL lets readers work alongside the writer, and
+/// `synchronous = NORMAL` is durable in WAL mode while syncing far less often.
+const KEEPER_PRAGMAS: &str = "PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;";
+
+/// Opens the keeper at `path`, creating it if missing.
+///
+/// The connection pool holds up to one connection per worker thread, plus one for the keeper
+/// writer.
+pub(crate) fn open_keeper(path: &str) -> Result<Pool<SqliteConnectionManager>> {
+    info!("Initializing database");
+    let manager =
+        SqliteConnectionManager::file(path).with_init(|conn| conn.execute_batch(KEEPER_PRAGMAS));
+    let pool = Pool::builder()
+        .max_size(worker_count() as u32 + 1)
+        .min_idle(Some(1))
+        .build(manager)?;
+
+    init_keeper(&*pool.get()?)?;
+    Ok(pool)
 }
 
 ///Generates a connection to the database.
@@ -55,6 +86,44 @@ fn init_keeper(conn: &Connection) -> Result<()> {
         [],
     )?;
 
+    //Single row holding the master key parameters
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS keeper_meta (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            kdf BLOB NOT NULL,
+            verifier BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    //Single row holding our X25519 identity, the secret key wrapped under the master key
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS identity (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            public_key BLOB NOT NULL,
+            secret_key BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    //Trained zstd dictionaries, sealed under the master key
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS dictionary (
+            id INTEGER PRIMARY KEY,
+            data BLOB NOT NULL
+        )",
+        [],
+    )?;
+
+    //Public keys of people we encrypt files to
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS recipient (
+            name TEXT PRIMARY KEY,
+            public_key BLOB NOT NULL
+        )",
+        [],
+    )?;
+
     conn.execute(
         "CREATE TABLE IF NOT EXISTS user_token (
             service TEXT PRIMARY KEY,
@@ -68,474 +137,1187 @@ fn init_keeper(conn: &Connection) -> Result<()> {
     return Ok(());
 }
 
-/// Export data from the keeper and write it to a CSV file.
-///
-/// # Arguments
-///
-/// * `alt_path`: An optional alternative path where the CSV file should be saved.
-///
-/// # Returns
-///
-/// Returns a `Result` or `Error` indicating success or failure.
-pub fn export_keeper(alt_path: Option<&str>) -> Result<()> {
-    // https://rust-lang-nursery.github.io/rust-cookbook/encoding/csv.html
-
-    // Query keeper crypts
-    let db_crypts = query_keeper_crypt()?;
+/// `Vault::keeper_is_initialized` of the default vault.
+pub fn keeper_is_initialized() -> Result<bool> {
+    default_vault()?.keeper_is_initialized()
+}
 
-    // Create CSV writer
-    let mut wtr = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+/// `Vault::unlock_keeper` of the default vault.
+pub fn unlock_keeper(passphrase: &str) -> Result<()> {
+    default_vault()?.unlock_keeper(passphrase)
+}
 
-    // Serialize crypts to CSV
-    for crypt in db_crypts {
-        wtr.serialize(crypt)?;
+/// Upgrades the keeper schema to `KEEPER_VERSION`, wrapping plaintext keys under `master`.
+fn migrate_keeper(conn: &Connection, master: &[u8; KEY_SIZE]) -> Result<()> {
+    let version: i32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version >= KEEPER_VERSION {
+        return Ok(());
     }
 
-    // Get CSV data as bytes
-    let data = wtr.into_inner().map_err(|e| e.into_error())?;
+    info!(
+        "migrating keeper from version {} to {}",
+        version, KEEPER_VERSION
+    );
+    if version < 1 {
+        let rows: Vec<(String, Vec<u8>)> = conn
+            .prepare("SELECT uuid, key_seed FROM crypt")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
 
-    // get crypt dir "C:\\users\\USER\\crypt_config"
-    let path: PathBuf = match alt_path {
-        Some(p) => PathBuf::from_str(p)?,
-        None => {
-            let mut p = get_config_folder();
-            p.push("crypt_export.csv");
-            p
+        for (uuid, key) in rows {
+            let Ok(key) = <[u8; KEY_SIZE]>::try_from(key.as_slice()) else {
+                warning!(
+                    "not wrapping the key of {}: expected {} bytes, found {}",
+                    uuid,
+                    KEY_SIZE,
+                    key.len()
+                );
+                continue;
+            };
+            conn.execute(
+                "UPDATE crypt SET key_seed = ?1 WHERE uuid = ?2",
+                params![wrap_key(master, &key, uuid.as_bytes())?, uuid],
+            )?;
         }
-    };
+    }
 
-    info!("writing export to {}", &path.display());
+    if version < 2 {
+        // every file written so far used ChaCha20Poly1305
+        conn.execute(
+            "ALTER TABLE crypt ADD COLUMN cipher INTEGER NOT NULL DEFAULT 1",
+            [],
+        )?;
+    }
 
-    if let Some(ap) = alt_path {
-        write_contents_to_file(ap, data)?;
-    } else {
-        write_contents_to_file(path, data)?;
+    if version < 3 {
+        // every file written so far has a random key
+        conn.execute(
+            "ALTER TABLE crypt ADD COLUMN key_mode INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+        conn.execute("ALTER TABLE keeper_meta ADD COLUMN secret BLOB", [])?;
     }
+
+    if version < 4 {
+        // sizes and modification times are unknown until the files are encrypted again
+        conn.execute(
+            "ALTER TABLE crypt ADD COLUMN size INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+        conn.execute(
+            "ALTER TABLE crypt ADD COLUMN mtime INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS crypt_full_path ON crypt (full_path)",
+            [],
+        )?;
+    }
+
+    conn.pragma_update(None, "user_version", KEEPER_VERSION)?;
     return Ok(());
 }
 
-/// Imports csv into database. <b>WARNING</b>, overrides may occur!
-pub fn import_keeper(path: &String) -> Result<()> {
-    let mut rdr = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .from_path(path)?;
-
-    for result in rdr.records() {
-        let record: StringRecord = match result {
-            Ok(it) => it,
-            Err(err) => {
-                error!("Failed to convert csv to StringRecord!: {}", err);
-                continue;
-            } // TODO: Fix with more elegant handling.
-        };
-        let fc: FileCrypt = match record.deserialize(None) {
-            Ok(it) => it,
-            Err(err) => {
-                error!("Failed to convert StringRecord to FileCrypt!: {}", err);
-                FileCrypt::default()
-            } // TODO: Fix with more elegant handling.
+/// `Vault::key_secret` of the default vault.
+pub fn key_secret() -> Result<[u8; KEY_SIZE]> {
+    default_vault()?.key_secret()
+}
+
+/// `Vault::export_key_secret` of the default vault.
+pub fn export_key_secret() -> Result<String> {
+    default_vault()?.export_key_secret()
+}
+
+/// `Vault::restore_key_secret` of the default vault.
+pub fn restore_key_secret(secret: &str) -> Result<()> {
+    default_vault()?.restore_key_secret(secret)
+}
+
+/// `Vault::derived_key` of the default vault.
+pub fn derived_key(uuid: &str) -> Result<[u8; KEY_SIZE]> {
+    default_vault()?.derived_key(uuid)
+}
+
+/// `Vault::seal_file_name` of the default vault.
+pub fn seal_file_name(name: &str) -> Result<String> {
+    default_vault()?.seal_file_name(name)
+}
+
+/// `Vault::open_file_name` of the default vault.
+pub fn open_file_name(sealed: &str) -> Result<String> {
+    default_vault()?.open_file_name(sealed)
+}
+
+/// A `crypt` row as written by `export_keeper`. Keys stay wrapped under the master key and the
+/// binary fields are hex encoded. Derived files have an empty key.
+#[derive(Debug, Deserialize, Serialize)]
+struct ExportRecord {
+    uuid: String,
+    filename: String,
+    ext: String,
+    drive_id: String,
+    full_path: String,
+    key: String,
+    nonce: String,
+    hash: String,
+    cipher: u8,
+}
+
+impl ExportRecord {
+    /// Number of fields in a serialized record.
+    const FIELDS: usize = 9;
+
+    fn from_crypt(crypt: &FileCrypt, master: &[u8; KEY_SIZE]) -> Result<Self> {
+        Ok(Self {
+            uuid: crypt.uuid.clone(),
+            filename: crypt.filename.clone(),
+            ext: crypt.ext.clone(),
+            drive_id: crypt.drive_id.clone(),
+            full_path: crypt.full_path.display().to_string(),
+            key: match crypt.key_mode {
+                KeyMode::Random => {
+                    hex::encode(wrap_key(master, &crypt.key, crypt.uuid.as_bytes())?)
+                }
+                KeyMode::Derived => String::new(),
+            },
+            nonce: hex::encode(&crypt.nonce),
+            hash: hex::encode(crypt.hash),
+            cipher: crypt.cipher as u8,
+        })
+    }
+
+    fn into_crypt(self, vault: &Vault, master: &[u8; KEY_SIZE]) -> Result<FileCrypt> {
+        let invalid = |_| Error::DatabaseError(DatabaseError::InvalidExportRecord);
+        let wrapped = hex::decode(&self.key).map_err(invalid)?;
+        let nonce = hex::decode(&self.nonce).map_err(invalid)?;
+        let hash = hex::decode(&self.hash).map_err(invalid)?;
+        let cipher = Cipher::try_from(self.cipher)?;
+        if nonce.len() != cipher.nonce_size() {
+            return Err(Error::DatabaseError(DatabaseError::InvalidExportRecord));
+        }
+
+        let (key, key_mode) = if wrapped.is_empty() {
+            (vault.derived_key(&self.uuid)?, KeyMode::Derived)
+        } else {
+            (
+                unwrap_key(master, &wrapped, self.uuid.as_bytes())?,
+                KeyMode::Random,
+            )
         };
-        _ = insert_crypt(&fc);
+
+        Ok(FileCrypt {
+            key,
+            nonce,
+            cipher,
+            key_mode,
+            hash: hash
+                .try_into()
+                .map_err(|_| DatabaseError::InvalidExportRecord)?,
+            uuid: self.uuid,
+            filename: self.filename,
+            ext: self.ext,
+            drive_id: self.drive_id,
+            full_path: PathBuf::from(self.full_path),
+            size: 0,
+            mtime: 0,
+        })
+    }
+}
+
+/// Layout of exports written before keys were wrapped: a flattened `FileCrypt` with a
+/// plaintext key and a ChaCha20Poly1305 nonce.
+#[derive(Debug, Deserialize)]
+struct LegacyRecord {
+    uuid: String,
+    filename: String,
+    ext: String,
+    drive_id: String,
+    full_path: PathBuf,
+    key: [u8; KEY_SIZE],
+    nonce: [u8; NONCE_SIZE],
+    hash: [u8; KEY_SIZE],
+}
+
+impl From<LegacyRecord> for FileCrypt {
+    fn from(r: LegacyRecord) -> Self {
+        FileCrypt {
+            uuid: r.uuid,
+            filename: r.filename,
+            ext: r.ext,
+            drive_id: r.drive_id,
+            full_path: r.full_path,
+            key: r.key,
+            nonce: r.nonce.to_vec(),
+            cipher: Cipher::ChaCha20Poly1305,
+            key_mode: KeyMode::Random,
+            hash: r.hash,
+            size: 0,
+            mtime: 0,
+        }
     }
+}
 
-    return Ok(());
+/// `Vault::export_keeper` of the default vault.
+pub fn export_keeper(alt_path: Option<&str>) -> Result<()> {
+    default_vault()?.export_keeper(alt_path)
 }
 
-///Grabs the connection
-///
-/// # Example:
-///```ignore
-/// let conn = get_keeper()?;
-/// conn.execute("SELECT * FROM *");
-///```
+/// `Vault::import_keeper` of the default vault.
+pub fn import_keeper(path: &String) -> Result<()> {
+    default_vault()?.import_keeper(path)
+}
+
+/// `Vault::get_keeper` of the default vault.
 pub fn get_keeper() -> Result<r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>> {
-    //Returns the static connection
-    let keeper = KEEPER.get()?;
-    return Ok(keeper);
+    default_vault()?.get_keeper()
 }
 
-///Insert a crypt into the database
-///
-/// # Example:
-///```ignore
-/// let fc = FileCrypt::new({...});
-/// let _ = insert_crypt(&fc);
-///```
+/// `Vault::insert_crypt` of the default vault.
 pub fn insert_crypt(crypt: &FileCrypt) -> Result<()> {
-    //Get the connection
-    let conn = get_keeper()?;
-
-    //Create insert command and execute -- should handle uuid conflicts
-    conn.execute(
-        "INSERT INTO crypt (
-            uuid,
-            filename,
-            extension,
-            drive_id,
-            full_path,
-            key_seed,
-            nonce_seed,
-            hash
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
-        ON CONFLICT(uuid) DO UPDATE SET
-            filename = excluded.filename,
-            extension = excluded.extension,
-            drive_id = excluded.drive_id,
-            full_path = excluded.full_path,
-            key_seed = excluded.key_seed,
-            nonce_seed = excluded.nonce_seed,
-            hash = excluded.hash",
-        params![
-            &crypt.uuid,
-            &crypt.filename,
-            &crypt.ext,
-            &crypt.drive_id,
-            &crypt.full_path.to_str().unwrap_or_default(),
-            &crypt.key.as_ref(),
-            &crypt.nonce.as_ref(),
-            &crypt.hash.as_ref(),
-        ],
-    )?;
+    default_vault()?.insert_crypt(crypt)
+}
 
-    return Ok(());
+/// `Vault::insert_crypts` of the default vault.
+pub fn insert_crypts(crypts: &[FileCrypt]) -> Result<()> {
+    default_vault()?.insert_crypts(crypts)
 }
 
-///Inserts a token into the database
-///
-/// # Example:
-///```ignore
-/// let ut = UserToken::new({...});
-/// let _ = insert_token(&ut);
-///```
+/// `Vault::insert_token` of the default vault.
 pub fn insert_token(user_token: &UserToken) -> Result<()> {
-    //Get the connection
-    let conn = get_keeper()?;
+    default_vault()?.insert_token(user_token)
+}
 
-    //Create insert command and execute -- should handle uuid conflicts
-    conn.execute(
-        "INSERT INTO user_token (
-            service,
-            key_seed,
-            nonce_seed,
-            expiration
-        ) VALUES (?1, ?2, ?3, ?4)
-        ON CONFLICT(service) DO UPDATE SET
-            key_seed = excluded.key_seed,
-            nonce_seed = excluded.nonce_seed,
-            expiration = excluded.expiration",
-        params![
-            &user_token.service.to_string(),
-            &user_token.key_seed.as_ref(),
-            &user_token.nonce_seed.as_ref(),
-            &user_token.expiration,
-        ],
-    )?;
+/// `Vault::query_crypt` of the default vault.
+pub fn query_crypt(uuid: String) -> Result<FileCrypt> {
+    default_vault()?.query_crypt(uuid)
+}
 
-    return Ok(());
+/// `Vault::query_token` of the default vault.
+pub fn query_token(service: CloudService) -> Result<UserToken> {
+    default_vault()?.query_token(service)
 }
 
-///Queries the database for the crypt
-///
-/// # Example:
-///```ignore
-/// let uuid = generate_uuid();
-/// let fc = query_crypt(uuid);
-///```
-pub fn query_crypt(uuid: String) -> Result<FileCrypt> {
-    //Get the connection
-    let conn = get_keeper()?;
-
-    //Get the results of the query
-    let filecrypt = conn.query_row(
-        "SELECT *
-        FROM crypt
-        WHERE uuid = ?1",
-        params![uuid],
-        |row| {
-            let path: String = row.get(4)?;
-            Ok(FileCrypt {
-                uuid: row.get(0)?,
-                filename: row.get(1)?,
-                ext: row.get(2)?,
-                drive_id: row.get(3)?,
-                full_path: PathBuf::from(path),
-                key: row.get(5)?,
-                nonce: row.get(6)?,
-                hash: row.get(7)?,
-            })
-        },
-    )?;
+/// `Vault::query_keeper_for_existing_file` of the default vault.
+pub fn query_keeper_for_existing_file(full_path: PathBuf) -> Result<FileCrypt> {
+    default_vault()?.query_keeper_for_existing_file(full_path)
+}
 
-    return Ok(filecrypt);
+/// `Vault::query_keeper_by_file_name` of the default vault.
+pub fn query_keeper_by_file_name<T: AsRef<Path>>(file_name: &T) -> Result<FileCrypt> {
+    default_vault()?.query_keeper_by_file_name(file_name)
 }
 
-///Queries the database for the token
-///
-/// # Example:
-///```ignore
-/// let cs = CloudService::Google;
-/// let user_token = query_token(&cs);
-///```
-pub fn query_token(service: CloudService) -> Result<UserToken> {
-    //Get the connection
-    let conn = get_keeper()?;
-
-    //Get the results of the query
-    let token = conn.query_row(
-        "SELECT *
-        FROM user_token
-        WHERE service = ?1",
-        params![service.to_string()],
-        |row| {
-            let service: String = row.get(0)?;
-            let expiration: u64 = row.get(3)?;
-            Ok(UserToken {
-                service: CloudService::from_str(&service)
-                    .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?,
-                key_seed: row.get(1)?,
-                nonce_seed: row.get(2)?,
-                expiration,
-                access_token: String::new(),
-            })
-        },
-    )?;
+/// `Vault::query_keeper_for_files_with_drive_id` of the default vault.
+pub fn query_keeper_for_files_with_drive_id() -> Result<Vec<FileCrypt>> {
+    default_vault()?.query_keeper_for_files_with_drive_id()
+}
 
-    return Ok(token);
+/// `Vault::query_keeper_crypt` of the default vault.
+pub fn query_keeper_crypt() -> Result<Vec<FileCrypt>> {
+    default_vault()?.query_keeper_crypt()
 }
 
-///Queries the database if a file's metadata matches existing entry in crypt keeper
-///
-/// # Example:
-///```ignore
-/// let path = PathBuf::from("path/to/file.txt");
-/// let fc = insert_crypt(path);
-///```
-pub fn query_keeper_for_existing_file(full_path: PathBuf) -> Result<FileCrypt> {
-    //Get the connection
-    let conn = get_keeper()?;
-
-    //Get the results of the query
-    let filecrypt = conn.query_row(
-        "SELECT *
-        FROM crypt
-        WHERE full_path = ?1",
-        params![full_path.to_str().unwrap_or_default().to_string()],
-        |row| {
-            let path: String = row.get(4)?;
-            Ok(FileCrypt {
-                uuid: row.get(0)?,
-                filename: row.get(1)?,
-                ext: row.get(2)?,
-                drive_id: row.get(3)?,
-                full_path: PathBuf::from(path),
-                key: row.get(5)?,
-                nonce: row.get(6)?,
-                hash: row.get(7)?,
-            })
-        },
-    )?;
+/// `Vault::query_keeper_token` of the default vault.
+pub fn query_keeper_token() -> Result<Vec<UserToken>> {
+    default_vault()?.query_keeper_token()
+}
 
-    return Ok(filecrypt);
+/// `Vault::insert_identity` of the default vault.
+pub fn insert_identity(identity: &Identity) -> Result<()> {
+    default_vault()?.insert_identity(identity)
 }
 
-///Queries the database if a file's metadata matches existing entry in crypt keeper
-///
-/// # Example:
-///```ignore
-/// let path = PathBuf::from("path/to/file.txt");
-/// let fc = insert_crypt(path);
-///```
-pub fn query_keeper_by_file_name<T: AsRef<Path>>(file_name: &T) -> Result<FileCrypt> {
-    let file_name = file_name.as_ref();
-    //Get the connection
-    let conn = get_keeper()?;
-
-    //Get the results of the query
-    let filecrypt = conn.query_row(
-        "SELECT *
-        FROM crypt
-        WHERE filename = ?1",
-        params![file_name.display().to_string()],
-        |row| {
-            let get: String = row.get(4)?;
-            Ok(FileCrypt {
-                uuid: row.get(0)?,
-                filename: row.get(1)?,
-                ext: row.get(2)?,
-                drive_id: row.get(3)?,
-                full_path: PathBuf::from(get),
-                key: row.get(5)?,
-                nonce: row.get(6)?,
-                hash: row.get(7)?,
-            })
-        },
-    )?;
+/// `Vault::query_identity` of the default vault.
+pub fn query_identity() -> Result<Option<Identity>> {
+    default_vault()?.query_identity()
+}
 
-    return Ok(filecrypt);
+/// `Vault::insert_dictionary` of the default vault.
+pub fn insert_dictionary(dictionary: &[u8]) -> Result<u32> {
+    default_vault()?.insert_dictionary(dictionary)
 }
 
-/// Searches the Crypt for FileCrypts whose `drive_id` IS NOT NULL AND IS NOT "", and returns those results in a vector.
-pub fn query_keeper_for_files_with_drive_id() -> Result<Vec<FileCrypt>> {
-    //Get the connection
-    let conn = get_keeper()?;
-
-    //Create the query and execute
-    let mut query = conn.prepare(
-        r#"
-        SELECT *
-        FROM crypt
-        WHERE drive_id IS NOT NULL AND drive_id != "" "#,
-    )?;
+/// `Vault::query_dictionary` of the default vault.
+pub fn query_dictionary(id: u32) -> Result<Vec<u8>> {
+    default_vault()?.query_dictionary(id)
+}
+
+/// `Vault::insert_recipient` of the default vault.
+pub fn insert_recipient(name: &str, key: &RecipientKey) -> Result<()> {
+    default_vault()?.insert_recipient(name, key)
+}
+
+/// `Vault::query_recipient` of the default vault.
+pub fn query_recipient(name: &str) -> Result<RecipientKey> {
+    default_vault()?.query_recipient(name)
+}
+
+/// `Vault::query_keeper_recipients` of the default vault.
+pub fn query_keeper_recipients() -> Result<Vec<(String, RecipientKey)>> {
+    default_vault()?.query_keeper_recipients()
+}
 
-    //Get the results of the query
-    let query_result = query.query_map([], |row| {
+/// `Vault::delete_recipient` of the default vault.
+pub fn delete_recipient(name: &str) -> Result<()> {
+    default_vault()?.delete_recipient(name)
+}
+
+/// `Vault::delete_crypt` of the default vault.
+pub fn delete_crypt(uuid: String) -> Result<()> {
+    default_vault()?.delete_crypt(uuid)
+}
+
+/// `Vault::delete_keeper` of the default vault.
+pub fn delete_keeper() -> Result<()> {
+    default_vault()?.delete_keeper()
+}
+
+impl Vault {
+    /// Returns `true` once a keeper passphrase has been set, i.e. `unlock_keeper` has run at least
+    /// once against this database.
+    pub fn keeper_is_initialized(&self) -> Result<bool> {
+        let conn = self.get_keeper()?;
+        let count: i64 =
+            conn.query_row("SELECT COUNT(*) FROM keeper_meta", [], |row| row.get(0))?;
+        return Ok(count > 0);
+    }
+
+    /// Unlocks the keeper for this session.
+    ///
+    /// File keys in the `crypt` table are wrapped under a master key derived from `passphrase` with
+    /// Argon2id. The first call on a database sets the passphrase, and wraps any plaintext keys left
+    /// by older versions of crypt.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DatabaseError::WrongKeeperPassphrase` if the passphrase does not match the one the
+    /// keeper was set up with.
+    pub fn unlock_keeper(&self, passphrase: &str) -> Result<()> {
+        let mut conn = self.get_keeper()?;
+        let tx = conn.transaction()?;
+
+        let meta: Option<(Vec<u8>, Vec<u8>)> = tx
+            .query_row(
+                "SELECT kdf, verifier FROM keeper_meta WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        let master = match meta {
+            Some((kdf, verifier)) => {
+                let master = KdfParams::from_bytes(&kdf)?.derive_key(passphrase)?;
+                unwrap_key(&master, &verifier, VERIFIER_AAD)
+                    .map_err(|_| DatabaseError::WrongKeeperPassphrase)?;
+                master
+            }
+            None => {
+                info!("setting up keeper passphrase");
+                let params = KdfParams::generate();
+                let master = params.derive_key(passphrase)?;
+                // a wrapped random value, only used to tell a wrong passphrase apart
+                let (check, _) = generate_seeds();
+                tx.execute(
+                    "INSERT INTO keeper_meta (id, kdf, verifier) VALUES (1, ?1, ?2)",
+                    params![params.to_bytes(), wrap_key(&master, &check, VERIFIER_AAD)?],
+                )?;
+                master
+            }
+        };
+
+        migrate_keeper(&tx, &master)?;
+
+        let wrapped: Option<Vec<u8>> =
+            tx.query_row("SELECT secret FROM keeper_meta WHERE id = 1", [], |row| {
+                row.get(0)
+            })?;
+        let secret = match wrapped {
+            Some(wrapped) => unwrap_key(&master, &wrapped, SECRET_AAD)?,
+            None => {
+                let (secret, _) = generate_seeds();
+                tx.execute(
+                    "UPDATE keeper_meta SET secret = ?1 WHERE id = 1",
+                    params![wrap_key(&master, &secret, SECRET_AAD)?],
+                )?;
+                secret
+            }
+        };
+        tx.commit()?;
+
+        let mut keys = self.keys().write().expect("keeper keys lock poisoned");
+        keys.master = Some(master);
+        keys.secret = Some(secret);
+        return Ok(());
+    }
+
+    /// Gets the master key of the unlocked keeper.
+    fn master_key(&self) -> Result<[u8; KEY_SIZE]> {
+        let master = self
+            .keys()
+            .read()
+            .expect("keeper keys lock poisoned")
+            .master;
+        return master.ok_or(Error::DatabaseError(DatabaseError::KeeperLocked));
+    }
+
+    /// Gets the secret the keys of `KeyMode::Derived` files are derived from.
+    ///
+    /// Together with the file uuids, which every `.crypt` header carries, the secret recovers every
+    /// derived file, so backing it up is enough to survive the loss of the keeper.
+    pub fn key_secret(&self) -> Result<[u8; KEY_SIZE]> {
+        let secret = self
+            .keys()
+            .read()
+            .expect("keeper keys lock poisoned")
+            .secret;
+        return secret.ok_or(Error::DatabaseError(DatabaseError::KeeperLocked));
+    }
+
+    /// Gets the key secret hex encoded, for backing it up.
+    pub fn export_key_secret(&self) -> Result<String> {
+        return Ok(hex::encode(self.key_secret()?));
+    }
+
+    /// Replaces the key secret with a hex encoded backup from `export_key_secret`, e.g. the secret
+    /// of a lost keeper.
+    ///
+    /// Derived files encrypted under the previous secret can no longer be decrypted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DatabaseError::InvalidKeySecret` if `secret` is not a hex encoded 32 byte secret.
+    pub fn restore_key_secret(&self, secret: &str) -> Result<()> {
+        let secret: [u8; KEY_SIZE] = hex::decode(secret.trim())
+            .ok()
+            .and_then(|s| s.try_into().ok())
+            .ok_or(DatabaseError::InvalidKeySecret)?;
+
+        let conn = self.get_keeper()?;
+        conn.execute(
+            "UPDATE keeper_meta SET secret = ?1 WHERE id = 1",
+            params![wrap_key(&self.master_key()?, &secret, SECRET_AAD)?],
+        )?;
+
+        self.keys()
+            .write()
+            .expect("keeper keys lock poisoned")
+            .secret = Some(secret);
+        return Ok(());
+    }
+
+    /// Gets the key of the `KeyMode::Derived` file `uuid`.
+    pub fn derived_key(&self, uuid: &str) -> Result<[u8; KEY_SIZE]> {
+        return Ok(derive_file_key(&self.key_secret()?, uuid));
+    }
+
+    /// Encrypts a file name under the master key, hex encoded so it can be used as a file name.
+    pub fn seal_file_name(&self, name: &str) -> Result<String> {
+        return Ok(hex::encode(seal_name(&self.master_key()?, name)?));
+    }
+
+    /// Decrypts a file name produced by `seal_file_name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EncryptionError::AeadError` if `sealed` was not sealed by this keeper.
+    pub fn open_file_name(&self, sealed: &str) -> Result<String> {
+        let sealed =
+            hex::decode(sealed).map_err(|_| Error::EncryptionError(EncryptionError::AeadError))?;
+        return open_name(&self.master_key()?, &sealed);
+    }
+
+    /// Builds a `FileCrypt` from a `SELECT * FROM crypt` row, unwrapping its key with `master` or
+    /// deriving it from the key secret.
+    fn crypt_from_row(&self, row: &Row, master: &[u8; KEY_SIZE]) -> rusqlite::Result<FileCrypt> {
+        let uuid: String = row.get(0)?;
         let path: String = row.get(4)?;
-        let key: [u8; KEY_SIZE] = row.get(5)?;
-        let nonce: [u8; NONCE_SIZE] = row.get(6)?;
-        let hash: [u8; KEY_SIZE] = row.get(7)?;
+        let wrapped: Vec<u8> = row.get(5)?;
+        let cipher = Cipher::try_from(row.get::<_, u8>(8)?)
+            .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?;
+        let key_mode = KeyMode::try_from(row.get::<_, u8>(9)?)
+            .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?;
+        let key = match key_mode {
+            KeyMode::Random => unwrap_key(master, &wrapped, uuid.as_bytes()),
+            KeyMode::Derived => self.derived_key(&uuid),
+        }
+        .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?;
 
         Ok(FileCrypt {
-            uuid: row.get(0)?,
+            uuid,
             filename: row.get(1)?,
             ext: row.get(2)?,
             drive_id: row.get(3)?,
             full_path: PathBuf::from(path),
             key,
-            nonce,
-            hash,
+            nonce: row.get(6)?,
+            cipher,
+            key_mode,
+            hash: row.get(7)?,
+            size: row.get(10)?,
+            mtime: row.get(11)?,
         })
-    })?;
+    }
+
+    /// Export data from the keeper and write it to a CSV file.
+    ///
+    /// File keys are written wrapped under the master key, so the export can only be imported into
+    /// a keeper unlocked with the same master key.
+    ///
+    /// # Arguments
+    ///
+    /// * `alt_path`: An optional alternative path where the CSV file should be saved.
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` or `Error` indicating success or failure.
+    pub fn export_keeper(&self, alt_path: Option<&str>) -> Result<()> {
+        // https://rust-lang-nursery.github.io/rust-cookbook/encoding/csv.html
+
+        // Query keeper crypts
+        let db_crypts = self.query_keeper_crypt()?;
+        let master = self.master_key()?;
 
-    //Convert the results into a vector
-    let mut crypts = vec![];
-    for crypt in query_result.into_iter() {
-        crypts.push(crypt?);
+        // Create CSV writer
+        let mut wtr = WriterBuilder::new().has_headers(false).from_writer(vec![]);
+
+        // Serialize crypts to CSV
+        for crypt in db_crypts {
+            wtr.serialize(ExportRecord::from_crypt(&crypt, &master)?)?;
+        }
+
+        // Get CSV data as bytes
+        let data = wtr.into_inner().map_err(|e| e.into_error())?;
+
+        // get crypt dir "C:\\users\\USER\\crypt_config"
+        let path: PathBuf = match alt_path {
+            Some(p) => PathBuf::from_str(p)?,
+            None => {
+                let mut p = get_config_folder();
+                p.push("crypt_export.csv");
+                p
+            }
+        };
+
+        info!("writing export to {}", &path.display());
+
+        if let Some(ap) = alt_path {
+            write_contents_to_file(ap, data)?;
+        } else {
+            write_contents_to_file(path, data)?;
+        }
+        return Ok(());
     }
 
-    return Ok(crypts);
-}
+    /// Imports csv into database. <b>WARNING</b>, overrides may occur!
+    ///
+    /// Accepts exports from `export_keeper` as well as older exports with plaintext keys, which are
+    /// wrapped on insert.
+    pub fn import_keeper(&self, path: &String) -> Result<()> {
+        let master = self.master_key()?;
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_path(path)?;
 
-///Queries the database for all crypts
-///
-/// # Example:
-///```ignore
-/// let fc = FileCrypt::new({...});
-/// let _ = insert_crypt(&fc);
-///```
-pub fn query_keeper_crypt() -> Result<Vec<FileCrypt>> {
-    //Get the connection
-    let conn = get_keeper()?;
-
-    //Create the query and execute
-    let mut query = conn.prepare(
-        "
-        SELECT *
-        FROM crypt",
-    )?;
+        for result in rdr.records() {
+            let record: StringRecord = match result {
+                Ok(it) => it,
+                Err(err) => {
+                    error!("Failed to convert csv to StringRecord!: {}", err);
+                    continue;
+                } // TODO: Fix with more elegant handling.
+            };
+            let fc: FileCrypt = if record.len() == ExportRecord::FIELDS {
+                match record
+                    .deserialize::<ExportRecord>(None)
+                    .map_err(Error::from)
+                    .and_then(|r| r.into_crypt(self, &master))
+                {
+                    Ok(it) => it,
+                    Err(err) => {
+                        error!("Failed to import exported crypt!: {}", err);
+                        continue;
+                    }
+                }
+            } else {
+                match record.deserialize::<LegacyRecord>(None) {
+                    Ok(it) => it.into(),
+                    Err(err) => {
+                        error!("Failed to convert StringRecord to FileCrypt!: {}", err);
+                        FileCrypt::default()
+                    } // TODO: Fix with more elegant handling.
+                }
+            };
+            _ = self.insert_crypt(&fc);
+        }
 
-    //Get the results of the query
-    let query_result = query.query_map([], |row| {
-        let get: String = row.get(4)?;
-        let key: [u8; KEY_SIZE] = row.get(5)?;
-        let nonce: [u8; NONCE_SIZE] = row.get(6)?;
-        let hash: [u8; KEY_SIZE] = row.get(7)?;
+        return Ok(());
+    }
 
-        Ok(FileCrypt {
-            uuid: row.get(0)?,
-            filename: row.get(1)?,
-            ext: row.get(2)?,
-            drive_id: row.get(3)?,
-            full_path: PathBuf::from(get),
-            key,
-            nonce,
-            hash,
-        })
-    })?;
+    ///Grabs the connection
+    ///
+    /// # Example:
+    ///```ignore
+    /// let conn = get_keeper()?;
+    /// conn.execute("SELECT * FROM *");
+    ///```
+    pub fn get_keeper(
+        &self,
+    ) -> Result<r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>> {
+        //Returns a connection of the vault
+        let keeper = self.keeper()?.get()?;
+        return Ok(keeper);
+    }
+
+    ///Insert a crypt into the database
+    ///
+    /// # Example:
+    ///```ignore
+    /// let fc = FileCrypt::new({...});
+    /// let _ = insert_crypt(&fc);
+    ///```
+    pub fn insert_crypt(&self, crypt: &FileCrypt) -> Result<()> {
+        //Get the connection
+        let conn = self.get_keeper()?;
+        self.upsert_crypt(&conn, crypt)?;
 
-    //Convert the results into a vector
-    let mut crypts = vec![];
-    for crypt in query_result.into_iter() {
-        crypts.push(crypt?);
+        return Ok(());
     }
 
-    return Ok(crypts);
-}
+    ///Inserts crypts into the database in a single transaction, much faster than calling
+    ///`insert_crypt` for each of them. Nothing is inserted if any of them fails.
+    ///
+    /// # Example:
+    ///```ignore
+    /// let crypts = vec![FileCrypt::new({...}), FileCrypt::new({...})];
+    /// let _ = insert_crypts(&crypts);
+    ///```
+    pub fn insert_crypts(&self, crypts: &[FileCrypt]) -> Result<()> {
+        let mut conn = self.get_keeper()?;
+        let tx = conn.transaction()?;
+        for crypt in crypts {
+            self.upsert_crypt(&tx, crypt)?;
+        }
+        tx.commit()?;
 
-///Queries the database for all tokens
-// /
-// / # Example:
-// /```ignore
-// / let fc = FileCrypt::new({...});
-// / let _ = insert_crypt(&fc);
-// /```
-pub fn query_keeper_token() -> Result<Vec<UserToken>> {
-    //Get the connection
-    let conn = get_keeper()?;
-
-    //Create the query and execute
-    let mut query = conn.prepare(
-        "
-        SELECT *
-        FROM user_token",
-    )?;
+        return Ok(());
+    }
 
-    //Get the results of the query
-    let query_result = query.query_map([], |row| {
-        let service: String = row.get(0)?;
-        let key: [u8; KEY_SIZE] = row.get(1)?;
-        let nonce: [u8; NONCE_SIZE] = row.get(2)?;
-
-        Ok(UserToken {
-            service: CloudService::from_str(&service)
-                .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?,
-            key_seed: key,
-            nonce_seed: nonce,
-            expiration: row.get(3)?,
-            access_token: String::new(),
-        })
-    })?;
+    /// Inserts or updates the row of `crypt` using `conn`.
+    fn upsert_crypt(&self, conn: &Connection, crypt: &FileCrypt) -> Result<()> {
+        // never store the file key in the clear, derived keys are not stored at all
+        let key = match crypt.key_mode {
+            KeyMode::Random => wrap_key(&self.master_key()?, &crypt.key, crypt.uuid.as_bytes())?,
+            KeyMode::Derived => Vec::new(),
+        };
 
-    //Convert the results into a vector
-    let mut tokens = vec![];
-    for token in query_result.into_iter() {
-        tokens.push(token?);
+        //Create insert command and execute -- should handle uuid conflicts
+        conn.prepare_cached(
+            "INSERT INTO crypt (
+                uuid,
+                filename,
+                extension,
+                drive_id,
+                full_path,
+                key_seed,
+                nonce_seed,
+                hash,
+                cipher,
+                key_mode,
+                size,
+                mtime
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            ON CONFLICT(uuid) DO UPDATE SET
+                filename = excluded.filename,
+                extension = excluded.extension,
+                drive_id = excluded.drive_id,
+                full_path = excluded.full_path,
+                key_seed = excluded.key_seed,
+                nonce_seed = excluded.nonce_seed,
+                hash = excluded.hash,
+                cipher = excluded.cipher,
+                key_mode = excluded.key_mode,
+                size = excluded.size,
+                mtime = excluded.mtime",
+        )?
+        .execute(params![
+            &crypt.uuid,
+            &crypt.filename,
+            &crypt.ext,
+            &crypt.drive_id,
+            &crypt.full_path.to_str().unwrap_or_default(),
+            &key,
+            &crypt.nonce,
+            &crypt.hash.as_ref(),
+            &(crypt.cipher as u8),
+            &(crypt.key_mode as u8),
+            &crypt.size,
+            &crypt.mtime,
+        ])?;
+
+        return Ok(());
     }
 
-    return Ok(tokens);
-}
+    ///Inserts a token into the database
+    ///
+    /// # Example:
+    ///```ignore
+    /// let ut = UserToken::new({...});
+    /// let _ = insert_token(&ut);
+    ///```
+    pub fn insert_token(&self, user_token: &UserToken) -> Result<()> {
+        //Get the connection
+        let conn = self.get_keeper()?;
 
-///Deletes the crypt
-///
-///
-pub fn delete_crypt(uuid: String) -> Result<()> {
-    //Get the connection
-    let conn = get_keeper()?;
+        //Create insert command and execute -- should handle uuid conflicts
+        conn.execute(
+            "INSERT INTO user_token (
+                service,
+                key_seed,
+                nonce_seed,
+                expiration
+            ) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(service) DO UPDATE SET
+                key_seed = excluded.key_seed,
+                nonce_seed = excluded.nonce_seed,
+                expiration = excluded.expiration",
+            params![
+                &user_token.service.to_string(),
+                &user_token.key_seed.as_ref(),
+                &user_token.nonce_seed.as_ref(),
+                &user_token.expiration,
+            ],
+        )?;
 
-    conn.execute(
-        "
-            DELETE FROM crypt WHERE uuid = ?
-        ",
-        params![uuid],
-    )?;
+        return Ok(());
+    }
 
-    Ok(())
-}
+    ///Queries the database for the crypt
+    ///
+    /// # Example:
+    ///```ignore
+    /// let uuid = generate_uuid();
+    /// let fc = query_crypt(uuid);
+    ///```
+    pub fn query_crypt(&self, uuid: String) -> Result<FileCrypt> {
+        //Get the connection
+        let conn = self.get_keeper()?;
+        let master = self.master_key()?;
 
-///Delete the database
-// /
-// / # Example:
-// /```ignore
-// / let fc = FileCrypt::new({...});
-// / let _ = insert_crypt(&fc);
-// /```
-pub fn delete_keeper() -> Result<()> {
-    let path;
-    {
-        let config = get_config();
-        path = config.database_path.to_string();
+        //Get the results of the query
+        let filecrypt = conn.query_row(
+            "SELECT *
+            FROM crypt
+            WHERE uuid = ?1",
+            params![uuid],
+            |row| self.crypt_from_row(row, &master),
+        )?;
+
+        return Ok(filecrypt);
+    }
+
+    ///Queries the database for the token
+    ///
+    /// # Example:
+    ///```ignore
+    /// let cs = CloudService::Google;
+    /// let user_token = query_token(&cs);
+    ///```
+    pub fn query_token(&self, service: CloudService) -> Result<UserToken> {
+        //Get the connection
+        let conn = self.get_keeper()?;
+
+        //Get the results of the query
+        let token = conn.query_row(
+            "SELECT *
+            FROM user_token
+            WHERE service = ?1",
+            params![service.to_string()],
+            |row| {
+                let service: String = row.get(0)?;
+                let expiration: u64 = row.get(3)?;
+                Ok(UserToken {
+                    service: CloudService::from_str(&service)
+                        .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?,
+                    key_seed: row.get(1)?,
+                    nonce_seed: row.get(2)?,
+                    expiration,
+                    access_token: String::new(),
+                })
+            },
+        )?;
+
+        return Ok(token);
+    }
+
+    ///Queries the database if a file's metadata matches existing entry in crypt keeper
+    ///
+    /// # Example:
+    ///```ignore
+    /// let path = PathBuf::from("path/to/file.txt");
+    /// let fc = insert_crypt(path);
+    ///```
+    pub fn query_keeper_for_existing_file(&self, full_path: PathBuf) -> Result<FileCrypt> {
+        //Get the connection
+        let conn = self.get_keeper()?;
+        let master = self.master_key()?;
+
+        //Get the results of the query
+        let filecrypt = conn.query_row(
+            "SELECT *
+            FROM crypt
+            WHERE full_path = ?1",
+            params![full_path.to_str().unwrap_or_default().to_string()],
+            |row| self.crypt_from_row(row, &master),
+        )?;
+
+        return Ok(filecrypt);
+    }
+
+    ///Queries the database if a file's metadata matches existing entry in crypt keeper
+    ///
+    /// # Example:
+    ///```ignore
+    /// let path = PathBuf::from("path/to/file.txt");
+    /// let fc = insert_crypt(path);
+    ///```
+    pub fn query_keeper_by_file_name<T: AsRef<Path>>(&self, file_name: &T) -> Result<FileCrypt> {
+        let file_name = file_name.as_ref();
+        //Get the connection
+        let conn = self.get_keeper()?;
+        let master = self.master_key()?;
+
+        //Get the results of the query
+        let filecrypt = conn.query_row(
+            "SELECT *
+            FROM crypt
+            WHERE filename = ?1",
+            params![file_name.display().to_string()],
+            |row| self.crypt_from_row(row, &master),
+        )?;
+
+        return Ok(filecrypt);
+    }
+
+    /// Searches the Crypt for FileCrypts whose `drive_id` IS NOT NULL AND IS NOT "", and returns those results in a vector.
+    pub fn query_keeper_for_files_with_drive_id(&self) -> Result<Vec<FileCrypt>> {
+        //Get the connection
+        let conn = self.get_keeper()?;
+        let master = self.master_key()?;
+
+        //Create the query and execute
+        let mut query = conn.prepare(
+            r#"
+            SELECT *
+            FROM crypt
+            WHERE drive_id IS NOT NULL AND drive_id != "" "#,
+        )?;
+
+        //Get the results of the query
+        let query_result = query.query_map([], |row| self.crypt_from_row(row, &master))?;
+
+        //Convert the results into a vector
+        let mut crypts = vec![];
+        for crypt in query_result.into_iter() {
+            crypts.push(crypt?);
+        }
+
+        return Ok(crypts);
+    }
+
+    ///Queries the database for all crypts
+    ///
+    /// # Example:
+    ///```ignore
+    /// let fc = FileCrypt::new({...});
+    /// let _ = insert_crypt(&fc);
+    ///```
+    pub fn query_keeper_crypt(&self) -> Result<Vec<FileCrypt>> {
+        //Get the connection
+        let conn = self.get_keeper()?;
+        let master = self.master_key()?;
+
+        //Create the query and execute
+        let mut query = conn.prepare(
+            "
+            SELECT *
+            FROM crypt",
+        )?;
+
+        //Get the results of the query
+        let query_result = query.query_map([], |row| self.crypt_from_row(row, &master))?;
+
+        //Convert the results into a vector
+        let mut crypts = vec![];
+        for crypt in query_result.into_iter() {
+            crypts.push(crypt?);
+        }
+
+        return Ok(crypts);
+    }
+
+    ///Queries the database for all tokens
+    // /
+    // / # Example:
+    // /```ignore
+    // / let fc = FileCrypt::new({...});
+    // / let _ = insert_crypt(&fc);
+    // /```
+    pub fn query_keeper_token(&self) -> Result<Vec<UserToken>> {
+        //Get the connection
+        let conn = self.get_keeper()?;
+
+        //Create the query and execute
+        let mut query = conn.prepare(
+            "
+            SELECT *
+            FROM user_token",
+        )?;
+
+        //Get the results of the query
+        let query_result = query.query_map([], |row| {
+            let service: String = row.get(0)?;
+            let key: [u8; KEY_SIZE] = row.get(1)?;
+            let nonce: [u8; NONCE_SIZE] = row.get(2)?;
+
+            Ok(UserToken {
+                service: CloudService::from_str(&service)
+                    .map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?,
+                key_seed: key,
+                nonce_seed: nonce,
+                expiration: row.get(3)?,
+                access_token: String::new(),
+            })
+        })?;
+
+        //Convert the results into a vector
+        let mut tokens = vec![];
+        for token in query_result.into_iter() {
+            tokens.push(token?);
+        }
+
+        return Ok(tokens);
+    }
+
+    ///Stores our identity, replacing any previous one.
+    ///
+    /// Files encrypted to the previous identity can no longer be decrypted.
+    pub fn insert_identity(&self, identity: &Identity) -> Result<()> {
+        let conn = self.get_keeper()?;
+
+        // the secret key is wrapped like file keys
+        let secret = wrap_key(&self.master_key()?, &identity.to_bytes(), IDENTITY_AAD)?;
+
+        conn.execute(
+            "INSERT INTO identity (id, public_key, secret_key) VALUES (1, ?1, ?2)
+            ON CONFLICT(id) DO UPDATE SET
+                public_key = excluded.public_key,
+                secret_key = excluded.secret_key",
+            params![identity.recipient().as_bytes().as_ref(), &secret],
+        )?;
+
+        return Ok(());
+    }
+
+    ///Queries the database for our identity, `None` if one was never generated.
+    pub fn query_identity(&self) -> Result<Option<Identity>> {
+        let conn = self.get_keeper()?;
+        let master = self.master_key()?;
+
+        let secret: Option<Vec<u8>> = conn
+            .query_row("SELECT secret_key FROM identity WHERE id = 1", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+
+        return secret
+            .map(|secret| unwrap_key(&master, &secret, IDENTITY_AAD).map(Identity::from_bytes))
+            .transpose();
+    }
+
+    ///Stores a trained zstd dictionary, sealed under the master key.
+    ///
+    /// # Returns
+    ///
+    /// The id files compressed with the dictionary refer to it by.
+    pub fn insert_dictionary(&self, dictionary: &[u8]) -> Result<u32> {
+        let conn = self.get_keeper()?;
+
+        conn.execute(
+            "INSERT INTO dictionary (data) VALUES (?1)",
+            params![seal_blob(&self.master_key()?, dictionary, DICTIONARY_AAD)?],
+        )?;
+
+        return Ok(conn.last_insert_rowid() as u32);
+    }
+
+    ///Queries the database for the zstd dictionary with the given id.
+    pub fn query_dictionary(&self, id: u32) -> Result<Vec<u8>> {
+        let conn = self.get_keeper()?;
+
+        let sealed: Vec<u8> = conn.query_row(
+            "SELECT data FROM dictionary WHERE id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+
+        return open_blob(&self.master_key()?, &sealed, DICTIONARY_AAD);
+    }
+
+    ///Registers the public key of a recipient under `name`, replacing any key with the same name.
+    pub fn insert_recipient(&self, name: &str, key: &RecipientKey) -> Result<()> {
+        let conn = self.get_keeper()?;
+
+        conn.execute(
+            "INSERT INTO recipient (name, public_key) VALUES (?1, ?2)
+            ON CONFLICT(name) DO UPDATE SET public_key = excluded.public_key",
+            params![name, key.as_bytes().as_ref()],
+        )?;
+
+        return Ok(());
+    }
+
+    ///Queries the database for the public key registered under `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DatabaseError::UnknownRecipient` if no recipient is registered under `name`.
+    pub fn query_recipient(&self, name: &str) -> Result<RecipientKey> {
+        let conn = self.get_keeper()?;
+
+        let key: Option<[u8; X25519_KEY_SIZE]> = conn
+            .query_row(
+                "SELECT public_key FROM recipient WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        return key
+            .map(RecipientKey::from_bytes)
+            .ok_or(Error::DatabaseError(DatabaseError::UnknownRecipient(
+                name.to_string(),
+            )));
     }
-    if Path::new(&path).exists() {
-        fs::remove_file(path)?;
+
+    ///Queries the database for all recipients, sorted by name.
+    pub fn query_keeper_recipients(&self) -> Result<Vec<(String, RecipientKey)>> {
+        let conn = self.get_keeper()?;
+
+        let mut query = conn.prepare("SELECT name, public_key FROM recipient ORDER BY name")?;
+        let query_result = query.query_map([], |row| {
+            let key: [u8; X25519_KEY_SIZE] = row.get(1)?;
+            Ok((row.get(0)?, RecipientKey::from_bytes(key)))
+        })?;
+
+        let mut recipients = vec![];
+        for recipient in query_result.into_iter() {
+            recipients.push(recipient?);
+        }
+
+        return Ok(recipients);
+    }
+
+    ///Deletes the recipient registered under `name`.
+    pub fn delete_recipient(&self, name: &str) -> Result<()> {
+        let conn = self.get_keeper()?;
+
+        let deleted = conn.execute("DELETE FROM recipient WHERE name = ?1", params![name])?;
+        if deleted == 0 {
+            return Err(Error::DatabaseError(DatabaseError::UnknownRecipient(
+                name.to_string(),
+            )));
+        }
+
+        Ok(())
+    }
+
+    ///Deletes the crypt
+    ///
+    ///
+    pub fn delete_crypt(&self, uuid: String) -> Result<()> {
+        //Get the connection
+        let conn = self.get_keeper()?;
+
+        conn.execute(
+            "
+                DELETE FROM crypt WHERE uuid = ?
+            ",
+            params![uuid],
+        )?;
+
+        Ok(())
+    }
+
+    ///Delete the database
+    // /
+    // / # Example:
+    // /```ignore
+    // / let fc = FileCrypt::new({...});
+    // / let _ = insert_crypt(&fc);
+    // /```
+    pub fn delete_keeper(&self) -> Result<()> {
+        let path;
+        {
+            let config = self.config();
+            path = config.database_path.to_string();
+        }
+        if Path::new(&path).exists() {
+            fs::remove_file(&path)?;
+        }
+        // WAL mode side files
+        for suffix in ["-wal", "-shm"] {
+            let side = format!("{}{}", path, suffix);
+            if Path::new(&side).exists() {
+                fs::remove_file(side)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{encryption::WRAPPED_KEY_SIZE, vault::TempVault};
+
+    #[test]
+    fn test_migrate_plaintext_keys() {
+        let vault = TempVault::new("migrate");
+        // a keeper left by a version of crypt storing plaintext keys
+        let (key, _) = generate_seeds();
+        let pool = vault.keeper().unwrap();
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO crypt (uuid, filename, extension, drive_id, full_path, key_seed,
+                    nonce_seed, hash) VALUES ('old', 'a', '.txt', '', '/a.txt', ?1, ?2, ?3)",
+                params![key.to_vec(), vec![1u8; 12], vec![2u8; KEY_SIZE]],
+            )
+            .unwrap();
+
+        vault.unlock_keeper("passphrase").unwrap();
+
+        let conn = pool.get().unwrap();
+        let version: i32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, KEEPER_VERSION);
+        let stored: Vec<u8> = conn
+            .query_row("SELECT key_seed FROM crypt WHERE uuid = 'old'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(stored.len(), WRAPPED_KEY_SIZE);
+        assert_ne!(&stored[..KEY_SIZE], key.as_slice());
+
+        let fc = vault.query_crypt("old".to_string()).unwrap();
+        assert_eq!(fc.key, key);
+        assert_eq!(fc.cipher, Cipher::ChaCha20Poly1305);
+        assert_eq!(fc.key_mode, KeyMode::Random);
+
+        // unlocking again leaves the wrapped keys alone
+        vault.unlock_keeper("passphrase").unwrap();
+        assert_eq!(vault.query_crypt("old".to_string()).unwrap().key, key);
+        assert!(vault.unlock_keeper("wrong").is_err());
     }
-    Ok(())
 }
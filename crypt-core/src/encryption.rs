@@ -1,14 +1,50 @@
-use crate::{error, filecrypt::FileCrypt, prelude::*};
+use crate::{
+    cipher::{StreamDecryptor, StreamEncryptor},
+    error,
+    filecrypt::FileCrypt,
+    header::Compression,
+    padding::{PadWriter, Padding, UnpadReader},
+    prelude::*,
+};
 use blake2::{Blake2s256, Digest, *};
 use chacha20poly1305::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
     ChaCha20Poly1305, Key, Nonce,
 };
+use hkdf::Hkdf;
 use logfather::*;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+    str::FromStr,
+};
 
 pub const KEY_SIZE: usize = 32;
 pub const NONCE_SIZE: usize = 12;
 
+/// Size of each plaintext chunk in a streamed `.crypt` payload.
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Size of the authentication tag appended to every chunk.
+pub const TAG_SIZE: usize = 16;
+
+/// Size of the random salt used when deriving a key from a passphrase.
+pub const SALT_SIZE: usize = 16;
+
+/// Size of a key wrapped with `wrap_key`: nonce, encrypted key and tag.
+pub const WRAPPED_KEY_SIZE: usize = NONCE_SIZE + KEY_SIZE + TAG_SIZE;
+
+/// Associated data used to seal file names with `seal_name`.
+const NAME_AAD: &[u8] = b"crypt name";
+
+/// HKDF info prefix used by `derive_file_key`, followed by the file uuid.
+const FILE_KEY_INFO: &[u8] = b"crypt file key ";
+
 /// Computes a 256-bit BLAKE2s hash for the given byte slice contents.
 ///
 /// # Arguments
@@ -29,6 +65,15 @@ pub fn compute_hash(contents: &[u8]) -> [u8; 32] {
     hasher.finalize().into()
 }
 
+/// Computes the BLAKE2s hash of the file at `path` like `compute_hash`, without reading it into
+/// memory.
+pub fn compute_file_hash<T: AsRef<Path>>(path: T) -> Result<[u8; 32]> {
+    let mut hasher = Blake2s256::new();
+    let mut reader = BufReader::new(File::open(path)?);
+    copy_hashed(&mut reader, &mut io::sink(), &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
 /// compress is the Zstd compression algorithm <https://en.wikipedia.org/wiki/Zstd> to deflate file size
 /// prior to encryption.
 ///
@@ -48,6 +93,66 @@ pub fn compress(contents: &[u8], level: i32) -> Vec<u8> {
     zstd::encode_all(contents, level).expect("failed to zip contents")
 }
 
+/// Maximum size of a dictionary built by `train_dictionary`, the zstd default.
+pub const DICTIONARY_SIZE: usize = 110 * 1024;
+
+/// Trains a zstd dictionary on `samples`, typically the contents of many small files of the same
+/// kind. Compressing such files with the dictionary gains what each file is too small to gain
+/// on its own.
+///
+/// # Errors
+///
+/// Returns an `io::Error` if zstd cannot train on the samples, e.g. when there are too few.
+pub fn train_dictionary(samples: &[Vec<u8>]) -> Result<Vec<u8>> {
+    Ok(zstd::dict::from_samples(samples, DICTIONARY_SIZE)?)
+}
+
+/// Magic bytes of formats that are already compressed, with the offset they start at.
+const COMPRESSED_MAGIC: &[(usize, &[u8])] = &[
+    (0, b"\xFF\xD8\xFF"),       // jpeg
+    (0, b"\x89PNG"),            // png
+    (0, b"GIF8"),               // gif
+    (8, b"WEBP"),               // webp
+    (4, b"ftyp"),               // mp4, mov, heic
+    (0, b"\x1A\x45\xDF\xA3"),   // mkv, webm
+    (0, b"ID3"),                // mp3
+    (0, b"OggS"),               // ogg, opus
+    (0, b"fLaC"),               // flac
+    (0, b"PK\x03\x04"),         // zip, docx, jar, apk
+    (0, b"\x1F\x8B"),           // gzip
+    (0, b"\x28\xB5\x2F\xFD"),   // zstd
+    (0, b"\xFD7zXZ\x00"),       // xz
+    (0, b"BZh"),                // bzip2
+    (0, b"7z\xBC\xAF\x27\x1C"), // 7z
+    (0, b"Rar!\x1A\x07"),       // rar
+];
+
+/// Ratio (in percent) `compress` must reach on a sample for `is_compressible` to keep
+/// compression on.
+const COMPRESSIBLE_RATIO: usize = 95;
+
+/// Returns `true` if `sample`, the first bytes of a file, looks worth compressing.
+///
+/// Known compressed formats are recognized by their magic bytes, anything else is trial
+/// compressed at the fastest level, with `dictionary` if given, and has to shrink below
+/// `COMPRESSIBLE_RATIO` percent.
+pub fn is_compressible(sample: &[u8], dictionary: Option<&[u8]>) -> bool {
+    let is_compressed_format = COMPRESSED_MAGIC
+        .iter()
+        .any(|(offset, magic)| sample.get(*offset..offset + magic.len()) == Some(*magic));
+    if is_compressed_format {
+        return false;
+    }
+    let compressed = match dictionary {
+        Some(dictionary) => zstd::bulk::Compressor::with_dictionary(1, dictionary)
+            .and_then(|mut c| c.compress(sample))
+            .map(|c| c.len())
+            .unwrap_or(usize::MAX),
+        None => compress(sample, 1).len(),
+    };
+    compressed.saturating_mul(100) < sample.len() * COMPRESSIBLE_RATIO
+}
+
 /// Decompresses a byte slice using the Zstandard compression algorithm.
 ///
 /// # Arguments
@@ -76,12 +181,268 @@ pub fn generate_seeds() -> ([u8; KEY_SIZE], [u8; NONCE_SIZE]) {
     (key, nonce)
 }
 
-/// Decrypts a byte slice using the ChaCha20Poly1305 encryption algorithm.
+/// Derives the key of the file `uuid` from the keeper's key secret with HKDF-SHA256.
+///
+/// Used by `KeyMode::Derived`: the key never needs to be stored, the secret and the uuid are
+/// enough to recover it.
+pub fn derive_file_key(secret: &[u8; KEY_SIZE], uuid: &str) -> [u8; KEY_SIZE] {
+    let mut info = FILE_KEY_INFO.to_vec();
+    info.extend_from_slice(uuid.as_bytes());
+
+    let mut key = [0u8; KEY_SIZE];
+    Hkdf::<Sha256>::new(None, secret)
+        .expand(&info, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+/// How the key of a file is produced.
+///
+/// The discriminant is stored in the keeper, so existing values must never change.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyMode {
+    /// random key, wrapped under the master key in the keeper.
+    #[default]
+    Random = 0,
+
+    /// key derived from the keeper's key secret and the file uuid with `derive_file_key`, the
+    /// keeper stores no key material.
+    Derived = 1,
+}
+
+impl TryFrom<u8> for KeyMode {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(KeyMode::Random),
+            1 => Ok(KeyMode::Derived),
+            _ => Err(Error::EncryptionError(
+                error::EncryptionError::UnknownKeyMode(value.to_string()),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for KeyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyMode::Random => write!(f, "random"),
+            KeyMode::Derived => write!(f, "derived"),
+        }
+    }
+}
+
+impl FromStr for KeyMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "random" => Ok(KeyMode::Random),
+            "derived" => Ok(KeyMode::Derived),
+            _ => Err(Error::EncryptionError(
+                error::EncryptionError::UnknownKeyMode(s.to_string()),
+            )),
+        }
+    }
+}
+
+/// Argon2id parameters used to derive a file key from a passphrase.
+///
+/// These are stored in the header of passphrase protected files so the key can be derived again
+/// on any machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// random salt, unique per file.
+    pub salt: [u8; SALT_SIZE],
+    /// memory cost in KiB.
+    pub m_cost: u32,
+    /// number of iterations.
+    pub t_cost: u32,
+    /// degree of parallelism.
+    pub p_cost: u32,
+}
+
+impl KdfParams {
+    /// Serialized length of the parameters.
+    pub const SIZE: usize = SALT_SIZE + 12;
+
+    /// Highest memory cost `from_bytes` accepts, 1 GiB in KiB.
+    pub const MAX_M_COST: u32 = 1024 * 1024;
+    /// Highest number of iterations `from_bytes` accepts.
+    pub const MAX_T_COST: u32 = 16;
+    /// Highest degree of parallelism `from_bytes` accepts.
+    pub const MAX_P_COST: u32 = 8;
+
+    /// Generates parameters with a fresh random salt and the default costs
+    /// (64 MiB of memory, 3 iterations, 1 lane).
+    pub fn generate() -> Self {
+        let mut salt = [0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            salt,
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+
+    /// Derives a `KEY_SIZE` key from `passphrase` using Argon2id.
+    pub fn derive_key(&self, passphrase: &str) -> Result<[u8; KEY_SIZE]> {
+        info!("deriving key from passphrase");
+        let params = argon2::Params::new(self.m_cost, self.t_cost, self.p_cost, Some(KEY_SIZE))
+            .map_err(|_| Error::EncryptionError(error::EncryptionError::KdfError))?;
+        let argon =
+            argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+        let mut key = [0u8; KEY_SIZE];
+        argon
+            .hash_password_into(passphrase.as_bytes(), &self.salt, &mut key)
+            .map_err(|_| Error::EncryptionError(error::EncryptionError::KdfError))?;
+        Ok(key)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.salt.to_vec();
+        bytes.extend_from_slice(&self.m_cost.to_le_bytes());
+        bytes.extend_from_slice(&self.t_cost.to_le_bytes());
+        bytes.extend_from_slice(&self.p_cost.to_le_bytes());
+        bytes
+    }
+
+    /// Parses parameters serialized with `to_bytes`.
+    ///
+    /// The parameters come from file headers, so costs above `MAX_M_COST`, `MAX_T_COST` or
+    /// `MAX_P_COST` are rejected before a crafted file can make `derive_key` exhaust memory or
+    /// time.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::SIZE {
+            return Err(Error::FcError(error::FcError::InvalidHeader(
+                "invalid kdf parameters",
+            )));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let params = Self {
+            salt: bytes[..SALT_SIZE].try_into().unwrap(),
+            m_cost: u32_at(SALT_SIZE),
+            t_cost: u32_at(SALT_SIZE + 4),
+            p_cost: u32_at(SALT_SIZE + 8),
+        };
+        if params.m_cost > Self::MAX_M_COST
+            || params.t_cost > Self::MAX_T_COST
+            || params.p_cost > Self::MAX_P_COST
+        {
+            return Err(Error::FcError(error::FcError::InvalidHeader(
+                "kdf parameters too costly",
+            )));
+        }
+        Ok(params)
+    }
+}
+
+/// Encrypts a file key under a master key (envelope encryption).
+///
+/// `aad` binds the wrapped key to its owner (e.g. the uuid of the file), so a wrapped key copied
+/// to another row fails to unwrap.
+///
+/// # Returns
+///
+/// `WRAPPED_KEY_SIZE` bytes: a random nonce followed by the encrypted key and tag.
+pub fn wrap_key(master: &[u8; KEY_SIZE], key: &[u8; KEY_SIZE], aad: &[u8]) -> Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let sealed = ChaCha20Poly1305::new(Key::from_slice(master))
+        .encrypt(&nonce, Payload { msg: key, aad })
+        .map_err(|_| Error::EncryptionError(error::EncryptionError::AeadError))?;
+
+    let mut wrapped = nonce.to_vec();
+    wrapped.extend_from_slice(&sealed);
+    Ok(wrapped)
+}
+
+/// Decrypts a file key wrapped with `wrap_key`.
+///
+/// # Errors
+///
+/// Returns `EncryptionError::KeyUnwrapError` if `master` or `aad` do not match the ones used to
+/// wrap the key, or if `wrapped` was modified.
+pub fn unwrap_key(master: &[u8; KEY_SIZE], wrapped: &[u8], aad: &[u8]) -> Result<[u8; KEY_SIZE]> {
+    if wrapped.len() != WRAPPED_KEY_SIZE {
+        return Err(Error::EncryptionError(
+            error::EncryptionError::KeyUnwrapError,
+        ));
+    }
+    let (nonce, sealed) = wrapped.split_at(NONCE_SIZE);
+    ChaCha20Poly1305::new(Key::from_slice(master))
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad })
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or(Error::EncryptionError(
+            error::EncryptionError::KeyUnwrapError,
+        ))
+}
+
+/// Encrypts a file name under `key`, for storing it where the plain name would leak.
+///
+/// # Returns
+///
+/// A random nonce followed by the encrypted name and tag.
+pub fn seal_name(key: &[u8; KEY_SIZE], name: &str) -> Result<Vec<u8>> {
+    seal_blob(key, name.as_bytes(), NAME_AAD)
+}
+
+/// Decrypts a file name sealed with `seal_name`.
+///
+/// # Errors
+///
+/// Returns `EncryptionError::AeadError` if `key` is wrong or `sealed` was modified.
+pub fn open_name(key: &[u8; KEY_SIZE], sealed: &[u8]) -> Result<String> {
+    open_blob(key, sealed, NAME_AAD).and_then(|name| {
+        String::from_utf8(name)
+            .map_err(|_| Error::EncryptionError(error::EncryptionError::AeadError))
+    })
+}
+
+/// Encrypts `data` under `key` with ChaCha20Poly1305, binding it to `aad`. Used for small
+/// values kept next to the files, like names and dictionaries.
+///
+/// # Returns
+///
+/// A random nonce followed by the encrypted data and tag.
+pub fn seal_blob(key: &[u8; KEY_SIZE], data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let sealed = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(&nonce, Payload { msg: data, aad })
+        .map_err(|_| Error::EncryptionError(error::EncryptionError::AeadError))?;
+
+    let mut out = nonce.to_vec();
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+/// Decrypts data sealed with `seal_blob`.
+///
+/// # Errors
+///
+/// Returns `EncryptionError::AeadError` if `key` or `aad` are wrong or `sealed` was modified.
+pub fn open_blob(key: &[u8; KEY_SIZE], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_SIZE + TAG_SIZE {
+        return Err(Error::EncryptionError(error::EncryptionError::AeadError));
+    }
+    let (nonce, sealed) = sealed.split_at(NONCE_SIZE);
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad })
+        .map_err(|_| Error::EncryptionError(error::EncryptionError::AeadError))
+}
+
+/// Decrypts a byte slice using the cipher recorded in `fc.cipher`.
 ///
 /// # Arguments
 ///
 /// * `fc` - A `FileCrypt` struct containing the key and nonce required for decryption.
 /// * `contents` - A reference to a `Vec<u8>` containing the encrypted data.
+/// * `aad` - associated data the contents were encrypted with.
 ///
 /// # Returns
 /// A `Result<Vec<u8>, chacha20poly1305::Error>` where the `Ok` variant contains the decrypted data on success.
@@ -92,21 +453,21 @@ pub fn generate_seeds() -> ([u8; KEY_SIZE], [u8; NONCE_SIZE]) {
 /// # Panics
 ///
 /// Panics if the decryption process encounters a critical error.
-pub fn decrypt(fc: FileCrypt, contents: &Vec<u8>) -> Result<Vec<u8>> {
+pub fn decrypt(fc: FileCrypt, contents: &Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
     info!("decrypting contents");
-    let k = Key::from_slice(&fc.key);
-    let n = Nonce::from_slice(&fc.nonce);
-    ChaCha20Poly1305::new(k)
-        .decrypt(n, contents.as_ref())
-        .map_err(|_| Error::EncryptionError(error::EncryptionError::ChaChaError))
+    let payload = Payload { msg: contents, aad };
+    fc.cipher.decrypt(&fc.key, &fc.nonce, payload)
 }
 
-/// Takes a `FileCrypt` struct and encrypts the provided contents using the ChaCha20-Poly1305 cipher.
+/// Takes a `FileCrypt` struct and encrypts the provided contents using the cipher recorded in
+/// `fc.cipher`.
 ///
 /// # Arguments
 ///
 /// * `fc` - A reference to a `FileCrypt` struct containing encryption parameters, including the key and nonce.
 /// * `contents` - A slice of bytes representing the contents to be encrypted.
+/// * `aad` - associated data to authenticate along with the contents, e.g.
+///   `Header::associated_data`.
 ///
 /// # Returns
 /// A `Result<Vec<u8>, chacha20poly1305::Error>` where the `Ok` variant contains the decrypted data on success.
@@ -115,13 +476,304 @@ pub fn decrypt(fc: FileCrypt, contents: &Vec<u8>) -> Result<Vec<u8>> {
 ///
 /// # Panics
 /// The function panics if encryption using ChaCha20-Poly1305 fails.
-pub fn encrypt(fc: &FileCrypt, contents: &[u8]) -> Result<Vec<u8>> {
+pub fn encrypt(fc: &FileCrypt, contents: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
     info!("encrypting file contents");
-    let k = Key::from_slice(&fc.key);
-    let n = Nonce::from_slice(&fc.nonce);
-    ChaCha20Poly1305::new(k)
-        .encrypt(n, contents)
-        .map_err(|_| Error::EncryptionError(error::EncryptionError::ChaChaError))
+    let payload = Payload { msg: contents, aad };
+    fc.cipher.encrypt(&fc.key, &fc.nonce, payload)
+}
+
+/// Encrypts everything read from `reader` into `writer` using the STREAM construction
+/// (`fc.cipher`, 32-bit big endian counter).
+///
+/// The plaintext is optionally compressed with zstd and padded, then split into
+/// `STREAM_CHUNK_SIZE` chunks which are encrypted one at a time, so memory use stays bounded
+/// regardless of input size.
+///
+/// # Arguments
+///
+/// * `fc` - `FileCrypt` holding the cipher, key and nonce. Only the first
+///   `Cipher::stream_nonce_size` bytes of the nonce are used.
+/// * `compression` - compression to apply before encryption.
+/// * `dictionary` - zstd dictionary, required by `Compression::ZstdDictionary`.
+/// * `level` - zstd compression level, ignored when `compression` is `Compression::None`.
+/// * `padding` - padding applied to the compressed payload, see `padding::Padding`.
+/// * `aad` - associated data authenticated with every chunk, e.g. `Header::associated_data`.
+///
+/// # Returns
+///
+/// The BLAKE2s hash of the plaintext read from `reader`.
+#[allow(clippy::too_many_arguments)]
+pub fn encrypt_stream<R: Read, W: Write>(
+    fc: &FileCrypt,
+    compression: Compression,
+    dictionary: Option<&[u8]>,
+    level: i32,
+    padding: Padding,
+    aad: &[u8],
+    mut reader: R,
+    writer: W,
+) -> Result<[u8; KEY_SIZE]> {
+    info!("encrypting stream");
+    let mut hasher = Blake2s256::new();
+    let sink = PadWriter::new(padding, EncryptWriter::new(fc, aad, writer)?);
+
+    let sink = match compression {
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::write::Encoder::new(sink, level)?;
+            copy_hashed(&mut reader, &mut encoder, &mut hasher)?;
+            encoder.finish()?
+        }
+        Compression::ZstdDictionary => {
+            let mut encoder = zstd::stream::write::Encoder::with_dictionary(
+                sink,
+                level,
+                dictionary.ok_or(Error::EncryptionError(
+                    error::EncryptionError::MissingDictionary,
+                ))?,
+            )?;
+            copy_hashed(&mut reader, &mut encoder, &mut hasher)?;
+            encoder.finish()?
+        }
+        Compression::None => {
+            let mut sink = sink;
+            copy_hashed(&mut reader, &mut sink, &mut hasher)?;
+            sink
+        }
+    };
+    sink.finish()?.finish()?;
+
+    Ok(hasher.finalize().into())
+}
+
+/// Decrypts a payload written by `encrypt_stream`, writing the plaintext into `writer`.
+/// `padded` tells whether the payload was written with a `Padding` other than `Padding::None`.
+///
+/// # Returns
+///
+/// The BLAKE2s hash of the plaintext written to `writer`.
+///
+/// # Errors
+///
+/// Returns `EncryptionError::AeadError` if any chunk fails authentication, including when the
+/// payload has been truncated, chunks were reordered or `aad` differs from the one used to
+/// encrypt.
+pub fn decrypt_stream<R: Read, W: Write>(
+    fc: &FileCrypt,
+    compression: Compression,
+    dictionary: Option<&[u8]>,
+    padded: bool,
+    aad: &[u8],
+    reader: R,
+    mut writer: W,
+) -> Result<[u8; KEY_SIZE]> {
+    info!("decrypting stream");
+    let mut hasher = Blake2s256::new();
+    let mut source = plaintext_reader(fc, compression, dictionary, padded, aad, reader)?;
+
+    copy_hashed(&mut source, &mut writer, &mut hasher).map_err(from_stream_error)?;
+
+    Ok(hasher.finalize().into())
+}
+
+/// Returns a reader over the plaintext of a payload written by `encrypt_stream`.
+///
+/// Chunks failing authentication surface as `io::Error`s, `from_stream_error` turns them back
+/// into `EncryptionError::AeadError`.
+pub(crate) fn plaintext_reader<'a, R: Read + 'a>(
+    fc: &FileCrypt,
+    compression: Compression,
+    dictionary: Option<&[u8]>,
+    padded: bool,
+    aad: &[u8],
+    reader: R,
+) -> Result<Box<dyn Read + 'a>> {
+    let source = DecryptReader::new(fc, aad, BufReader::new(reader))?;
+    let source: Box<dyn Read + 'a> = match padded {
+        true => Box::new(UnpadReader::new(source)),
+        false => Box::new(source),
+    };
+    Ok(match compression {
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(source)?),
+        Compression::ZstdDictionary => Box::new(zstd::stream::read::Decoder::with_dictionary(
+            BufReader::new(source),
+            dictionary.ok_or(Error::EncryptionError(
+                error::EncryptionError::MissingDictionary,
+            ))?,
+        )?),
+        Compression::None => Box::new(source),
+    })
+}
+
+/// Copies `reader` into `writer`, feeding every byte through `hasher`.
+fn copy_hashed<R: Read + ?Sized, W: Write + ?Sized>(
+    reader: &mut R,
+    writer: &mut W,
+    hasher: &mut Blake2s256,
+) -> io::Result<()> {
+    let mut buf = vec![0u8; STREAM_CHUNK_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        digest::Update::update(hasher, &buf[..n]);
+        writer.write_all(&buf[..n])?;
+    }
+    writer.flush()
+}
+
+/// Turns the `io::Error` produced by `DecryptReader` back into a crate error.
+pub(crate) fn from_stream_error(e: io::Error) -> Error {
+    match e
+        .get_ref()
+        .and_then(|i| i.downcast_ref::<error::EncryptionError>())
+    {
+        Some(_) => Error::EncryptionError(error::EncryptionError::AeadError),
+        None => Error::IoError(e),
+    }
+}
+
+/// `Write` adapter that encrypts everything written to it in `STREAM_CHUNK_SIZE` chunks.
+///
+/// A full chunk is only sealed once more data arrives, so the final chunk (which may be full or
+/// empty) is always sealed with `encrypt_last` by `finish`.
+struct EncryptWriter<W: Write> {
+    inner: W,
+    encryptor: Option<StreamEncryptor>,
+    aad: Vec<u8>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    fn new(fc: &FileCrypt, aad: &[u8], inner: W) -> Result<Self> {
+        Ok(Self {
+            inner,
+            encryptor: Some(fc.cipher.stream_encryptor(&fc.key, &fc.nonce)?),
+            aad: aad.to_vec(),
+            buffer: Vec::with_capacity(STREAM_CHUNK_SIZE),
+        })
+    }
+
+    /// Seals the remaining buffer as the last chunk and returns the inner writer.
+    fn finish(mut self) -> io::Result<W> {
+        let encryptor = self.encryptor.take().expect("stream already finished");
+        let payload = Payload {
+            msg: &self.buffer,
+            aad: &self.aad,
+        };
+        let chunk = encryptor
+            .encrypt_last(payload)
+            .map_err(|_| stream_error())?;
+        self.inner.write_all(&chunk)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.buffer.len() == STREAM_CHUNK_SIZE {
+            let encryptor = self.encryptor.as_mut().expect("stream already finished");
+            let payload = Payload {
+                msg: &self.buffer,
+                aad: &self.aad,
+            };
+            let chunk = encryptor
+                .encrypt_next(payload)
+                .map_err(|_| stream_error())?;
+            self.inner.write_all(&chunk)?;
+            self.buffer.clear();
+        }
+        let n = buf.len().min(STREAM_CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `Read` adapter that decrypts a STREAM payload chunk by chunk.
+struct DecryptReader<R: BufRead> {
+    inner: R,
+    decryptor: Option<StreamDecryptor>,
+    aad: Vec<u8>,
+    chunk: Vec<u8>,
+    plaintext: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> DecryptReader<R> {
+    fn new(fc: &FileCrypt, aad: &[u8], inner: R) -> Result<Self> {
+        Ok(Self {
+            inner,
+            decryptor: Some(fc.cipher.stream_decryptor(&fc.key, &fc.nonce)?),
+            aad: aad.to_vec(),
+            chunk: vec![0u8; STREAM_CHUNK_SIZE + TAG_SIZE],
+            plaintext: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    /// Reads and decrypts the next chunk into `plaintext`.
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let mut len = 0;
+        while len < self.chunk.len() {
+            match self.inner.read(&mut self.chunk[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        // a short chunk, or a full one with nothing after it, is the last chunk
+        let is_last = len < self.chunk.len() || self.inner.fill_buf()?.is_empty();
+        let payload = Payload {
+            msg: &self.chunk[..len],
+            aad: &self.aad,
+        };
+
+        self.plaintext = if is_last {
+            let decryptor = self.decryptor.take().expect("stream already finished");
+            decryptor.decrypt_last(payload)
+        } else {
+            let decryptor = self.decryptor.as_mut().expect("stream already finished");
+            decryptor.decrypt_next(payload)
+        }
+        .map_err(|_| stream_error())?;
+        self.pos = 0;
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plaintext.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let n = buf.len().min(self.plaintext.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plaintext[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+fn stream_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        error::EncryptionError::AeadError,
+    )
 }
 
 // cargo nextest run
@@ -130,6 +782,7 @@ mod test {
     use std::path::PathBuf;
 
     use super::*;
+    use crate::{cipher::Cipher, filecrypt::generate_uuid};
 
     #[test]
     fn test_hash() {
@@ -172,12 +825,12 @@ mod test {
             [0u8; KEY_SIZE],
         );
         let contents = b"hello there";
-        let res = encrypt(&fc, contents).unwrap();
+        let res = encrypt(&fc, contents, &[]).unwrap();
         assert_ne!(contents, res.as_slice());
     }
     #[test]
     fn test_decrypt() {
-        let fc = FileCrypt::new(
+        let mut fc = FileCrypt::new(
             "".to_string(),
             "".to_string(),
             "".to_string(),
@@ -185,10 +838,283 @@ mod test {
             [0u8; KEY_SIZE],
         );
         let contents = b"hello there";
-        let res = encrypt(&fc, &contents.clone()).unwrap();
-        assert_ne!(contents, res.as_slice());
+        for cipher in Cipher::ALL {
+            fc.set_cipher(cipher);
+            let res = encrypt(&fc, &contents.clone(), b"aad").unwrap();
+            assert_ne!(contents, res.as_slice());
+
+            let dec = decrypt(fc.clone(), &res, b"aad").unwrap();
+            assert_eq!(contents, dec.as_slice());
+            assert!(decrypt(fc.clone(), &res, b"other").is_err());
+        }
+    }
+
+    #[test]
+    fn test_kdf() {
+        // keep the costs low so the test stays fast
+        let params = KdfParams {
+            m_cost: 256,
+            t_cost: 1,
+            ..KdfParams::generate()
+        };
+        let key = params.derive_key("correct horse battery staple").unwrap();
+        assert_eq!(
+            key,
+            params.derive_key("correct horse battery staple").unwrap()
+        );
+        assert_ne!(key, params.derive_key("wrong passphrase").unwrap());
+
+        let parsed = KdfParams::from_bytes(&params.to_bytes()).unwrap();
+        assert_eq!(parsed, params);
+    }
+
+    #[test]
+    fn test_kdf_rejects_costly_params() {
+        let max = KdfParams {
+            m_cost: KdfParams::MAX_M_COST,
+            t_cost: KdfParams::MAX_T_COST,
+            p_cost: KdfParams::MAX_P_COST,
+            ..KdfParams::generate()
+        };
+        assert_eq!(KdfParams::from_bytes(&max.to_bytes()).unwrap(), max);
+
+        for costly in [
+            KdfParams {
+                m_cost: u32::MAX,
+                ..max
+            },
+            KdfParams {
+                t_cost: u32::MAX,
+                ..max
+            },
+            KdfParams {
+                p_cost: max.p_cost + 1,
+                ..max
+            },
+        ] {
+            assert!(matches!(
+                KdfParams::from_bytes(&costly.to_bytes()),
+                Err(Error::FcError(error::FcError::InvalidHeader(_)))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_wrap_key() {
+        let (master, _) = generate_seeds();
+        let (key, _) = generate_seeds();
+
+        let wrapped = wrap_key(&master, &key, b"uuid").unwrap();
+        assert_eq!(wrapped.len(), WRAPPED_KEY_SIZE);
+        assert_eq!(unwrap_key(&master, &wrapped, b"uuid").unwrap(), key);
 
-        let dec = decrypt(fc, &res).unwrap();
-        assert_eq!(contents, dec.as_slice());
+        // wrong master key or owner must not unwrap
+        let (other, _) = generate_seeds();
+        assert!(unwrap_key(&other, &wrapped, b"uuid").is_err());
+        assert!(unwrap_key(&master, &wrapped, b"other").is_err());
+    }
+
+    #[test]
+    fn test_is_compressible() {
+        let text = "It was a dark and stormy night. ".repeat(1024);
+        assert!(is_compressible(text.as_bytes(), None));
+
+        // already compressed data does not shrink again
+        assert!(!is_compressible(&compress(text.as_bytes(), 3), None));
+
+        let mut random = vec![0u8; 64 * 1024];
+        OsRng.fill_bytes(&mut random);
+        assert!(!is_compressible(&random, None));
+
+        let mut jpeg = b"\xFF\xD8\xFF\xE0".to_vec();
+        jpeg.extend_from_slice(text.as_bytes());
+        assert!(!is_compressible(&jpeg, None));
+    }
+
+    #[test]
+    fn test_derive_file_key() {
+        let (secret, _) = generate_seeds();
+        let uuid = generate_uuid();
+
+        // the same secret and uuid always give the same key
+        let key = derive_file_key(&secret, &uuid);
+        assert_eq!(key, derive_file_key(&secret, &uuid));
+
+        assert_ne!(key, derive_file_key(&secret, &generate_uuid()));
+        let (other, _) = generate_seeds();
+        assert_ne!(key, derive_file_key(&other, &uuid));
+    }
+
+    #[test]
+    fn test_seal_name() {
+        let (key, _) = generate_seeds();
+
+        let sealed = seal_name(&key, "tax_return.pdf").unwrap();
+        assert_eq!(open_name(&key, &sealed).unwrap(), "tax_return.pdf");
+
+        // the same name never seals to the same bytes
+        assert_ne!(sealed, seal_name(&key, "tax_return.pdf").unwrap());
+
+        let (other, _) = generate_seeds();
+        assert!(open_name(&other, &sealed).is_err());
+        assert!(open_name(&key, &sealed[..NONCE_SIZE]).is_err());
+    }
+
+    #[test]
+    fn test_stream_round_trip() {
+        let mut fc = FileCrypt::new(
+            "".to_string(),
+            "".to_string(),
+            "".to_string(),
+            PathBuf::from(""),
+            [0u8; KEY_SIZE],
+        );
+        // spans several chunks and ends on a partial one
+        let contents: Vec<u8> = (0..STREAM_CHUNK_SIZE * 3 + 17)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        for (cipher, compression, padding) in Cipher::ALL.into_iter().flat_map(|c| {
+            [
+                (c, Compression::Zstd, Padding::None),
+                (c, Compression::None, Padding::None),
+                (c, Compression::Zstd, Padding::Padme),
+            ]
+        }) {
+            fc.set_cipher(cipher);
+            let padded = padding != Padding::None;
+            let mut encrypted = Vec::new();
+            let hash = encrypt_stream(
+                &fc,
+                compression,
+                None,
+                3,
+                padding,
+                b"aad",
+                contents.as_slice(),
+                &mut encrypted,
+            )
+            .unwrap();
+            assert_eq!(hash, compute_hash(&contents));
+
+            let mut decrypted = Vec::new();
+            let dec_hash = decrypt_stream(
+                &fc,
+                compression,
+                None,
+                padded,
+                b"aad",
+                encrypted.as_slice(),
+                &mut decrypted,
+            )
+            .unwrap();
+            assert_eq!(decrypted, contents);
+            assert_eq!(dec_hash, hash);
+
+            // changed associated data must be rejected
+            let res = decrypt_stream(
+                &fc,
+                compression,
+                None,
+                padded,
+                b"other",
+                encrypted.as_slice(),
+                io::sink(),
+            );
+            assert!(matches!(res, Err(Error::EncryptionError(_))));
+        }
+    }
+
+    #[test]
+    fn test_stream_with_dictionary() {
+        let fc = FileCrypt::new(
+            "".to_string(),
+            "".to_string(),
+            "".to_string(),
+            PathBuf::from(""),
+            [0u8; KEY_SIZE],
+        );
+        let samples: Vec<Vec<u8>> = (0..200)
+            .map(|i| {
+                format!("fn item_{i}() -> Result<()> {{\n    return Ok(());\n}}\n").into_bytes()
+            })
+            .collect();
+        let dictionary = train_dictionary(&samples).unwrap();
+
+        let mut encrypted = Vec::new();
+        encrypt_stream(
+            &fc,
+            Compression::ZstdDictionary,
+            Some(&dictionary),
+            3,
+            Padding::None,
+            &[],
+            samples[7].as_slice(),
+            &mut encrypted,
+        )
+        .unwrap();
+
+        let mut decrypted = Vec::new();
+        decrypt_stream(
+            &fc,
+            Compression::ZstdDictionary,
+            Some(&dictionary),
+            false,
+            &[],
+            encrypted.as_slice(),
+            &mut decrypted,
+        )
+        .unwrap();
+        assert_eq!(decrypted, samples[7]);
+
+        // the dictionary is needed to read it back
+        let res = decrypt_stream(
+            &fc,
+            Compression::ZstdDictionary,
+            None,
+            false,
+            &[],
+            encrypted.as_slice(),
+            io::sink(),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_stream_detects_truncation() {
+        let fc = FileCrypt::new(
+            "".to_string(),
+            "".to_string(),
+            "".to_string(),
+            PathBuf::from(""),
+            [0u8; KEY_SIZE],
+        );
+        let contents = vec![7u8; STREAM_CHUNK_SIZE * 2 + 5];
+
+        let mut encrypted = Vec::new();
+        encrypt_stream(
+            &fc,
+            Compression::None,
+            None,
+            3,
+            Padding::None,
+            &[],
+            contents.as_slice(),
+            &mut encrypted,
+        )
+        .unwrap();
+
+        // drop the final chunk, leaving a stream that ends on a chunk boundary
+        encrypted.truncate((STREAM_CHUNK_SIZE + TAG_SIZE) * 2);
+        let res = decrypt_stream(
+            &fc,
+            Compression::None,
+            None,
+            false,
+            &[],
+            encrypted.as_slice(),
+            io::sink(),
+        );
+        assert!(matches!(res, Err(Error::EncryptionError(_))));
     }
 }
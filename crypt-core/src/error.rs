@@ -32,6 +32,13 @@ pub enum Error {
     #[error(transparent)]
     EncryptionError(#[from] EncryptionError),
 
+    // #################### age Errors ####################
+    #[error(transparent)]
+    AgeEncryptError(#[from] age::EncryptError),
+
+    #[error(transparent)]
+    AgeDecryptError(#[from] age::DecryptError),
+
     // #################### General Errors ####################
     #[error(transparent)]
     IoError(#[from] std::io::Error),
@@ -64,7 +71,25 @@ pub enum TokenError {
 }
 
 #[derive(Debug, Error)]
-pub enum DatabaseError {}
+pub enum DatabaseError {
+    #[error("keeper is locked, unlock it with the keeper passphrase first")]
+    KeeperLocked,
+
+    #[error("incorrect keeper passphrase")]
+    WrongKeeperPassphrase,
+
+    #[error("malformed keeper export record")]
+    InvalidExportRecord,
+
+    #[error("no identity in the keeper, generate one with `crypt recipient identity`")]
+    NoIdentity,
+
+    #[error("unknown recipient: {0}")]
+    UnknownRecipient(String),
+
+    #[error("key secret must be 32 bytes, hex encoded")]
+    InvalidKeySecret,
+}
 
 /// Represents various errors that can occur during file decryption.
 ///
@@ -81,6 +106,23 @@ pub enum DatabaseError {}
 /// - `FileReadError`: An error occurred while reading the file.
 /// - `FileError(String)`: An error occurred during file operations (read or write).
 /// - `DecryptError(String)`: Failed to decrypt the file contents.
+/// - `NotACryptFile`: The input does not start with a crypt header or legacy UUID.
+/// - `InvalidHeader`: The crypt header is truncated or malformed.
+/// - `HeaderTooLarge(usize)`: A header extension is too long to be written, e.g. too many recipients.
+/// - `UnsupportedVersion(u8)`: The file was written with a newer format version.
+/// - `UnknownCipher(u8)` / `UnknownCompression(u8)`: The header names an unknown algorithm.
+/// - `PassphraseRequired`: The file is passphrase protected and no passphrase was given.
+/// - `WrongPassphrase`: The passphrase did not decrypt the file.
+/// - `Tampered`: The payload failed authentication, e.g. the header, uuid or name were changed.
+/// - `NotARecipient`: The file was encrypted to recipients that do not include our identity.
+/// - `NotKeeperTracked`: The operation needs a keeper tracked file, not a portable one.
+/// - `NotAnArchive`: The operation needs an archive written by `encrypt_archive`.
+/// - `ArchiveMemberNotFound(String)`: The archive has no member with that path.
+/// - `ArchiveStream`: Archives extract to a folder and cannot be decrypted to a stream.
+/// - `ArchiveMemberName(String)`: A name in the directory to archive is not valid UTF-8.
+/// - `Unverified(String)`: A new `.crypt` file did not verify, so its original was not deleted.
+/// - `FileCryptMismatch`: The contents were sealed under another `FileCrypt`.
+/// - `KeeperDictionary`: The contents need a dictionary from the keeper to decompress.
 ///
 /// # Examples
 ///
@@ -115,17 +157,92 @@ pub enum FcError {
     #[error("file decompression failed. {0}")]
     DecompressionError(String),
 
-    #[error("")]
+    #[error("failed to delete {1}: {0}")]
     FileDeletionError(std::io::Error, String),
 
     #[error("Decryption failed: {0}")]
     DecryptError(String),
+
+    #[error("file is not a crypt file")]
+    NotACryptFile,
+
+    #[error("invalid crypt header: {0}")]
+    InvalidHeader(&'static str),
+
+    #[error("header extension too large: {0} bytes")]
+    HeaderTooLarge(usize),
+
+    #[error("unsupported crypt format version: {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("unknown cipher id: {0}")]
+    UnknownCipher(u8),
+
+    #[error("unknown compression id: {0}")]
+    UnknownCompression(u8),
+
+    #[error("file is protected with a passphrase")]
+    PassphraseRequired,
+
+    #[error("incorrect passphrase or corrupted file")]
+    WrongPassphrase,
+
+    #[error("authentication failed: the file contents, header or keeper entry were modified")]
+    Tampered,
+
+    #[error("file was not encrypted to this keeper's identity")]
+    NotARecipient,
+
+    #[error("file is protected with a passphrase or recipients, not tracked by the keeper")]
+    NotKeeperTracked,
+
+    #[error("file is not an archive")]
+    NotAnArchive,
+
+    #[error("no such archive member: {0}")]
+    ArchiveMemberNotFound(String),
+
+    #[error("archives extract to a folder, they cannot be decrypted to a stream")]
+    ArchiveStream,
+
+    #[error("name is not valid UTF-8, it cannot be archived: {0}")]
+    ArchiveMemberName(String),
+
+    #[error("{0} did not verify, the original was kept")]
+    Unverified(String),
+
+    #[error("contents were sealed under another file crypt")]
+    FileCryptMismatch,
+
+    #[error("contents are compressed with a keeper dictionary")]
+    KeeperDictionary,
 }
 
 #[derive(Debug, Error)]
 pub enum EncryptionError {
-    #[error("ChaChaPoly1305 Error")]
-    ChaChaError,
+    #[error("AEAD encryption failed")]
+    AeadError,
+
+    #[error("failed to derive key from passphrase")]
+    KdfError,
+
+    #[error("failed to unwrap key")]
+    KeyUnwrapError,
+
+    #[error("nonce length does not match the cipher")]
+    InvalidNonce,
+
+    #[error("unknown cipher: {0}")]
+    UnknownCipherName(String),
+
+    #[error("invalid recipient public key: {0}")]
+    InvalidRecipient(String),
+
+    #[error("unknown key mode: {0}")]
+    UnknownKeyMode(String),
+
+    #[error("file is compressed with a dictionary that was not provided")]
+    MissingDictionary,
 }
 
 #[derive(Debug, Error)]
@@ -135,4 +252,13 @@ pub enum CommonError {
 
     #[error("user aborted file search")]
     UserAbort,
+
+    #[error("unknown name mode: {0}")]
+    UnknownNameMode(String),
+
+    #[error("unknown padding: {0}")]
+    UnknownPadding(String),
+
+    #[error("unknown conflict policy: {0}, expected overwrite, skip or rename")]
+    UnknownConflictPolicy(String),
 }
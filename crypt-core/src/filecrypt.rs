@@ -1,24 +1,34 @@
 use crate::{
-    common::{
-        chooser, get_crypt_folder, get_file_contents, get_full_file_path, get_vec_file_bytes,
-        write_contents_to_file,
-    },
-    config::get_config,
-    db::{insert_crypt, query_crypt},
+    archive::{pack_directory, read_index, ArchiveEntry, Unpacker},
+    common::{get_full_file_path, parallel_map, shred_file, write_contents_to_file},
+    config::{Config, NameMode},
     encryption::{
-        compress, compute_hash, decompress, decrypt, encrypt, generate_seeds, KEY_SIZE, NONCE_SIZE,
+        compress, compute_file_hash, compute_hash, decompress, decrypt, decrypt_stream,
+        derive_file_key, encrypt_stream, from_stream_error, generate_seeds, is_compressible,
+        open_name, plaintext_reader, seal_name, train_dictionary, KdfParams, KeyMode,
+        DICTIONARY_SIZE, KEY_SIZE,
     },
     error,
+    header::{
+        Cipher, Compression, Header, EXT_DICTIONARY, EXT_EXTENSION, EXT_FILENAME, EXT_KDF,
+        EXT_NONCE, EXT_RECIPIENT, FLAG_ARCHIVE, FLAG_DERIVED_KEY, FLAG_PADDED, FLAG_PASSPHRASE,
+        FLAG_RECIPIENTS, FLAG_SEALED_NAME, FLAG_STREAM,
+    },
+    padding::Padding,
     prelude::*,
+    recipient::{Identity, RecipientKey},
+    vault::{default_vault, Vault},
 };
 use logfather::*;
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use std::{
-    fs::{read, File},
-    io::{BufReader, Read},
-    path::{Path, PathBuf},
-    time::Duration,
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Chain, Cursor, Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, UNIX_EPOCH},
 };
 
 /// Represents cryptographic information associated with an encrypted file.
@@ -45,11 +55,27 @@ pub struct FileCrypt {
     /// The encryption key used to encrypt the file.
     pub key: [u8; KEY_SIZE],
 
-    /// The nonce used in the encryption process.
-    pub nonce: [u8; NONCE_SIZE],
+    /// The nonce used in the encryption process, `cipher.nonce_size()` bytes long.
+    pub nonce: Vec<u8>,
+
+    /// The AEAD cipher the file is encrypted with.
+    pub cipher: Cipher,
+
+    /// How `key` was produced.
+    #[serde(default)]
+    pub key_mode: KeyMode,
 
     /// The hash of the encrypted file.
     pub hash: [u8; KEY_SIZE],
+
+    /// Size of the original file when it was encrypted, 0 if unknown.
+    #[serde(default)]
+    pub size: u64,
+
+    /// Modification time of the original file when it was encrypted, in nanoseconds since the
+    /// Unix epoch, 0 if unknown.
+    #[serde(default)]
+    pub mtime: u64,
 }
 
 impl FileCrypt {
@@ -65,7 +91,9 @@ impl FileCrypt {
     /// * `hash` - The hash of the encrypted file.
     ///
     /// # Returns
-    /// A new `FileCrypt` instance with generated `UUID`, `key`, and `nonce`.
+    /// A new `FileCrypt` instance with generated `UUID`, `key`, and `nonce`, using the default
+    /// `Cipher`. See `set_cipher` to pick another one, and `derive_key` to switch to
+    /// `KeyMode::Derived`.
     pub fn new(
         filename: String,
         ext: String,
@@ -74,7 +102,9 @@ impl FileCrypt {
         hash: [u8; KEY_SIZE],
     ) -> Self {
         // generate key & nonce
-        let (key, nonce) = generate_seeds();
+        let (key, _) = generate_seeds();
+        let cipher = Cipher::default();
+        let nonce = cipher.generate_nonce();
 
         // generate file uuid
         let uuid = generate_uuid();
@@ -85,12 +115,37 @@ impl FileCrypt {
             drive_id,
             key,
             nonce,
+            cipher,
+            key_mode: KeyMode::Random,
             ext,
             uuid,
             hash,
+            size: 0,
+            mtime: 0,
         }
     }
 
+    /// Replaces the random key with one derived from `secret` and the uuid, see
+    /// `encryption::derive_file_key`.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret` - The keeper's key secret, see `db::key_secret`.
+    pub fn derive_key(&mut self, secret: &[u8; KEY_SIZE]) {
+        self.key = derive_file_key(secret, &self.uuid);
+        self.key_mode = KeyMode::Derived;
+    }
+
+    /// Sets the cipher used to encrypt the file, generating a new nonce of the matching size.
+    ///
+    /// # Arguments
+    ///
+    /// * `cipher` - The AEAD cipher to use.
+    pub fn set_cipher(&mut self, cipher: Cipher) {
+        self.cipher = cipher;
+        self.nonce = cipher.generate_nonce();
+    }
+
     /// Sets the drive ID associated with the encrypted file.
     ///
     /// # Arguments
@@ -101,249 +156,2100 @@ impl FileCrypt {
     }
 }
 
-/// Decrypts a file using ChaCha20Poly1305 encryption and verifies its integrity.
+/// `Vault::decrypt_file` of the default vault.
+pub fn decrypt_file<T: AsRef<Path>>(path: T, output: String) -> Result<()> {
+    default_vault()?.decrypt_file(path, output)
+}
+
+/// `Vault::decrypt_file_with_passphrase` of the default vault.
+pub fn decrypt_file_with_passphrase<T: AsRef<Path>>(
+    path: T,
+    output: String,
+    passphrase: &str,
+) -> Result<()> {
+    default_vault()?.decrypt_file_with_passphrase(path, output, passphrase)
+}
+
+/// `Vault::decrypt_file_with` of the default vault.
+pub fn decrypt_file_with<T, F>(path: T, output: String, passphrase: F) -> Result<()>
+where
+    T: AsRef<Path>,
+    F: FnOnce() -> Option<String>,
+{
+    default_vault()?.decrypt_file_with(path, output, passphrase)
+}
+
+/// `Vault::decrypt_crypt_file` of the default vault.
+pub fn decrypt_crypt_file<T, F>(crypt_path: T, output: String, passphrase: F) -> Result<()>
+where
+    T: AsRef<Path>,
+    F: FnOnce() -> Option<String>,
+{
+    default_vault()?.decrypt_crypt_file(crypt_path, output, passphrase)
+}
+
+/// What `restore_file` does when the location of a file is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// replace what is there.
+    Overwrite,
+
+    /// leave what is there and restore nothing.
+    #[default]
+    Skip,
+
+    /// restore next to it as `name(1).ext`, `name(2).ext`, ... like `decrypt_file`.
+    Rename,
+}
+
+impl std::fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictPolicy::Overwrite => write!(f, "overwrite"),
+            ConflictPolicy::Skip => write!(f, "skip"),
+            ConflictPolicy::Rename => write!(f, "rename"),
+        }
+    }
+}
+
+impl std::str::FromStr for ConflictPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "rename" => Ok(ConflictPolicy::Rename),
+            _ => Err(Error::CommonError(
+                error::CommonError::UnknownConflictPolicy(s.to_string()),
+            )),
+        }
+    }
+}
+
+/// What `restore_file` did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreStatus {
+    /// the location was free and the file was restored to it.
+    Restored,
+
+    /// the file replaced what was at its location.
+    Overwritten,
+
+    /// the location was taken, the file was restored next to it.
+    Renamed,
+
+    /// the location was taken, nothing was restored.
+    Skipped,
+}
+
+impl std::fmt::Display for RestoreStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestoreStatus::Restored => write!(f, "restored"),
+            RestoreStatus::Overwritten => write!(f, "overwritten"),
+            RestoreStatus::Renamed => write!(f, "renamed"),
+            RestoreStatus::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+/// Gets the location a file recorded at `full_path` is restored to: `full_path` itself, or
+/// `full_path` relocated under `prefix`, e.g. `/home/ryan/docs/a.txt` under `/mnt/old` is
+/// `/mnt/old/home/ryan/docs/a.txt`.
+pub fn restore_target(full_path: &Path, prefix: Option<&Path>) -> PathBuf {
+    match prefix {
+        None => full_path.to_path_buf(),
+        Some(prefix) => full_path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .fold(prefix.to_path_buf(), |p, c| p.join(c)),
+    }
+}
+
+/// `Vault::restore_file` of the default vault.
+pub fn restore_file<T: AsRef<Path>>(
+    crypt_path: T,
+    policy: ConflictPolicy,
+    prefix: Option<&Path>,
+) -> Result<(RestoreStatus, PathBuf)> {
+    default_vault()?.restore_file(crypt_path, policy, prefix)
+}
+
+/// Finds a free location next to `target` for `fc`, `name(1).ext`, `name(2).ext`, ...
+fn free_path(fc: &FileCrypt, target: &Path) -> PathBuf {
+    let parent = target.parent().unwrap_or(Path::new(""));
+    (1..)
+        .map(|n| parent.join(format!("{}({}){}", fc.filename, n, fc.ext)))
+        .find(|p| std::fs::symlink_metadata(p).is_err())
+        .expect("some name is free")
+}
+
+/// Builds the `FileCrypt` of a file that carries its nonce and name in `header`, see
+/// `push_portable_extensions`. The hash is left empty, the AEAD tag is the integrity check.
+fn file_crypt_from_header(header: &Header, key: [u8; KEY_SIZE]) -> Result<FileCrypt> {
+    let nonce = header
+        .extension(EXT_NONCE)
+        .filter(|n| n.len() == header.cipher.nonce_size())
+        .ok_or(Error::FcError(error::FcError::InvalidHeader(
+            "missing nonce",
+        )))?;
+    let text = |tag| -> Result<String> {
+        match header.extension(tag) {
+            Some(v) if header.has_flag(FLAG_SEALED_NAME) => {
+                open_name(&key, v).map_err(|_| Error::FcError(error::FcError::WrongPassphrase))
+            }
+            Some(v) => Ok(String::from_utf8_lossy(v).to_string()),
+            None => Ok(String::new()),
+        }
+    };
+
+    Ok(FileCrypt {
+        uuid: header.uuid.clone(),
+        filename: text(EXT_FILENAME)?,
+        ext: text(EXT_EXTENSION)?,
+        drive_id: String::new(),
+        full_path: PathBuf::new(),
+        key,
+        nonce: nonce.to_vec(),
+        cipher: header.cipher,
+        key_mode: KeyMode::Random,
+        hash: [0u8; KEY_SIZE],
+        size: 0,
+        mtime: 0,
+    })
+}
+
+/// Finds the recipient stanza in `header` addressed to `identity` and unwraps the file key.
 ///
-/// # Arguments
+/// # Errors
+///
+/// Returns `FcError::NotARecipient` if none of the stanzas unwrap with `identity`.
+fn unwrap_recipient_key(header: &Header, identity: &Identity) -> Result<[u8; KEY_SIZE]> {
+    header
+        .extensions_with(EXT_RECIPIENT)
+        .find_map(|stanza| identity.unwrap_key(stanza, header.uuid.as_bytes()).ok())
+        .ok_or(Error::FcError(error::FcError::NotARecipient))
+}
+
+/// Outcome of `verify_file` for a single `.crypt` file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyStatus {
+    /// decrypts and matches the hash recorded in the keeper.
+    Ok,
+
+    /// the header or payload fails authentication, or the contents do not match the keeper hash.
+    Tampered,
+
+    /// the keeper has no entry for the uuid in the header.
+    MissingKeeperEntry,
+
+    /// the file key could not be recovered: the keeper entry does not unwrap, or the passphrase
+    /// or identity does not match.
+    WrongKey,
+
+    /// passphrase protected file and no passphrase was given.
+    Skipped,
+}
+
+impl VerifyStatus {
+    /// Returns `true` if the file did not verify. Skipped files are not failures.
+    pub fn is_failure(&self) -> bool {
+        !matches!(self, VerifyStatus::Ok | VerifyStatus::Skipped)
+    }
+}
+
+impl std::fmt::Display for VerifyStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyStatus::Ok => write!(f, "OK"),
+            VerifyStatus::Tampered => write!(f, "tampered"),
+            VerifyStatus::MissingKeeperEntry => write!(f, "missing keeper entry"),
+            VerifyStatus::WrongKey => write!(f, "wrong key"),
+            VerifyStatus::Skipped => write!(f, "skipped, passphrase required"),
+        }
+    }
+}
+
+/// `Vault::verify_file` of the default vault.
+pub fn verify_file<T, F>(path: T, passphrase: F) -> Result<VerifyStatus>
+where
+    T: AsRef<Path>,
+    F: FnOnce() -> Option<String>,
+{
+    default_vault()?.verify_file(path, passphrase)
+}
+
+/// Returns `true` if `fc` was recovered from the header of a derived key file rather than read
+/// from the keeper, so there is no hash to compare against.
+fn is_recovered(fc: &FileCrypt, header: &Header) -> bool {
+    header.has_flag(FLAG_DERIVED_KEY) && fc.hash == [0u8; KEY_SIZE]
+}
+
+/// `Vault::decrypt_contents` of the default vault.
+pub fn decrypt_contents(fc: FileCrypt, contents: Vec<u8>) -> Result<()> {
+    default_vault()?.decrypt_contents(fc, contents)
+}
+
+/// Decrypts and decompresses the payload following `header`, writing the plaintext into `writer`
+/// and verifying it against `fc.hash`. `dictionary` is the one the payload is compressed with,
+/// see `Vault::header_dictionary`.
 ///
-/// * `filename` - filename of the .crypt file residing in the crypt folder.
-/// * `output` - An optional output path for the decrypted content.
-/// * `conf` - An optional configuration, if not provided, the default configuration is used.
+/// Passphrase protected files are not tracked in the keeper, so only the AEAD tag is checked.
+///
+/// # Errors
+///
+/// Returns `FcError::Tampered` (or `FcError::WrongPassphrase` for passphrase protected files) if
+/// the payload fails authentication against the header and `fc`'s filename and extension.
+///
+/// Streamed payloads are processed chunk by chunk; older single-message payloads are read into
+/// memory first.
+pub(crate) fn decrypt_payload<R: Read, W: Write>(
+    fc: &FileCrypt,
+    header: &Header,
+    dictionary: Option<&Dictionary>,
+    mut reader: R,
+    mut writer: W,
+) -> Result<()> {
+    let aad = header.associated_data(&fc.filename, &fc.ext)?;
+    let res = if header.has_flag(FLAG_STREAM) {
+        decrypt_stream(
+            fc,
+            header.compression,
+            dictionary.map(|d| d.data.as_slice()),
+            header.has_flag(FLAG_PADDED),
+            &aad,
+            reader,
+            writer,
+        )
+    } else {
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+
+        decrypt(fc.clone(), &contents, &aad).and_then(|mut decrypted_content| {
+            if header.compression == Compression::Zstd {
+                decrypted_content = decompress(&decrypted_content)?;
+            }
+            writer.write_all(&decrypted_content)?;
+            Ok(compute_hash(&decrypted_content))
+        })
+    };
+
+    // portable files have no keeper hash, the AEAD tag is the integrity check
+    if header.has_flag(FLAG_PASSPHRASE) {
+        return match res {
+            Err(Error::EncryptionError(_)) => Err(Error::FcError(error::FcError::WrongPassphrase)),
+            res => res.map(|_| ()),
+        };
+    }
+    if header.has_flag(FLAG_RECIPIENTS) {
+        return match res {
+            Err(Error::EncryptionError(_)) => Err(Error::FcError(error::FcError::Tampered)),
+            res => res.map(|_| ()),
+        };
+    }
+
+    let hash = match res {
+        Err(Error::EncryptionError(error::EncryptionError::AeadError)) => {
+            return Err(Error::FcError(error::FcError::Tampered));
+        }
+        res => res?,
+    };
+    if is_recovered(fc, header) {
+        return Ok(());
+    }
+    if hash != fc.hash {
+        return Err(Error::FcError(error::FcError::HashFail(fc.hash, hash)));
+    }
+    Ok(())
+}
+
+/// `Vault::encrypt_file` of the default vault.
+pub fn encrypt_file(path: &str, output: &Option<String>) -> Result<()> {
+    default_vault()?.encrypt_file(path, output)
+}
+
+/// `Vault::encrypt_file_with_dictionary` of the default vault.
+pub fn encrypt_file_with_dictionary(
+    path: &str,
+    output: &Option<String>,
+    dictionary: &Dictionary,
+) -> Result<()> {
+    default_vault()?.encrypt_file_with_dictionary(path, output, dictionary)
+}
+
+/// `Vault::encrypt_reader` of the default vault.
+pub fn encrypt_reader<T: AsRef<Path>, R: Read>(
+    path: T,
+    reader: R,
+    output: &Option<String>,
+) -> Result<PathBuf> {
+    default_vault()?.encrypt_reader(path, reader, output)
+}
+
+/// `Vault::encrypt_to_writer` of the default vault.
+pub fn encrypt_to_writer<R: Read, W: Write>(name: &str, reader: R, writer: W) -> Result<FileCrypt> {
+    default_vault()?.encrypt_to_writer(name, reader, writer)
+}
+
+/// `Vault::decrypt_to_writer` of the default vault.
+pub fn decrypt_to_writer<R, W, F>(reader: R, writer: W, passphrase: F) -> Result<FileCrypt>
+where
+    R: Read,
+    W: Write,
+    F: FnOnce() -> Option<String>,
+{
+    default_vault()?.decrypt_to_writer(reader, writer, passphrase)
+}
+
+/// Encrypts `contents` in memory as the file `name`, without touching the disk or the keeper.
+/// The cipher, compression and padding are taken from `conf`, like `encrypt_to_writer` takes
+/// them from the config of the vault, but the key is always random, whatever
+/// `Config::key_mode` says.
 ///
 /// # Returns
 ///
-/// A `Result<(), FcError>` indicating success or an error with details.
+/// The `FileCrypt` holding the key, which the caller keeps to `open_contents` later, and the
+/// `.crypt` contents.
+///
+/// # Example
+///
+/// ```ignore
+/// # use crypt_core::{config::Config, filecrypt::{open_contents, seal_contents}};
+/// let (fc, sealed) = seal_contents(b"secret", "note.txt", &Config::default())?;
+/// assert_eq!(open_contents(&sealed, &fc)?, b"secret");
+/// ```
+pub fn seal_contents(contents: &[u8], name: &str, conf: &Config) -> Result<(FileCrypt, Vec<u8>)> {
+    let (mut fc, mut header) = stream_file_crypt(name, conf.cipher);
+
+    let mut sealed = Vec::new();
+    write_crypt(contents, &mut sealed, &mut fc, &mut header, None, conf)?;
+    Ok((fc, sealed))
+}
+
+/// Creates the `FileCrypt` and header of a stream named `name`, e.g. `dump.sql`, which has no
+/// path on disk.
+fn stream_file_crypt(name: &str, cipher: Cipher) -> (FileCrypt, Header) {
+    let index = name.find('.').unwrap_or(name.len());
+    let (filename, extension) = name.split_at(index);
+    let mut fc = FileCrypt::new(
+        filename.to_string(),
+        extension.to_string(),
+        "".to_string(),
+        PathBuf::new(),
+        [0u8; KEY_SIZE],
+    );
+    fc.set_cipher(cipher);
+
+    let mut header = Header::new(&fc.uuid);
+    header.cipher = fc.cipher;
+    header.flags |= FLAG_STREAM;
+    (fc, header)
+}
+
+/// Decrypts `.crypt` contents in memory with `fc`, without touching the disk or the keeper.
 ///
 /// # Errors
 ///
-/// This function returns various error types under the `FcError` enum:
-/// - `InvalidFilePath`: The provided file path is invalid.
-/// - `FileError(String)`: An error occurred while reading or writing the file.
-/// - `CryptQueryError`: Failed to query the cryptographic information.
-/// - `DecryptError(String)`: Failed to decrypt the file contents.
-/// - `DecompressionError`: Failed to decompress the decrypted content.
-/// - `HashFail(String)`: Hash comparison between file and decrypted content failed.
-/// - `FileDeletionError(std::io::Error, String)`: Failed to delete the original file.
-/// # Panics
+/// Returns `FcError::FileCryptMismatch` if the contents were not sealed under `fc`,
+/// `FcError::KeeperDictionary` if they need a keeper dictionary to decompress, or
+/// `FcError::Tampered` if they were modified.
+pub fn open_contents(contents: &[u8], fc: &FileCrypt) -> Result<Vec<u8>> {
+    let mut reader = contents;
+    let header = Header::read_from(&mut reader)?;
+    if header.uuid != fc.uuid {
+        return Err(Error::FcError(error::FcError::FileCryptMismatch));
+    }
+    if header.compression == Compression::ZstdDictionary {
+        return Err(Error::FcError(error::FcError::KeeperDictionary));
+    }
+
+    let mut opened = Vec::new();
+    decrypt_payload(fc, &header, None, reader, &mut opened)?;
+    Ok(opened)
+}
+
+/// `Vault::encrypt_archive` of the default vault.
+pub fn encrypt_archive<T: AsRef<Path>>(path: T, output: &Option<String>) -> Result<PathBuf> {
+    default_vault()?.encrypt_archive(path, output)
+}
+
+/// `Vault::list_archive` of the default vault.
+pub fn list_archive<T: AsRef<Path>>(path: T) -> Result<Vec<ArchiveEntry>> {
+    default_vault()?.list_archive(path)
+}
+
+/// `Vault::extract_archive` of the default vault.
+pub fn extract_archive<T: AsRef<Path>>(
+    path: T,
+    output: String,
+    members: &[String],
+) -> Result<PathBuf> {
+    default_vault()?.extract_archive(path, output, members)
+}
+
+/// Decrypts the archive payload following `header`, compressed with `dictionary`, into `dest`,
+/// creating it if needed. Existing files are replaced. Cleaning up after a failure is left to
+/// the caller.
+fn unpack_into<R: Read>(
+    fc: &FileCrypt,
+    header: &Header,
+    dictionary: Option<&Dictionary>,
+    reader: R,
+    dest: &Path,
+    members: &[String],
+) -> Result<usize> {
+    std::fs::create_dir_all(dest)?;
+    let mut unpacker = Unpacker::new(dest, members);
+    decrypt_payload(fc, header, dictionary, reader, &mut unpacker)?;
+    unpacker.finish()
+}
+
+/// A file for `encrypt_files` to encrypt.
+pub struct EncryptJob {
+    /// file to encrypt.
+    pub path: PathBuf,
+
+    /// subdirectory of the crypt folder to write the `.crypt` file to.
+    pub output: Option<String>,
+}
+
+/// What encrypting a keeper tracked file did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptStatus {
+    /// the file was not in the keeper and has been encrypted.
+    Encrypted,
+
+    /// the file changed since it was last encrypted and has been re-encrypted under its uuid.
+    Updated,
+
+    /// the file has not changed since it was last encrypted, nothing was written.
+    Unchanged,
+}
+
+/// Number of keeper rows `encrypt_files` writes per transaction.
+const INSERT_BATCH_SIZE: usize = 512;
+
+/// Outcome of `encrypt_path`: what was done, the `FileCrypt` to save in the keeper if any, and
+/// the `.crypt` file.
+type PathOutcome = (EncryptStatus, Option<FileCrypt>, PendingCrypt);
+
+/// `Vault::encrypt_files` of the default vault.
+pub fn encrypt_files(
+    jobs: &[EncryptJob],
+    dictionary: Option<&Dictionary>,
+) -> Result<Vec<Result<(EncryptStatus, PathBuf)>>> {
+    Ok(default_vault()?.encrypt_files(jobs, dictionary))
+}
+
+/// A `.crypt` file written by `write_crypt_file`, kept under a temporary name until its keeper
+/// row is in, see `Vault::settle_crypt_file`. A modified file keeps its uuid but gets a fresh
+/// nonce, so its previous `.crypt` file stays in place until then.
+struct PendingCrypt {
+    /// path of the `.crypt` file once settled.
+    path: PathBuf,
+
+    /// `true` while the file is still at `PendingCrypt::partial_path`.
+    partial: bool,
+
+    /// previous `.crypt` file of the same keeper entry, removed once the new one is in place.
+    replaces: Option<PathBuf>,
+}
+
+impl PendingCrypt {
+    /// Wraps a `.crypt` file that is already in place.
+    fn settled(path: PathBuf) -> Self {
+        PendingCrypt {
+            path,
+            partial: false,
+            replaces: None,
+        }
+    }
+
+    /// Gets the temporary path the file is written to, next to `path`.
+    fn partial_path(&self) -> PathBuf {
+        self.path.with_extension("partial")
+    }
+}
+
+/// Keeper state of a file about to be encrypted, see `tracked_state`.
+enum Tracked {
+    /// not in the keeper yet.
+    New,
+
+    /// in the keeper and its `.crypt` file is up to date. Holds the `.crypt` file, and the
+    /// keeper entry if its size and modification time need refreshing.
+    Unchanged(PathBuf, Option<FileCrypt>),
+
+    /// in the keeper but modified since, or its `.crypt` file is gone. Holds the keeper entry and
+    /// the old `.crypt` file, if any.
+    Modified(FileCrypt, Option<PathBuf>),
+}
+
+/// Gets the size and modification time, in nanoseconds since the Unix epoch, of the file at
+/// `path`.
+fn file_stat(path: &Path) -> Option<(u64, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), mtime.as_nanos() as u64))
+}
+
+/// `Vault::encrypt_file_with_passphrase` of the default vault.
+pub fn encrypt_file_with_passphrase(
+    path: &str,
+    output: &Option<String>,
+    passphrase: &str,
+) -> Result<()> {
+    default_vault()?.encrypt_file_with_passphrase(path, output, passphrase)
+}
+
+/// `Vault::encrypt_file_to_recipients` of the default vault.
+pub fn encrypt_file_to_recipients(
+    path: &str,
+    output: &Option<String>,
+    recipients: &[RecipientKey],
+) -> Result<()> {
+    default_vault()?.encrypt_file_to_recipients(path, output, recipients)
+}
+
+/// Flags `header` as padded unless `Config::padding` is `none`.
 ///
-/// This function may panic in case of critical errors, but most errors are returned in the `Result`.
-pub fn decrypt_file<T: AsRef<Path>>(path: T, output: String) -> Result<()> {
+/// # Returns
+///
+/// The padding to encrypt the payload with.
+fn apply_padding(header: &mut Header, conf: &Config) -> Padding {
+    let padding = conf.padding;
+    if padding != Padding::None {
+        header.flags |= FLAG_PADDED;
+    }
+    padding
+}
+
+/// Writes `header` and the encrypted contents of `reader` to `writer`, compressed and padded
+/// like `write_crypt_file`. Sets `fc.hash` to the hash of the plaintext.
+fn write_crypt<R: Read, W: Write>(
+    reader: R,
+    writer: &mut W,
+    fc: &mut FileCrypt,
+    header: &mut Header,
+    dictionary: Option<&Dictionary>,
+    conf: &Config,
+) -> Result<()> {
+    let (compression, reader) = choose_compression(&fc.ext, dictionary, reader, conf)?;
+    header.compression = compression;
+    push_dictionary(header, dictionary);
+    let padding = apply_padding(header, conf);
+
+    header.write_to(writer)?;
+    let aad = header.associated_data(&fc.filename, &fc.ext)?;
+    fc.hash = encrypt_stream(
+        fc,
+        header.compression,
+        dictionary.map(|d| d.data.as_slice()),
+        conf.zstd_level,
+        padding,
+        &aad,
+        reader,
+        writer,
+    )?;
+    Ok(())
+}
+
+/// Size of the sample `choose_compression` looks at.
+const COMPRESSION_SAMPLE_SIZE: u64 = 64 * 1024;
+
+/// Reader returned by `choose_compression`: the sample followed by the rest of the contents.
+type SampledReader<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Picks the compression for the contents of `reader`, the contents of a file with extension
+/// `ext`. Extensions listed in `Config::no_compress` and contents whose first
+/// `COMPRESSION_SAMPLE_SIZE` bytes do not compress (see `encryption::is_compressible`) are
+/// stored uncompressed. Contents that compress use `dictionary` if there is one.
+///
+/// # Returns
+///
+/// The compression and a reader yielding the full contents again, sample included.
+fn choose_compression<R: Read>(
+    ext: &str,
+    dictionary: Option<&Dictionary>,
+    mut reader: R,
+    conf: &Config,
+) -> Result<(Compression, SampledReader<R>)> {
+    let mut sample = Vec::new();
+    reader
+        .by_ref()
+        .take(COMPRESSION_SAMPLE_SIZE)
+        .read_to_end(&mut sample)?;
+
+    let dictionary_data = dictionary.map(|d| d.data.as_slice());
+    let compression = if conf.skips_compression(ext) || !is_compressible(&sample, dictionary_data) {
+        Compression::None
+    } else if dictionary.is_some() {
+        Compression::ZstdDictionary
+    } else {
+        Compression::Zstd
+    };
+    Ok((compression, Cursor::new(sample).chain(reader)))
+}
+
+/// A trained zstd dictionary stored in the keeper, see `train_file_dictionary`.
+pub struct Dictionary {
+    /// id the files compressed with the dictionary refer to it by.
+    pub id: u32,
+
+    /// the dictionary itself.
+    pub data: Vec<u8>,
+}
+
+/// Bytes read from the start of each file to train a dictionary on.
+const DICTIONARY_SAMPLE_SIZE: u64 = 128 * 1024;
+
+/// Longest stem of an encrypted `.crypt` file name, so the name and its temporary `.rotating` and
+/// `.partial` siblings stay within the 255 byte file name limit of common file systems.
+const MAX_SEALED_STEM: usize = 255 - ".rotating".len();
+
+/// `Vault::train_file_dictionary` of the default vault.
+pub fn train_file_dictionary(files: &[PathBuf]) -> Result<Dictionary> {
+    default_vault()?.train_file_dictionary(files)
+}
+
+/// Records the id of `dictionary` in `header` if the file is compressed with it.
+fn push_dictionary(header: &mut Header, dictionary: Option<&Dictionary>) {
+    if let (Compression::ZstdDictionary, Some(dictionary)) = (header.compression, dictionary) {
+        header.push_extension(EXT_DICTIONARY, dictionary.id.to_le_bytes().to_vec());
+    }
+}
+
+/// `Vault::rotate_file` of the default vault.
+pub fn rotate_file<T: AsRef<Path>>(path: T) -> Result<(FileCrypt, PathBuf)> {
+    default_vault()?.rotate_file(path)
+}
+
+/// `Vault::crypt_file_name` of the default vault.
+pub fn crypt_file_name(fc: &FileCrypt, portable: bool) -> Result<String> {
+    default_vault()?.crypt_file_name(fc, portable)
+}
+
+/// `Vault::resolve_crypt_name` of the default vault.
+pub fn resolve_crypt_name<T: AsRef<Path>>(path: T) -> Option<String> {
+    default_vault().ok()?.resolve_crypt_name(path)
+}
+
+pub fn create_file_crypt<T: AsRef<Path>>(path: T, contents: &[u8]) -> FileCrypt {
     let path = path.as_ref();
+    // parse out file path
+    let (fp, _, filename, extension) = get_file_info(path);
+    FileCrypt::new(
+        filename,
+        extension,
+        "".to_string(),
+        fp,
+        compute_hash(contents),
+    )
+}
+
+/// `Vault::zip_contents` of the default vault.
+pub fn zip_contents(contents: &[u8]) -> Result<Vec<u8>> {
+    default_vault()?.zip_contents(contents)
+}
+
+/// `Vault::do_file_encryption` of the default vault.
+pub fn do_file_encryption<T: AsRef<Path>>(path: T) -> Result<Vec<u8>> {
+    default_vault()?.do_file_encryption(path)
+}
+
+impl Vault {
+    /// Decrypts a file using ChaCha20Poly1305 encryption and verifies its integrity. Unless
+    /// `Config::retain` is set, the `.crypt` file is deleted once it decrypted successfully.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - filename of the .crypt file residing in the crypt folder.
+    /// * `output` - An optional output path for the decrypted content.
+    /// * `conf` - An optional configuration, if not provided, the default configuration is used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result<(), FcError>` indicating success or an error with details.
+    ///
+    /// # Errors
+    ///
+    /// This function returns various error types under the `FcError` enum:
+    /// - `InvalidFilePath`: The provided file path is invalid.
+    /// - `FileError(String)`: An error occurred while reading or writing the file.
+    /// - `CryptQueryError`: Failed to query the cryptographic information.
+    /// - `DecryptError(String)`: Failed to decrypt the file contents.
+    /// - `DecompressionError`: Failed to decompress the decrypted content.
+    /// - `HashFail(String)`: Hash comparison between file and decrypted content failed.
+    /// - `FileDeletionError(std::io::Error, String)`: Failed to delete the original file.
+    /// # Panics
+    ///
+    /// This function may panic in case of critical errors, but most errors are returned in the `Result`.
+    pub fn decrypt_file<T: AsRef<Path>>(&self, path: T, output: String) -> Result<()> {
+        self.decrypt_file_with(path, output, || None)
+    }
+
+    /// Decrypts a file like `decrypt_file`, using `passphrase` to derive the key of passphrase
+    /// protected files. Files tracked in the keeper are decrypted as usual.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcError::WrongPassphrase` if the passphrase does not decrypt the file.
+    pub fn decrypt_file_with_passphrase<T: AsRef<Path>>(
+        &self,
+        path: T,
+        output: String,
+        passphrase: &str,
+    ) -> Result<()> {
+        self.decrypt_file_with(path, output, || Some(passphrase.to_string()))
+    }
+
+    /// Decrypts a file like `decrypt_file`. `passphrase` is only called once the chosen file turns
+    /// out to be passphrase protected, which lets callers prompt the user lazily.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcError::PassphraseRequired` if the file needs a passphrase and `passphrase` returns
+    /// `None`, or `FcError::WrongPassphrase` if the passphrase does not decrypt the file.
+    pub fn decrypt_file_with<T, F>(&self, path: T, output: String, passphrase: F) -> Result<()>
+    where
+        T: AsRef<Path>,
+        F: FnOnce() -> Option<String>,
+    {
+        let path = path.as_ref();
+        // have user choose
+        let file_match = self.chooser(path.to_str().unwrap_or(""))?;
+        self.decrypt_crypt_file(file_match, output, passphrase)
+    }
+
+    /// Decrypts the `.crypt` file at `crypt_path` like `decrypt_file_with`, without asking the user
+    /// to choose a file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcError::PassphraseRequired` if the file needs a passphrase and `passphrase` returns
+    /// `None`, or `FcError::WrongPassphrase` if the passphrase does not decrypt the file.
+    pub fn decrypt_crypt_file<T, F>(
+        &self,
+        crypt_path: T,
+        output: String,
+        passphrase: F,
+    ) -> Result<()>
+    where
+        T: AsRef<Path>,
+        F: FnOnce() -> Option<String>,
+    {
+        let file_match = crypt_path.as_ref();
+        let mut reader = BufReader::new(File::open(file_match)?);
+
+        // reject anything that is not a crypt file before touching the keeper
+        let header = Header::read_from(&mut reader)?;
+
+        let fc = self.resolve_file_crypt(&header, passphrase)?;
+
+        // archives extract into a folder of their own
+        if header.has_flag(FLAG_ARCHIVE) {
+            self.unpack_archive(&fc, &header, reader, output, &[])?;
+            return self.discard_crypt(file_match);
+        }
+
+        // make sure we put decrypted file in the "decrypted" folder, dummy.
+        // get location of crypt folder and append "decrypted" path
+        let mut crypt_folder = self.get_crypt_folder();
+        crypt_folder.push("decrypted");
+        let file = generate_output_file(&fc, output, &mut crypt_folder);
+
+        let dictionary = self.header_dictionary(&header)?;
+        let mut writer = BufWriter::new(File::create(&file)?);
+        let res = decrypt_payload(&fc, &header, dictionary.as_ref(), reader, &mut writer).and_then(
+            |_| {
+                writer.flush()?;
+                Ok(())
+            },
+        );
+
+        // never leave a partially decrypted or tampered file behind
+        if res.is_err() {
+            drop(writer);
+            _ = std::fs::remove_file(&file);
+        }
+        res?;
+        self.discard_crypt(file_match)
+    }
+
+    /// Deletes the `.crypt` file at `crypt_path` once it has been decrypted, unless
+    /// `Config::retain` is set, along with its backup. The keeper entry stays, so encrypting the
+    /// file again reuses it.
+    fn discard_crypt(&self, crypt_path: &Path) -> Result<()> {
+        if self.config().retain {
+            return Ok(());
+        }
+        std::fs::remove_file(crypt_path).map_err(|e| {
+            Error::FcError(error::FcError::FileDeletionError(
+                e,
+                crypt_path.display().to_string(),
+            ))
+        })?;
+        self.remove_backup(crypt_path);
+        Ok(())
+    }
+
+    /// Deletes `original` once the `.crypt` file at `crypt_path` written from it verifies, unless
+    /// `Config::retain` is set. The original is overwritten first, see `common::shred_file`.
+    /// `passphrase` is handed to `verify_file` for passphrase protected files.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcError::Unverified`, and keeps the original, if the `.crypt` file does not verify.
+    fn discard_original<F>(&self, original: &Path, crypt_path: &Path, passphrase: F) -> Result<()>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        if self.config().retain {
+            return Ok(());
+        }
+        if self.verify_file(crypt_path, passphrase)? != VerifyStatus::Ok {
+            return Err(Error::FcError(error::FcError::Unverified(
+                crypt_path.display().to_string(),
+            )));
+        }
+        shred_file(original)
+    }
+
+    /// Decrypts the `.crypt` file at `crypt_path` back to the location it was encrypted from, the
+    /// `full_path` recorded in the keeper, or to that location under `prefix` (see
+    /// `restore_target`). Archives restore the whole directory tree. `policy` decides what happens
+    /// when the location is taken.
+    ///
+    /// Files are decrypted next to their location and renamed into place, so a file that fails
+    /// authentication leaves nothing behind and an overwritten file is never half written. Archives
+    /// are extracted in place. Unless `Config::retain` is set, the `.crypt` file is deleted once
+    /// restored.
+    ///
+    /// # Returns
+    ///
+    /// What was done and the location restored to, or that would have been restored to if skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcError::NotKeeperTracked` for passphrase or recipient files, which record no
+    /// location.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::filecrypt::{restore_file, ConflictPolicy};
+    /// let (status, path) = restore_file("crypt/report.crypt", ConflictPolicy::Rename, None)?;
+    /// ```
+    pub fn restore_file<T: AsRef<Path>>(
+        &self,
+        crypt_path: T,
+        policy: ConflictPolicy,
+        prefix: Option<&Path>,
+    ) -> Result<(RestoreStatus, PathBuf)> {
+        let crypt_path = crypt_path.as_ref();
+        let mut reader = BufReader::new(File::open(crypt_path)?);
+        let header = Header::read_from(&mut reader)?;
+        let fc = self.resolve_file_crypt(&header, || None)?;
+        if fc.full_path.as_os_str().is_empty() {
+            return Err(Error::FcError(error::FcError::NotKeeperTracked));
+        }
+
+        let mut target = restore_target(&fc.full_path, prefix);
+        let taken = std::fs::symlink_metadata(&target).is_ok();
+        let status = match (taken, policy) {
+            (false, _) => RestoreStatus::Restored,
+            (true, ConflictPolicy::Skip) => return Ok((RestoreStatus::Skipped, target)),
+            (true, ConflictPolicy::Overwrite) => RestoreStatus::Overwritten,
+            (true, ConflictPolicy::Rename) => {
+                target = free_path(&fc, &target);
+                RestoreStatus::Renamed
+            }
+        };
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let dictionary = self.header_dictionary(&header)?;
+        if header.has_flag(FLAG_ARCHIVE) {
+            let res = unpack_into(&fc, &header, dictionary.as_ref(), reader, &target, &[]);
+            // only a tree we created is ours to remove
+            if res.is_err() && status != RestoreStatus::Overwritten {
+                _ = std::fs::remove_dir_all(&target);
+            }
+            res?;
+        } else {
+            let mut name = target.file_name().unwrap_or_default().to_os_string();
+            name.push(".restoring");
+            let partial = target.with_file_name(name);
+
+            let res = (|| -> Result<()> {
+                let mut writer = BufWriter::new(File::create(&partial)?);
+                decrypt_payload(&fc, &header, dictionary.as_ref(), reader, &mut writer)?;
+                let file = writer.into_inner().map_err(|e| e.into_error())?;
+                if fc.mtime != 0 {
+                    _ = file.set_modified(UNIX_EPOCH + Duration::from_nanos(fc.mtime));
+                }
+                drop(file);
+                std::fs::rename(&partial, &target)?;
+                Ok(())
+            })();
+            if res.is_err() {
+                _ = std::fs::remove_file(&partial);
+            }
+            res?;
+        }
+
+        self.discard_crypt(crypt_path)?;
+        Ok((status, target))
+    }
+
+    /// Gets the `FileCrypt` needed to decrypt the file described by `header`.
+    ///
+    /// Keeper tracked files are looked up by uuid. Portable files carry everything but the key in
+    /// their header: the key of passphrase protected files is derived from the passphrase returned
+    /// by `passphrase`, the key of files encrypted to recipients is unwrapped with our identity.
+    /// Derived key files missing from the keeper are recovered the same way, with the key secret.
+    pub(crate) fn resolve_file_crypt<F>(&self, header: &Header, passphrase: F) -> Result<FileCrypt>
+    where
+        F: FnOnce() -> Option<String>,
+    {
+        if !header.is_portable() {
+            let fc = match self.query_crypt(header.uuid.clone()) {
+                Err(Error::DbError(rusqlite::Error::QueryReturnedNoRows))
+                    if header.has_flag(FLAG_DERIVED_KEY) =>
+                {
+                    // the keeper entry is gone, the header and the key secret are enough
+                    let key = derive_file_key(&self.key_secret()?, &header.uuid);
+                    return Ok(FileCrypt {
+                        key_mode: KeyMode::Derived,
+                        ..file_crypt_from_header(header, key)?
+                    });
+                }
+                res => res?,
+            };
+            if fc.cipher != header.cipher {
+                return Err(Error::FcError(error::FcError::InvalidHeader(
+                    "cipher does not match the keeper",
+                )));
+            }
+            return Ok(fc);
+        }
+
+        let key = if header.has_flag(FLAG_RECIPIENTS) {
+            let identity = self
+                .query_identity()?
+                .ok_or(Error::DatabaseError(error::DatabaseError::NoIdentity))?;
+            unwrap_recipient_key(header, &identity)?
+        } else {
+            let passphrase =
+                passphrase().ok_or(Error::FcError(error::FcError::PassphraseRequired))?;
+            header
+                .extension(EXT_KDF)
+                .ok_or(Error::FcError(error::FcError::InvalidHeader(
+                    "missing kdf parameters",
+                )))
+                .and_then(KdfParams::from_bytes)?
+                .derive_key(&passphrase)?
+        };
+
+        file_crypt_from_header(header, key)
+    }
+
+    /// Checks that a `.crypt` file decrypts and matches the hash recorded in the keeper, without
+    /// writing the plaintext anywhere.
+    ///
+    /// The payload is decrypted and decompressed chunk by chunk and only hashed. Portable files have
+    /// no keeper hash, for them the AEAD tag is the check.
+    ///
+    /// # Returns
+    ///
+    /// The `VerifyStatus` of the file. Failures to read the file are returned as errors.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::filecrypt::{verify_file, VerifyStatus};
+    /// assert_eq!(verify_file("crypt/report.crypt", || None)?, VerifyStatus::Ok);
+    /// ```
+    pub fn verify_file<T, F>(&self, path: T, passphrase: F) -> Result<VerifyStatus>
+    where
+        T: AsRef<Path>,
+        F: FnOnce() -> Option<String>,
+    {
+        let mut reader = BufReader::new(File::open(path)?);
+        let res = Header::read_from(&mut reader).and_then(|header| {
+            let fc = self.resolve_file_crypt(&header, passphrase)?;
+            let dictionary = self.header_dictionary(&header)?;
+            decrypt_payload(&fc, &header, dictionary.as_ref(), reader, std::io::sink())
+        });
+
+        let status = match res {
+            Ok(()) => VerifyStatus::Ok,
+            Err(Error::DbError(rusqlite::Error::QueryReturnedNoRows)) => {
+                VerifyStatus::MissingKeeperEntry
+            }
+            // the keeper key does not unwrap under the master key
+            Err(Error::DbError(rusqlite::Error::UserFunctionError(_)))
+            | Err(Error::EncryptionError(error::EncryptionError::KeyUnwrapError))
+            | Err(Error::DatabaseError(error::DatabaseError::NoIdentity))
+            | Err(Error::FcError(error::FcError::WrongPassphrase))
+            | Err(Error::FcError(error::FcError::NotARecipient)) => VerifyStatus::WrongKey,
+            Err(Error::FcError(error::FcError::PassphraseRequired)) => VerifyStatus::Skipped,
+            Err(Error::EncryptionError(_))
+            | Err(Error::FcError(
+                error::FcError::Tampered
+                | error::FcError::HashFail(..)
+                | error::FcError::NotACryptFile
+                | error::FcError::InvalidHeader(_)
+                | error::FcError::DecompressionError(_),
+            )) => VerifyStatus::Tampered,
+            Err(e) => return Err(e),
+        };
+        Ok(status)
+    }
+
+    pub fn decrypt_contents(&self, fc: FileCrypt, contents: Vec<u8>) -> Result<()> {
+        // get location of crypt folder and append "decrypted" path
+        let mut crypt_folder = self.get_crypt_folder();
+        crypt_folder.push("decrypted");
+
+        // get output file
+        let file = generate_output_file(&fc, String::new(), &mut PathBuf::from(&crypt_folder));
+
+        // strip out header from contents
+        let (header, stripped_contents) = Header::from_bytes(&contents)?;
+
+        // Decrypt, unzip and verify contents
+        let dictionary = self.header_dictionary(&header)?;
+        let mut decrypted_content = Vec::new();
+        decrypt_payload(
+            &fc,
+            &header,
+            dictionary.as_ref(),
+            stripped_contents,
+            &mut decrypted_content,
+        )?;
+
+        // Write contents to file
+        write_contents_to_file(file, decrypted_content)?;
+
+        Ok(())
+    }
+
+    /// Encrypts the contents of a file and performs additional operations based on the provided configuration.
+    ///
+    /// Files already in the keeper are encrypted incrementally: an unchanged file is skipped, a
+    /// modified one is re-encrypted under its existing uuid and keeper row (see `tracked_state`).
+    ///
+    /// Unless `Config::retain` is set, the original is deleted once its `.crypt` file verifies.
+    ///
+    /// # Arguments
+    ///
+    /// * `conf` - A reference to a Config struct containing encryption and configuration settings.
+    /// * `path` - A string representing the path to the file to be encrypted.
+    /// * `in_place` - A boolean indicating whether to perform in-place encryption.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_lib::{Config, load_config};
+    /// # use crypt_lib::encryption::{encrypt_file};
+    ///
+    /// let path = "/path/to/your/file.txt";
+    /// encrypt_file(&conf, path, false);
+    /// ```
+    pub fn encrypt_file(&self, path: &str, output: &Option<String>) -> Result<()> {
+        let (_, fc, pending) =
+            self.encrypt_path(Path::new(path), output, None, &self.index_crypt_folder())?;
+        let inserted = fc.map_or(Ok(()), |fc| self.insert_crypt(&fc));
+        let crypt_path = self.settle_crypt_file(pending, inserted)?;
+        self.discard_original(Path::new(path), &crypt_path, || None)
+    }
+
+    /// Encrypts a file like `encrypt_file`, compressing it with a trained zstd dictionary (see
+    /// `train_file_dictionary`). The file refers to the dictionary by id, it decrypts transparently
+    /// as long as the dictionary is in the keeper.
+    pub fn encrypt_file_with_dictionary(
+        &self,
+        path: &str,
+        output: &Option<String>,
+        dictionary: &Dictionary,
+    ) -> Result<()> {
+        let index = self.index_crypt_folder();
+        let (_, fc, pending) =
+            self.encrypt_path(Path::new(path), output, Some(dictionary), &index)?;
+        let inserted = fc.map_or(Ok(()), |fc| self.insert_crypt(&fc));
+        let crypt_path = self.settle_crypt_file(pending, inserted)?;
+        self.discard_original(Path::new(path), &crypt_path, || None)
+    }
+
+    /// Encrypts everything read from `reader` into a keeper tracked `.crypt` file, as if it were the
+    /// contents of the file at `path`. The file at `path` does not need to exist. A keeper entry for
+    /// `path` is reused, like for a modified file in `encrypt_file`.
+    ///
+    /// # Returns
+    ///
+    /// The path of the written `.crypt` file.
+    pub fn encrypt_reader<T: AsRef<Path>, R: Read>(
+        &self,
+        path: T,
+        reader: R,
+        output: &Option<String>,
+    ) -> Result<PathBuf> {
+        let path = path.as_ref();
+        let previous = self.previous_entry(path)?;
+        let (fc, pending) = self.write_tracked(path, reader, output, None, previous, 0)?;
+
+        // write fc to crypt_keeper
+        let inserted = self.insert_crypt(&fc);
+        self.settle_crypt_file(pending, inserted)
+    }
+
+    /// Encrypts everything read from `reader` into a keeper tracked `.crypt` stream written to
+    /// `writer`, e.g. from stdin to stdout. Nothing is written to the crypt folder. `name` is recorded
+    /// as the file name, there is no recorded location.
+    ///
+    /// The keeper entry is inserted once the whole stream is written, the stream does not decrypt
+    /// before then.
+    ///
+    /// # Returns
+    ///
+    /// The `FileCrypt` inserted into the keeper.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::filecrypt::encrypt_to_writer;
+    /// let fc = encrypt_to_writer("dump.sql", std::io::stdin().lock(), std::io::stdout().lock())?;
+    /// ```
+    pub fn encrypt_to_writer<R: Read, W: Write>(
+        &self,
+        name: &str,
+        reader: R,
+        mut writer: W,
+    ) -> Result<FileCrypt> {
+        let conf = self.config();
+        let (mut fc, mut header) = stream_file_crypt(name, conf.cipher);
+        self.apply_key_mode(&mut fc, &mut header)?;
+
+        write_crypt(reader, &mut writer, &mut fc, &mut header, None, &conf)?;
+        writer.flush()?;
+
+        // write fc to crypt_keeper
+        self.insert_crypt(&fc)?;
+        Ok(fc)
+    }
+
+    /// Decrypts a `.crypt` stream read from `reader` into `writer`, e.g. from stdin to stdout.
+    /// `passphrase` is only called if the stream turns out to be passphrase protected, like in
+    /// `decrypt_file_with`.
+    ///
+    /// The plaintext is written as it is authenticated, chunk by chunk. On error, what was already
+    /// written must be discarded: the rest of the stream failed authentication or the hash check.
+    ///
+    /// # Returns
+    ///
+    /// The `FileCrypt` the stream was decrypted with.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcError::ArchiveStream` for archives, which only extract to a folder.
+    pub fn decrypt_to_writer<R, W, F>(
+        &self,
+        reader: R,
+        mut writer: W,
+        passphrase: F,
+    ) -> Result<FileCrypt>
+    where
+        R: Read,
+        W: Write,
+        F: FnOnce() -> Option<String>,
+    {
+        let mut reader = BufReader::new(reader);
+        let header = Header::read_from(&mut reader)?;
+        if header.has_flag(FLAG_ARCHIVE) {
+            return Err(Error::FcError(error::FcError::ArchiveStream));
+        }
+        let fc = self.resolve_file_crypt(&header, passphrase)?;
+        let dictionary = self.header_dictionary(&header)?;
 
-    // have user choose
-    let file_match = chooser(path.to_str().unwrap_or(""))?;
+        decrypt_payload(&fc, &header, dictionary.as_ref(), reader, &mut writer)?;
+        writer.flush()?;
+        Ok(fc)
+    }
 
-    let content = read(file_match)?;
+    /// Packs the directory at `path` into a single keeper tracked `.crypt` file, named after the
+    /// directory. Paths, permissions and empty directories are kept in an index inside the encrypted
+    /// payload (see `archive`), so the file leaks neither names nor structure.
+    ///
+    /// Archiving the same directory again reuses its keeper entry.
+    ///
+    /// # Returns
+    ///
+    /// The path of the written `.crypt` file.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::filecrypt::encrypt_archive;
+    /// encrypt_archive("./docs", &None)?;
+    /// ```
+    pub fn encrypt_archive<T: AsRef<Path>>(
+        &self,
+        path: T,
+        output: &Option<String>,
+    ) -> Result<PathBuf> {
+        let path = path.as_ref();
+        let reader = pack_directory(path)?;
+        let previous = self.previous_entry(path)?;
+        let (fc, pending) =
+            self.write_tracked(path, reader, output, None, previous, FLAG_ARCHIVE)?;
+
+        let inserted = self.insert_crypt(&fc);
+        self.settle_crypt_file(pending, inserted)
+    }
 
-    let (uuid, contents) = get_uuid(&content)?;
+    /// Lists the members of an archive written by `encrypt_archive`. Only the index at the start of
+    /// the payload is decrypted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcError::NotAnArchive` if the chosen file is not an archive.
+    pub fn list_archive<T: AsRef<Path>>(&self, path: T) -> Result<Vec<ArchiveEntry>> {
+        let file = self.chooser(path.as_ref().to_str().unwrap_or(""))?;
+        self.archive_index(&file)
+    }
 
-    let fc = match query_crypt(uuid) {
-        Ok(f) => f,
-        Err(e) => panic!("{}", e.to_string()),
-    };
+    /// Reads the index of the archive at `file`, see `list_archive`.
+    fn archive_index(&self, file: &Path) -> Result<Vec<ArchiveEntry>> {
+        let (fc, header, reader) = self.open_archive(file)?;
+        let aad = header.associated_data(&fc.filename, &fc.ext)?;
+        let dictionary = self.header_dictionary(&header)?;
+        let mut source = plaintext_reader(
+            &fc,
+            header.compression,
+            dictionary.as_ref().map(|d| d.data.as_slice()),
+            header.has_flag(FLAG_PADDED),
+            &aad,
+            reader,
+        )?;
+
+        read_index(&mut source).map_err(|e| match from_stream_error(e) {
+            Error::EncryptionError(error::EncryptionError::AeadError) => {
+                Error::FcError(error::FcError::Tampered)
+            }
+            e => e,
+        })
+    }
 
-    let fc_hash: [u8; 32] = fc.hash.to_owned();
+    /// Extracts `members` of an archive written by `encrypt_archive`, or the whole tree if `members`
+    /// is empty, into `<crypt folder>/decrypted/<output>/<directory name>`. A member that is a
+    /// directory brings everything below it.
+    ///
+    /// # Returns
+    ///
+    /// The folder the members were extracted to.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcError::ArchiveMemberNotFound` before extracting anything if a member is not in the
+    /// archive.
+    pub fn extract_archive<T: AsRef<Path>>(
+        &self,
+        path: T,
+        output: String,
+        members: &[String],
+    ) -> Result<PathBuf> {
+        let file = self.chooser(path.as_ref().to_str().unwrap_or(""))?;
+        if !members.is_empty() {
+            let index = self.archive_index(&file)?;
+            if let Some(missing) = members.iter().find(|m| !index.iter().any(|e| e.is_in(m))) {
+                return Err(Error::FcError(error::FcError::ArchiveMemberNotFound(
+                    missing.to_string(),
+                )));
+            }
+        }
 
-    // make sure we put decrypted file in the "decrypted" folder, dummy.
-    // get location of crypt folder and append "decrypted" path
-    let mut crypt_folder = get_crypt_folder();
-    crypt_folder.push("decrypted");
-    let file = generate_output_file(&fc, output, &mut crypt_folder);
-    dbg!(&file);
+        let (fc, header, reader) = self.open_archive(&file)?;
+        self.unpack_archive(&fc, &header, reader, output, members)
+    }
 
-    let mut decrypted_content = decrypt(fc.clone(), &contents.to_vec())?;
-    decrypted_content = decompress(&decrypted_content)?;
+    /// Opens the archive at `file` and resolves its key.
+    fn open_archive(&self, file: &Path) -> Result<(FileCrypt, Header, BufReader<File>)> {
+        let mut reader = BufReader::new(File::open(file)?);
+        let header = Header::read_from(&mut reader)?;
+        if !header.has_flag(FLAG_ARCHIVE) {
+            return Err(Error::FcError(error::FcError::NotAnArchive));
+        }
+        let fc = self.resolve_file_crypt(&header, || None)?;
+        Ok((fc, header, reader))
+    }
 
-    let hash = compute_hash(&decrypted_content);
+    /// Decrypts the archive payload following `header` into a new folder under
+    /// `<crypt folder>/decrypted`, see `extract_archive`. The folder is removed if the archive fails
+    /// authentication.
+    fn unpack_archive<R: Read>(
+        &self,
+        fc: &FileCrypt,
+        header: &Header,
+        reader: R,
+        output: String,
+        members: &[String],
+    ) -> Result<PathBuf> {
+        let mut crypt_folder = self.get_crypt_folder();
+        crypt_folder.push("decrypted");
+        let dest = PathBuf::from(generate_output_file(fc, output, &mut crypt_folder));
+
+        // never leave a partially extracted or tampered tree behind
+        let res = self.header_dictionary(header).and_then(|dictionary| {
+            unpack_into(fc, header, dictionary.as_ref(), reader, &dest, members)
+        });
+        if let Err(e) = res {
+            _ = std::fs::remove_dir_all(&dest);
+            return Err(e);
+        }
+        Ok(dest)
+    }
 
-    if hash != fc_hash {
-        return Err(Error::FcError(error::FcError::HashFail(fc_hash, hash)));
+    /// Gets the keeper entry of the file at `path` and its current `.crypt` file, for `write_tracked`
+    /// to reuse.
+    fn previous_entry(&self, path: &Path) -> Result<Option<(FileCrypt, Option<PathBuf>)>> {
+        match self.query_keeper_for_existing_file(get_full_file_path(path)) {
+            Ok(fc) => {
+                let old_path = self.index_crypt_folder().remove(&fc.uuid);
+                Ok(Some((fc, old_path)))
+            }
+            Err(Error::DbError(rusqlite::Error::QueryReturnedNoRows)) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
-    write_contents_to_file(&file, decrypted_content)?;
 
-    Ok(())
-}
+    /// Encrypts many files like `encrypt_file`, spread over one worker per core (see
+    /// `common::parallel_map`), optionally compressed with `dictionary`.
+    ///
+    /// Workers hand their `FileCrypt`s to a single writer thread, which inserts them into the keeper
+    /// `INSERT_BATCH_SIZE` at a time. Originals are deleted once the keeper is up to date, unless
+    /// `Config::retain` is set.
+    ///
+    /// # Returns
+    ///
+    /// What was done with each file and the path of its `.crypt` file, or why encrypting it failed,
+    /// in the order of `jobs`.
+    pub fn encrypt_files(
+        &self,
+        jobs: &[EncryptJob],
+        dictionary: Option<&Dictionary>,
+    ) -> Vec<Result<(EncryptStatus, PathBuf)>> {
+        let index = self.index_crypt_folder();
+        let (sender, receiver) = mpsc::channel();
+
+        let results = std::thread::scope(|s| {
+            let writer = s.spawn(move || self.insert_batches(receiver, jobs.len()));
+
+            let indexed: Vec<(usize, &EncryptJob)> = jobs.iter().enumerate().collect();
+            parallel_map(&indexed, |&(i, job)| {
+                let res = self.encrypt_path(&job.path, &job.output, dictionary, &index);
+                _ = sender.send((i, res));
+            });
+            drop(sender);
+
+            writer.join().expect("keeper writer thread panicked")
+        });
+        if self.config().retain {
+            return results;
+        }
 
-pub fn decrypt_contents(fc: FileCrypt, contents: Vec<u8>) -> Result<()> {
-    let fc_hash: [u8; 32] = fc.hash.to_owned();
+        // verifying needs the keeper rows, so originals go once every batch is in
+        let written: Vec<Option<(&Path, &Path)>> = jobs
+            .iter()
+            .zip(&results)
+            .map(|(job, res)| {
+                res.as_ref()
+                    .ok()
+                    .map(|(_, p)| (job.path.as_path(), p.as_path()))
+            })
+            .collect();
+        let discarded = parallel_map(&written, |w| {
+            w.map_or(Ok(()), |(path, crypt_path)| {
+                self.discard_original(path, crypt_path, || None)
+            })
+        });
+        results
+            .into_iter()
+            .zip(discarded)
+            .map(|(res, discarded)| res.and_then(|r| discarded.map(|_| r)))
+            .collect()
+    }
 
-    // get location of crypt folder and append "decrypted" path
-    let mut crypt_folder = get_crypt_folder();
-    crypt_folder.push("decrypted");
+    /// Receives the results of `encrypt_files` and inserts the `FileCrypt`s into the keeper in
+    /// batches.
+    fn insert_batches(
+        &self,
+        receiver: mpsc::Receiver<(usize, Result<PathOutcome>)>,
+        count: usize,
+    ) -> Vec<Result<(EncryptStatus, PathBuf)>> {
+        let mut results: Vec<Option<Result<(EncryptStatus, PathBuf)>>> =
+            (0..count).map(|_| None).collect();
+        let mut batch = Vec::with_capacity(INSERT_BATCH_SIZE);
+
+        let flush =
+            |batch: &mut Vec<(usize, EncryptStatus, FileCrypt, PendingCrypt)>,
+             results: &mut Vec<Option<Result<(EncryptStatus, PathBuf)>>>| {
+                let crypts: Vec<FileCrypt> = batch.iter().map(|(_, _, fc, _)| fc.clone()).collect();
+                let batch_ok = self.insert_crypts(&crypts).is_ok();
+                for (i, status, fc, pending) in batch.drain(..) {
+                    // a failed batch is retried row by row, so only the rows at fault report an error
+                    let inserted = match batch_ok {
+                        true => Ok(()),
+                        false => self.insert_crypt(&fc),
+                    };
+                    results[i] = Some(
+                        self.settle_crypt_file(pending, inserted)
+                            .map(|path| (status, path)),
+                    );
+                }
+            };
 
-    // get output file
-    let file = generate_output_file(&fc, String::new(), &mut PathBuf::from(&crypt_folder));
+        for (i, res) in receiver {
+            match res {
+                Ok((status, Some(fc), pending)) => batch.push((i, status, fc, pending)),
+                Ok((status, None, pending)) => {
+                    results[i] = Some(self.settle_crypt_file(pending, Ok(())).map(|p| (status, p)))
+                }
+                Err(e) => results[i] = Some(Err(e)),
+            }
+            if batch.len() >= INSERT_BATCH_SIZE {
+                flush(&mut batch, &mut results);
+            }
+        }
+        flush(&mut batch, &mut results);
 
-    // strip out uuid from contents
-    let (_uuid, stripped_contents) = get_uuid(&contents)?;
+        results
+            .into_iter()
+            .map(|r| r.expect("every job reports a result"))
+            .collect()
+    }
 
-    // Decrypt contents
-    let mut decrypted_content =
-        decrypt(fc.clone(), &stripped_contents.to_vec()).expect("failed decryption");
+    /// Compares the file at `path` with its keeper entry, looked up by full path.
+    ///
+    /// The file is unchanged if its size and modification time match the keeper. A file that was
+    /// only touched, or encrypted before sizes were recorded, is also unchanged if its hash matches.
+    /// `index` maps uuids to `.crypt` files, see `index_crypt_folder`.
+    fn tracked_state(&self, path: &Path, index: &HashMap<String, PathBuf>) -> Result<Tracked> {
+        let mut fc = match self.query_keeper_for_existing_file(get_full_file_path(path)) {
+            Ok(fc) => fc,
+            Err(Error::DbError(rusqlite::Error::QueryReturnedNoRows)) => return Ok(Tracked::New),
+            Err(e) => return Err(e),
+        };
+        let Some(crypt_path) = index.get(&fc.uuid).cloned() else {
+            // the .crypt file is gone, write it again
+            return Ok(Tracked::Modified(fc, None));
+        };
+        let Some((size, mtime)) = file_stat(path) else {
+            return Ok(Tracked::Modified(fc, Some(crypt_path)));
+        };
 
-    // unzip contents
-    decrypted_content = decompress(&decrypted_content)?;
+        if size == fc.size && mtime == fc.mtime {
+            return Ok(Tracked::Unchanged(crypt_path, None));
+        }
+        if (size == fc.size || fc.mtime == 0) && compute_file_hash(path)? == fc.hash {
+            fc.size = size;
+            fc.mtime = mtime;
+            return Ok(Tracked::Unchanged(crypt_path, Some(fc)));
+        }
+        Ok(Tracked::Modified(fc, Some(crypt_path)))
+    }
 
-    // compute hash on contents
-    let hash = compute_hash(&decrypted_content);
+    /// Encrypts the file at `path` into a keeper tracked `.crypt` file unless it is unchanged, see
+    /// `tracked_state`. The keeper is left to the caller.
+    fn encrypt_path(
+        &self,
+        path: &Path,
+        output: &Option<String>,
+        dictionary: Option<&Dictionary>,
+        index: &HashMap<String, PathBuf>,
+    ) -> Result<PathOutcome> {
+        let (status, previous) = match self.tracked_state(path, index)? {
+            Tracked::Unchanged(crypt_path, fc) => {
+                return Ok((
+                    EncryptStatus::Unchanged,
+                    fc,
+                    PendingCrypt::settled(crypt_path),
+                ))
+            }
+            Tracked::Modified(fc, old_path) => (EncryptStatus::Updated, Some((fc, old_path))),
+            Tracked::New => (EncryptStatus::Encrypted, None),
+        };
 
-    // verify file integrity
-    if hash != fc_hash {
-        return Err(Error::FcError(error::FcError::HashFail(fc_hash, hash)));
+        let reader = BufReader::new(File::open(path)?);
+        let (fc, pending) = self.write_tracked(path, reader, output, dictionary, previous, 0)?;
+        Ok((status, Some(fc), pending))
     }
-    // Write contents to file
-    write_contents_to_file(file, decrypted_content)?;
 
-    Ok(())
-}
+    /// Maps the uuid in the header of every `.crypt` file in the crypt folder to its path.
+    fn index_crypt_folder(&self) -> HashMap<String, PathBuf> {
+        let Ok((files, _)) = self.walk_crypt_folder() else {
+            return HashMap::new();
+        };
+        files
+            .into_iter()
+            .filter(|p| p.extension().is_some_and(|e| e == "crypt"))
+            .filter_map(|p| {
+                let mut reader = BufReader::new(File::open(&p).ok()?);
+                let header = Header::read_from(&mut reader).ok()?;
+                Some((header.uuid, p))
+            })
+            .collect()
+    }
 
-/// Encrypts the contents of a file and performs additional operations based on the provided configuration.
-///
-/// # Arguments
-///
-/// * `conf` - A reference to a Config struct containing encryption and configuration settings.
-/// * `path` - A string representing the path to the file to be encrypted.
-/// * `in_place` - A boolean indicating whether to perform in-place encryption.
-///
-/// # Example
-///
-/// ```ignore
-/// # use crypt_lib::{Config, load_config};
-/// # use crypt_lib::encryption::{encrypt_file};
-///
-/// let path = "/path/to/your/file.txt";
-/// encrypt_file(&conf, path, false);
-/// ```
-pub fn encrypt_file(path: &str, output: &Option<String>) {
-    let conf = get_config();
-    // parse out file path
-    let (fp, _, filename, extension) = get_file_info(path);
+    /// Encrypts everything read from `reader`, the contents of the file at `path`, into a keeper
+    /// tracked `.crypt` file, optionally compressed with `dictionary`. The keeper is left to the
+    /// caller.
+    ///
+    /// `previous` is the keeper entry of the file and its old `.crypt` file, if any. Its uuid and row
+    /// are reused with a fresh nonce, and the old `.crypt` file is replaced once the new one is
+    /// settled. `flags` are added to the header.
+    ///
+    /// # Returns
+    ///
+    /// The `FileCrypt` to insert and the written `.crypt` file, to settle with `settle_crypt_file`
+    /// once `FileCrypt` is in the keeper.
+    fn write_tracked<R: Read>(
+        &self,
+        path: &Path,
+        reader: R,
+        output: &Option<String>,
+        dictionary: Option<&Dictionary>,
+        previous: Option<(FileCrypt, Option<PathBuf>)>,
+        flags: u16,
+    ) -> Result<(FileCrypt, PendingCrypt)> {
+        // hash is filled in once the contents have been streamed through
+        let (mut fc, old_path) = match previous {
+            Some((mut fc, old_path)) => {
+                // apply_key_mode derives the key again if the key mode is still derived
+                if fc.key_mode == KeyMode::Derived {
+                    (fc.key, _) = generate_seeds();
+                    fc.key_mode = KeyMode::Random;
+                }
+                (fc, old_path)
+            }
+            None => {
+                // parse out file path
+                let (fp, _, filename, extension) = get_file_info(path);
+                let fc = FileCrypt::new(filename, extension, "".to_string(), fp, [0u8; KEY_SIZE]);
+                (fc, None)
+            }
+        };
+        fc.set_cipher(self.config().cipher);
+        if let Some((size, mtime)) = file_stat(path) {
+            fc.size = size;
+            fc.mtime = mtime;
+        }
 
-    // get contents of file
-    let binding = get_vec_file_bytes(path);
-    let mut contents = binding.as_slice();
+        let mut header = Header::new(&fc.uuid);
+        header.cipher = fc.cipher;
+        header.flags |= FLAG_STREAM | flags;
+        self.apply_key_mode(&mut fc, &mut header)?;
 
-    let fc = FileCrypt::new(
-        filename,
-        extension,
-        "".to_string(),
-        fp,
-        compute_hash(contents),
-    );
+        let mut pending =
+            self.write_crypt_file(reader, output, &mut fc, &mut header, dictionary)?;
+        pending.replaces = old_path;
 
-    // zip contents
-    let binding = compress(contents, conf.zstd_level);
-    contents = binding.as_slice();
+        Ok((fc, pending))
+    }
 
-    let mut encrypted_contents = encrypt(&fc, contents).unwrap();
+    /// Encrypts a file with a key derived from `passphrase` (Argon2id).
+    ///
+    /// The salt, KDF parameters, nonce and original filename are stored in the file header, so the
+    /// resulting `.crypt` file decrypts on any machine with `decrypt_file_with_passphrase`, without
+    /// the keeper. Nothing is written to the keeper. Unless `Config::retain` is set, the original is
+    /// deleted once the `.crypt` file verifies.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::filecrypt::encrypt_file_with_passphrase;
+    /// encrypt_file_with_passphrase("tax_return.pdf", &None, "correct horse battery staple")?;
+    /// ```
+    pub fn encrypt_file_with_passphrase(
+        &self,
+        path: &str,
+        output: &Option<String>,
+        passphrase: &str,
+    ) -> Result<()> {
+        // parse out file path
+        let (fp, _, filename, extension) = get_file_info(path);
+
+        let mut fc = FileCrypt::new(filename, extension, "".to_string(), fp, [0u8; KEY_SIZE]);
+        fc.set_cipher(self.config().cipher);
+
+        // replace the random key with one derived from the passphrase
+        let params = KdfParams::generate();
+        fc.key = params.derive_key(passphrase)?;
+
+        let mut header = Header::new(&fc.uuid);
+        header.cipher = fc.cipher;
+        header.flags |= FLAG_STREAM | FLAG_PASSPHRASE;
+        header.push_extension(EXT_KDF, params.to_bytes());
+        self.push_portable_extensions(&mut header, &fc)?;
+
+        let pending = self.write_crypt_file(
+            BufReader::new(File::open(path)?),
+            output,
+            &mut fc,
+            &mut header,
+            None,
+        )?;
+        // portable files have no keeper row to wait for
+        let crypt_path = self.settle_crypt_file(pending, Ok(()))?;
+
+        self.discard_original(Path::new(path), &crypt_path, || {
+            Some(passphrase.to_string())
+        })
+    }
 
-    // prepend uuid to contents
-    encrypted_contents = prepend_uuid(&fc.uuid, &mut encrypted_contents);
+    /// Encrypts a file to one or more X25519 recipients.
+    ///
+    /// The random file key is wrapped to every recipient in the file header, together with the
+    /// nonce and original filename, so each recipient decrypts it with their own identity and
+    /// without our keeper. Nothing is written to the keeper, add our own identity to `recipients`
+    /// to keep access to the file. Without it the file cannot be verified, so the original is kept
+    /// even if `Config::retain` is off.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::{db::query_recipient, filecrypt::encrypt_file_to_recipients};
+    /// let alice = query_recipient("alice")?;
+    /// encrypt_file_to_recipients("design.pdf", &None, &[alice])?;
+    /// ```
+    pub fn encrypt_file_to_recipients(
+        &self,
+        path: &str,
+        output: &Option<String>,
+        recipients: &[RecipientKey],
+    ) -> Result<()> {
+        // parse out file path
+        let (fp, _, filename, extension) = get_file_info(path);
+
+        let mut fc = FileCrypt::new(filename, extension, "".to_string(), fp, [0u8; KEY_SIZE]);
+        fc.set_cipher(self.config().cipher);
+
+        let mut header = Header::new(&fc.uuid);
+        header.cipher = fc.cipher;
+        header.flags |= FLAG_STREAM | FLAG_RECIPIENTS;
+        for recipient in recipients {
+            header.push_extension(
+                EXT_RECIPIENT,
+                recipient.wrap_key(&fc.key, fc.uuid.as_bytes())?,
+            );
+        }
+        self.push_portable_extensions(&mut header, &fc)?;
+
+        let pending = self.write_crypt_file(
+            BufReader::new(File::open(path)?),
+            output,
+            &mut fc,
+            &mut header,
+            None,
+        )?;
+        // portable files have no keeper row to wait for
+        let crypt_path = self.settle_crypt_file(pending, Ok(()))?;
+
+        self.discard_original(Path::new(path), &crypt_path, || None)
+    }
 
-    let mut path = get_crypt_folder();
-    match output {
-        Some(o) => {
-            let mut alt_path = path.clone();
-            alt_path.push(o);
-            if !PathBuf::from(&alt_path).exists() {
-                match std::fs::create_dir_all(&alt_path) {
-                    Ok(_) => (),
-                    Err(e) => panic!("{}", e.to_string()),
-                }
-            }
-            path.push(format!(r#"{}\{}{}"#, o, fc.filename, ".crypt"));
+    /// Switches a keeper tracked `fc` to `Config::key_mode`. Derived key files carry their nonce and
+    /// name in `header` like portable files, so they can be recovered without the keeper.
+    fn apply_key_mode(&self, fc: &mut FileCrypt, header: &mut Header) -> Result<()> {
+        let key_mode = self.config().key_mode;
+        if key_mode == KeyMode::Derived {
+            fc.derive_key(&self.key_secret()?);
+            header.flags |= FLAG_DERIVED_KEY;
+            self.push_portable_extensions(header, fc)?;
         }
-        None => path.push(format!("{}{}", fc.filename, ".crypt")),
+        Ok(())
     }
 
-    // write fc to crypt_keeper
-    insert_crypt(&fc).expect("failed to insert FileCrypt data into database!");
+    /// Adds the nonce and original name of `fc` to the header of a portable file. The name is sealed
+    /// with the file key unless `Config::name_mode` is `plain`.
+    fn push_portable_extensions(&self, header: &mut Header, fc: &FileCrypt) -> Result<()> {
+        header.push_extension(EXT_NONCE, fc.nonce.to_vec());
+        if self.config().name_mode == NameMode::Plain {
+            header.push_extension(EXT_FILENAME, fc.filename.as_bytes().to_vec());
+            header.push_extension(EXT_EXTENSION, fc.ext.as_bytes().to_vec());
+        } else {
+            // the header travels with the file, keep the name out of it too
+            header.flags |= FLAG_SEALED_NAME;
+            header.push_extension(EXT_FILENAME, seal_name(&fc.key, &fc.filename)?);
+            header.push_extension(EXT_EXTENSION, seal_name(&fc.key, &fc.ext)?);
+        }
+        Ok(())
+    }
 
-    dbg!(&path);
-    write_contents_to_file(path.to_str().unwrap(), encrypted_contents.clone())
-        .expect("failed to write contents to file!");
-}
+    /// Streams `reader` into `<crypt folder>/<output>/<name>.crypt`, prefixed with
+    /// `header`, where `<name>` follows `Config::name_mode` (see `crypt_file_name`). Sets
+    /// `header.compression` with `choose_compression`, using `dictionary` if the contents compress,
+    /// and pads the payload following `Config::padding`. Sets `fc.hash` to the hash of the
+    /// plaintext.
+    ///
+    /// The file is written under a temporary name next to its final path, so an existing `.crypt`
+    /// file of the same name is left alone until `settle_crypt_file` renames the new one over it.
+    ///
+    /// # Returns
+    ///
+    /// The written `.crypt` file.
+    fn write_crypt_file<R: Read>(
+        &self,
+        reader: R,
+        output: &Option<String>,
+        fc: &mut FileCrypt,
+        header: &mut Header,
+        dictionary: Option<&Dictionary>,
+    ) -> Result<PendingCrypt> {
+        let mut crypt_path = self.get_crypt_folder();
+        if let Some(o) = output {
+            crypt_path.push(o);
+            std::fs::create_dir_all(&crypt_path)?;
+        }
+        crypt_path.push(self.crypt_file_name(fc, header.is_portable())?);
 
-pub fn create_file_crypt<T: AsRef<Path>>(path: T, contents: &[u8]) -> FileCrypt {
-    let path = path.as_ref();
-    // parse out file path
-    let (fp, _, filename, extension) = get_file_info(path);
-    FileCrypt::new(
-        filename,
-        extension,
-        "".to_string(),
-        fp,
-        compute_hash(contents),
-    )
-}
+        let pending = PendingCrypt {
+            path: crypt_path,
+            partial: true,
+            replaces: None,
+        };
+        let tmp_path = pending.partial_path();
+        let res = (|| -> Result<()> {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            write_crypt(reader, &mut writer, fc, header, dictionary, &self.config())?;
+            writer.flush()?;
+            Ok(())
+        })();
+        if let Err(e) = res {
+            _ = std::fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+        Ok(pending)
+    }
 
-pub fn zip_contents(contents: &[u8]) -> Result<Vec<u8>> {
-    let conf = get_config();
-    Ok(compress(contents, conf.zstd_level))
-}
+    /// Puts a `.crypt` file written by `write_crypt_file` in place once `inserted`, the result of
+    /// saving its keeper row, is `Ok`: renames it over any file of the same name, removes the
+    /// `.crypt` file it replaces and backs it up. Otherwise removes it and returns the error, so the
+    /// previous `.crypt` file still matches its keeper row.
+    ///
+    /// # Returns
+    ///
+    /// The path of the `.crypt` file.
+    fn settle_crypt_file(&self, pending: PendingCrypt, inserted: Result<()>) -> Result<PathBuf> {
+        let tmp_path = pending.partial_path();
+        if let Err(e) = inserted {
+            if pending.partial {
+                _ = std::fs::remove_file(&tmp_path);
+            }
+            return Err(e);
+        }
+        if !pending.partial {
+            return Ok(pending.path);
+        }
 
-pub fn do_file_encryption<T: AsRef<Path>>(path: T) -> Result<Vec<u8>> {
-    let path = path.as_ref();
+        std::fs::rename(&tmp_path, &pending.path)?;
+        // the name mode or output folder may have changed since the file was last encrypted
+        if let Some(old_path) = pending.replaces.filter(|p| *p != pending.path) {
+            _ = std::fs::remove_file(&old_path);
+            self.remove_backup(&old_path);
+        }
+        self.backup_crypt_file(&pending.path)?;
+        Ok(pending.path)
+    }
 
-    let contents = get_file_contents(path)?;
+    /// Trains a zstd dictionary on the start of each of `files` and stores it in the keeper.
+    ///
+    /// Meant for directories of many small, similar files (e.g. source trees), which are too small
+    /// to compress well on their own. Samples are capped at 100 times `DICTIONARY_SIZE` in total.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// # use crypt_core::filecrypt::{encrypt_file_with_dictionary, train_file_dictionary};
+    /// let dictionary = train_file_dictionary(&files)?;
+    /// for file in &files {
+    ///     encrypt_file_with_dictionary(&file.to_string_lossy(), &None, &dictionary)?;
+    /// }
+    /// ```
+    pub fn train_file_dictionary(&self, files: &[PathBuf]) -> Result<Dictionary> {
+        let mut samples = Vec::new();
+        let mut total = 0;
+        for file in files {
+            if total >= DICTIONARY_SIZE * 100 {
+                break;
+            }
+            let mut sample = Vec::new();
+            File::open(file)?
+                .take(DICTIONARY_SAMPLE_SIZE)
+                .read_to_end(&mut sample)?;
+            total += sample.len();
+            samples.push(sample);
+        }
 
-    let fc = create_file_crypt(path, &contents);
+        let data = train_dictionary(&samples)?;
+        let id = self.insert_dictionary(&data)?;
+        Ok(Dictionary { id, data })
+    }
 
-    let compressed_contents: Vec<u8> = zip_contents(&contents)?;
+    /// Gets the dictionary the file described by `header` is compressed with from the keeper.
+    pub(crate) fn header_dictionary(&self, header: &Header) -> Result<Option<Dictionary>> {
+        if header.compression != Compression::ZstdDictionary {
+            return Ok(None);
+        }
+        let id = header
+            .extension(EXT_DICTIONARY)
+            .and_then(|id| id.try_into().ok())
+            .map(u32::from_le_bytes)
+            .ok_or(Error::FcError(error::FcError::InvalidHeader(
+                "missing dictionary id",
+            )))?;
+        Ok(Some(Dictionary {
+            id,
+            data: self.query_dictionary(id)?,
+        }))
+    }
 
-    let mut encrypted_contents = encrypt(&fc, &compressed_contents)?;
+    /// Re-keys a keeper tracked `.crypt` file with a fresh key, nonce and uuid.
+    ///
+    /// The payload is decrypted with the old key and streamed straight into the new one, the
+    /// plaintext never touches the disk. The new file is written next to the old one and renamed
+    /// into place, and the keeper entries are swapped around it, so an interrupted rotation always
+    /// leaves a file that decrypts. Cipher, compression, naming and key mode follow the current
+    /// config.
+    ///
+    /// # Returns
+    ///
+    /// The new `FileCrypt` and the path of the rotated file, which changes with the uuid or
+    /// encrypted naming modes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FcError::NotKeeperTracked` for passphrase or recipient protected files.
+    pub fn rotate_file<T: AsRef<Path>>(&self, path: T) -> Result<(FileCrypt, PathBuf)> {
+        let path = path.as_ref();
+        let conf = self.config();
+
+        let mut reader = BufReader::new(File::open(path)?);
+        let header = Header::read_from(&mut reader)?;
+        if header.is_portable() {
+            return Err(Error::FcError(error::FcError::NotKeeperTracked));
+        }
+        let old = self.resolve_file_crypt(&header, || None)?;
+
+        let mut fc = FileCrypt::new(
+            old.filename.clone(),
+            old.ext.clone(),
+            old.drive_id.clone(),
+            old.full_path.clone(),
+            old.hash,
+        );
+        fc.set_cipher(conf.cipher);
+
+        let mut new_header = Header::new(&fc.uuid);
+        new_header.cipher = fc.cipher;
+        // archives stay archives
+        new_header.flags |= FLAG_STREAM | (header.flags & FLAG_ARCHIVE);
+        self.apply_key_mode(&mut fc, &mut new_header)?;
+
+        let new_path = path
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(self.crypt_file_name(&fc, false)?);
+        let tmp_path = new_path.with_extension("rotating");
+
+        // the rotated file keeps the dictionary of the old one
+        let dictionary = self.header_dictionary(&header)?;
+
+        let res = (|| -> Result<[u8; KEY_SIZE]> {
+            // a failed chunk of the old payload surfaces as an io error
+            let stream_error = |e| match e {
+                Error::IoError(e) => from_stream_error(e),
+                e => e,
+            };
+
+            // older one-shot payloads are decrypted in memory first
+            let source: Box<dyn Read> = if header.has_flag(FLAG_STREAM) {
+                let aad = header.associated_data(&old.filename, &old.ext)?;
+                plaintext_reader(
+                    &old,
+                    header.compression,
+                    dictionary.as_ref().map(|d| d.data.as_slice()),
+                    header.has_flag(FLAG_PADDED),
+                    &aad,
+                    reader,
+                )?
+            } else {
+                let mut plaintext = Vec::new();
+                decrypt_payload(&old, &header, dictionary.as_ref(), reader, &mut plaintext)?;
+                Box::new(Cursor::new(plaintext))
+            };
+            let (compression, source) =
+                choose_compression(&fc.ext, dictionary.as_ref(), source, &conf)
+                    .map_err(stream_error)?;
+            new_header.compression = compression;
+            push_dictionary(&mut new_header, dictionary.as_ref());
+            let padding = apply_padding(&mut new_header, &conf);
+
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            new_header.write_to(&mut writer)?;
+
+            let hash = encrypt_stream(
+                &fc,
+                new_header.compression,
+                dictionary.as_ref().map(|d| d.data.as_slice()),
+                conf.zstd_level,
+                padding,
+                &new_header.associated_data(&fc.filename, &fc.ext)?,
+                source,
+                &mut writer,
+            )
+            .map_err(stream_error)?;
+            writer.flush()?;
+            Ok(hash)
+        })();
+
+        let hash = match res {
+            Ok(hash) if hash == old.hash || is_recovered(&old, &header) => hash,
+            Ok(hash) => {
+                _ = std::fs::remove_file(&tmp_path);
+                return Err(Error::FcError(error::FcError::HashFail(old.hash, hash)));
+            }
+            Err(Error::EncryptionError(error::EncryptionError::AeadError)) => {
+                _ = std::fs::remove_file(&tmp_path);
+                return Err(Error::FcError(error::FcError::Tampered));
+            }
+            Err(e) => {
+                _ = std::fs::remove_file(&tmp_path);
+                return Err(e);
+            }
+        };
+        fc.hash = hash;
 
-    // prepend uuid to contents
-    Ok(prepend_uuid(&fc.uuid, &mut encrypted_contents))
-}
+        // both entries exist while the file is swapped
+        if let Err(e) = self.insert_crypt(&fc) {
+            _ = std::fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+        std::fs::rename(&tmp_path, &new_path)?;
+        if new_path != path {
+            std::fs::remove_file(path)?;
+            self.remove_backup(path);
+        }
+        self.delete_crypt(old.uuid)?;
+        self.backup_crypt_file(&new_path)?;
 
-pub fn encrypt_contents(path: &str) -> Option<Vec<u8>> {
-    if path.contains(".crypt") {
-        return None;
+        Ok((fc, new_path))
     }
-    let conf = get_config();
-    // parse out file path
-    let (fp, _, filename, extension) = get_file_info(path);
-
-    // get contents of file
-    let binding = get_vec_file_bytes(path);
-    let mut contents = binding.as_slice();
-
-    let fc = FileCrypt::new(
-        filename,
-        extension,
-        "".to_string(),
-        fp,
-        compute_hash(contents),
-    );
 
-    // zip contents
-    let binding = compress(contents, conf.zstd_level);
-    contents = binding.as_slice();
+    /// Gets the name of the `.crypt` file written for `fc`, following `Config::name_mode`.
+    ///
+    /// Encrypted names of `portable` files (see `Header::is_portable`) are sealed with the file key,
+    /// everything else with the keeper master key. Names too long to seal within
+    /// `MAX_SEALED_STEM` fall back to the uuid, the original name stays in the keeper or header.
+    pub fn crypt_file_name(&self, fc: &FileCrypt, portable: bool) -> Result<String> {
+        let name = format!("{}{}", fc.filename, fc.ext);
+        let stem = match self.config().name_mode {
+            NameMode::Plain => fc.filename.clone(),
+            NameMode::Uuid => fc.uuid.clone(),
+            NameMode::Encrypted => {
+                let sealed = match portable {
+                    true => hex::encode(seal_name(&fc.key, &name)?),
+                    false => self.seal_file_name(&name)?,
+                };
+                match sealed.len() <= MAX_SEALED_STEM {
+                    true => sealed,
+                    false => fc.uuid.clone(),
+                }
+            }
+        };
+        Ok(format!("{stem}.crypt"))
+    }
 
-    let mut encrypted_contents = encrypt(&fc, contents).unwrap();
+    /// Resolves the original name (filename and extension) of the `.crypt` file at `path`.
+    ///
+    /// Uuid and encrypted names are resolved from the file name alone, so this also works for
+    /// listings of files that are not on disk, like the cloud. Files that are on disk are otherwise
+    /// resolved through their header.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the name cannot be resolved, e.g. the keeper is locked, has no entry for the file,
+    /// or the file is portable with a sealed name.
+    pub fn resolve_crypt_name<T: AsRef<Path>>(&self, path: T) -> Option<String> {
+        let path = path.as_ref();
+        let stem = path.file_stem()?.to_str()?;
+        if path.extension()? != "crypt" {
+            return None;
+        }
 
-    // prepend uuid to contents
-    encrypted_contents = prepend_uuid(&fc.uuid, &mut encrypted_contents);
+        let from_keeper = |uuid: &str| {
+            self.query_crypt(uuid.to_string())
+                .ok()
+                .map(|fc| format!("{}{}", fc.filename, fc.ext))
+        };
 
-    // write crypt file to crypt folder
-    let mut path = get_crypt_folder();
-    // make sure we append the filename, dummy.
-    path.push(format!("{}{}", fc.filename, ".crypt"));
+        if uuid::Uuid::try_parse(stem).is_ok() {
+            if let Some(name) = from_keeper(stem) {
+                return Some(name);
+            }
+        }
+        if let Ok(name) = self.open_file_name(stem) {
+            return Some(name);
+        }
 
-    // TODO: fix this later.
-    match write_contents_to_file(path.to_str().unwrap(), encrypted_contents.clone()) {
-        Ok(_) => (),
-        Err(_) => todo!(),
+        let header = Header::read_from(&mut BufReader::new(File::open(path).ok()?)).ok()?;
+        if !header.is_portable() {
+            return from_keeper(&header.uuid);
+        }
+        if header.has_flag(FLAG_SEALED_NAME) {
+            return None;
+        }
+        let text =
+            |tag| String::from_utf8_lossy(header.extension(tag).unwrap_or_default()).to_string();
+        Some(format!("{}{}", text(EXT_FILENAME), text(EXT_EXTENSION)))
     }
 
-    // TODO: fix this later.
-    // write fc to crypt_keeper
-    match insert_crypt(&fc) {
-        Ok(_) => (),
-        Err(_) => todo!(),
+    pub fn zip_contents(&self, contents: &[u8]) -> Result<Vec<u8>> {
+        let conf = self.config();
+        Ok(compress(contents, conf.zstd_level))
     }
 
-    Some(encrypted_contents)
+    pub fn do_file_encryption<T: AsRef<Path>>(&self, path: T) -> Result<Vec<u8>> {
+        let path = path.as_ref();
+        let conf = self.config();
+
+        // parse out file path, hash is filled in while streaming
+        let (fp, _, filename, extension) = get_file_info(path);
+        let mut fc = FileCrypt::new(filename, extension, "".to_string(), fp, [0u8; KEY_SIZE]);
+        fc.set_cipher(conf.cipher);
+
+        let mut header = Header::new(&fc.uuid);
+        header.cipher = fc.cipher;
+        header.flags |= FLAG_STREAM;
+        let padding = apply_padding(&mut header, &conf);
+
+        let mut encrypted_contents = header.to_bytes()?;
+        let reader = BufReader::new(File::open(path)?);
+        encrypt_stream(
+            &fc,
+            header.compression,
+            None,
+            conf.zstd_level,
+            padding,
+            &header.associated_data(&fc.filename, &fc.ext)?,
+            reader,
+            &mut encrypted_contents,
+        )?;
+
+        Ok(encrypted_contents)
+    }
 }
 
 /// Generates the output file path for decrypted content based on the provided parameters.
@@ -459,6 +2365,9 @@ pub fn generate_uuid() -> String {
 
 /// Extracts a UUID and the remaining contents from a byte slice.
 ///
+/// This only understands the legacy (pre-header) file layout, use `Header::from_bytes` for
+/// `.crypt` files.
+///
 /// # Arguments
 ///
 /// * `contents` - A slice of `u8` bytes containing the UUID and additional data.
@@ -559,20 +2468,16 @@ pub fn get_uuid_from_file<T: AsRef<Path>>(file: T) -> Result<String> {
     // Create a buffered reader
     let mut reader = BufReader::new(file);
 
-    // Create a buffer to store the content
-    let mut buffer = [0; 36];
-
-    // Read the first 36 characters into the buffer
-    let bytes_read = reader.read(&mut buffer)?;
-
-    // Convert the buffer to a string
-    let uuid = String::from_utf8_lossy(&buffer[..bytes_read]).to_string();
+    // Only the header is read, legacy files yield the bare uuid prefix
+    let header = Header::read_from(&mut reader)?;
 
-    Ok(uuid)
+    Ok(header.uuid)
 }
 
 /// Prepends a UUID represented as a string to a vector of encrypted contents. Modifies vector in place.
 ///
+/// This is the legacy (pre-header) file layout, new files are written with `prepend_header`.
+///
 /// # Arguments
 ///
 /// * `uuid` - A string slice representing the UUID to prepend.
@@ -603,6 +2508,38 @@ pub fn prepend_uuid(uuid: &str, encrypted_contents: &mut [u8]) -> Vec<u8> {
     uuid_bytes
 }
 
+/// Prepends a serialized crypt `Header` to a slice of encrypted contents.
+///
+/// # Arguments
+///
+/// * `header` - The header describing the encrypted contents.
+/// * `encrypted_contents` - A slice of bytes containing encrypted contents.
+///
+/// # Returns
+///
+/// Returns a new vector of bytes with the header prepended to the encrypted contents, or
+/// `FcError::HeaderTooLarge` if the header cannot be serialized, see `Header::to_bytes`.
+///
+/// # Examples
+///
+/// ```
+/// use crypt_core::{filecrypt::prepend_header, header::Header};
+///
+/// let encrypted_data = vec![1, 2, 3];
+/// let header = Header::new("550e8400-e29b-41d4-a716-446655440000");
+///
+/// let result = prepend_header(&header, &encrypted_data).unwrap();
+///
+/// let (parsed, contents) = Header::from_bytes(&result).unwrap();
+/// assert_eq!(parsed, header);
+/// assert_eq!(contents, encrypted_data.as_slice());
+/// ```
+pub fn prepend_header(header: &Header, encrypted_contents: &[u8]) -> Result<Vec<u8>> {
+    let mut contents = header.to_bytes()?;
+    contents.extend_from_slice(encrypted_contents);
+    Ok(contents)
+}
+
 /// given a path, dissect and return it's full path, parent folder path, filename, and extension.
 ///
 /// # Example
@@ -624,7 +2561,7 @@ pub fn get_file_info<T: AsRef<Path>>(path: T) -> (PathBuf, PathBuf, String, Stri
     let fp = get_full_file_path(path);
     let parent_dir = fp.parent().unwrap().to_owned();
     let name = fp.file_name().unwrap().to_string_lossy().to_string(); // Convert to owned String
-    let index = name.find('.').unwrap();
+    let index = name.find('.').unwrap_or(name.len());
     let (filename, extension) = name.split_at(index);
 
     // Convert slices to owned Strings
@@ -637,16 +2574,18 @@ pub fn get_file_info<T: AsRef<Path>>(path: T) -> (PathBuf, PathBuf, String, Stri
 // cargo nextest run
 #[cfg(test)]
 mod test {
+    use std::fs;
     use std::thread;
     use std::time::Duration;
 
     use super::*;
+    use crate::vault::TempVault;
 
     #[test]
     #[ignore = "works locally, fails in CI"]
     fn test_encrypt_decrypt_file() {
-        encrypt_file("crypt-core/benches/files/dracula.txt", &None);
-        let mut crypt = get_crypt_folder();
+        encrypt_file("crypt-core/benches/files/dracula.txt", &None).unwrap();
+        let mut crypt = crate::common::get_crypt_folder().unwrap();
         crypt.push("dracula.crypt");
         assert!(crypt.exists());
 
@@ -654,7 +2593,7 @@ mod test {
 
         _ = decrypt_file(crypt.to_str().unwrap(), String::from(""));
 
-        let mut dracula_decypted = get_crypt_folder();
+        let mut dracula_decypted = crate::common::get_crypt_folder().unwrap();
         dracula_decypted.push("decrypted");
         dracula_decypted.push("dracula.txt");
 
@@ -677,4 +2616,446 @@ mod test {
         let (uuid, _) = get_uuid(&contents).unwrap();
         assert_eq!(uuid, uuid_test);
     }
+
+    #[test]
+    fn test_restore_target() {
+        let full_path = Path::new("/home/ryan/docs/a.txt");
+        assert_eq!(restore_target(full_path, None), full_path);
+        assert_eq!(
+            restore_target(full_path, Some(Path::new("/mnt/old"))),
+            Path::new("/mnt/old/home/ryan/docs/a.txt")
+        );
+        assert_eq!(
+            "Rename".parse::<ConflictPolicy>().unwrap(),
+            ConflictPolicy::Rename
+        );
+        assert!("replace".parse::<ConflictPolicy>().is_err());
+    }
+
+    /// Encrypts `report.txt` in a fresh vault, edits it and restores the `.crypt` file over the
+    /// edited file with `policy`. Returns the outcome and the location of the edited file.
+    fn restore_over_edit(
+        vault: &TempVault,
+        policy: ConflictPolicy,
+    ) -> (Result<(RestoreStatus, PathBuf)>, PathBuf) {
+        let path = vault.dir().join("report.txt");
+        fs::write(&path, b"original").unwrap();
+        vault.encrypt_file(path.to_str().unwrap(), &None).unwrap();
+        fs::write(&path, b"edited").unwrap();
+
+        let crypt_path = vault.root().join("report.crypt");
+        (vault.restore_file(&crypt_path, policy, None), path)
+    }
+
+    #[test]
+    fn test_restore_skip() {
+        let vault = TempVault::unlocked("restore-skip");
+        let (res, path) = restore_over_edit(&vault, ConflictPolicy::Skip);
+        assert_eq!(res.unwrap(), (RestoreStatus::Skipped, path.clone()));
+        assert_eq!(fs::read(&path).unwrap(), b"edited");
+    }
+
+    #[test]
+    fn test_restore_overwrite() {
+        let vault = TempVault::unlocked("restore-overwrite");
+        let (res, path) = restore_over_edit(&vault, ConflictPolicy::Overwrite);
+        assert_eq!(res.unwrap(), (RestoreStatus::Overwritten, path.clone()));
+        assert_eq!(fs::read(&path).unwrap(), b"original");
+        assert!(!path.with_file_name("report.txt.restoring").exists());
+    }
+
+    #[test]
+    fn test_restore_rename() {
+        let vault = TempVault::unlocked("restore-rename");
+        let (res, path) = restore_over_edit(&vault, ConflictPolicy::Rename);
+        let renamed = path.with_file_name("report(1).txt");
+        assert_eq!(res.unwrap(), (RestoreStatus::Renamed, renamed.clone()));
+        assert_eq!(fs::read(&path).unwrap(), b"edited");
+        assert_eq!(fs::read(&renamed).unwrap(), b"original");
+    }
+
+    #[test]
+    fn test_encrypt_files_incremental() {
+        let vault = TempVault::unlocked("incremental");
+        let path = vault.dir().join("notes.txt");
+        fs::write(&path, b"first draft").unwrap();
+        let jobs = [EncryptJob {
+            path: path.clone(),
+            output: None,
+        }];
+        let encrypt = || vault.encrypt_files(&jobs, None).pop().unwrap().unwrap();
+
+        let (status, crypt_path) = encrypt();
+        assert_eq!(status, EncryptStatus::Encrypted);
+        let first = vault.query_keeper_for_existing_file(path.clone()).unwrap();
+        assert_eq!(encrypt(), (EncryptStatus::Unchanged, crypt_path.clone()));
+
+        fs::write(&path, b"second, longer draft").unwrap();
+        assert_eq!(encrypt(), (EncryptStatus::Updated, crypt_path));
+        let second = vault.query_keeper_for_existing_file(path).unwrap();
+        assert_eq!(second.uuid, first.uuid);
+        assert_ne!(second.nonce, first.nonce);
+        assert_eq!(vault.query_keeper_crypt().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_crypt_file_names() {
+        let vault = TempVault::unlocked("names");
+        let long = "x".repeat(120);
+        for (mode, name) in [
+            (NameMode::Plain, "notes"),
+            (NameMode::Uuid, "notes"),
+            (NameMode::Encrypted, "notes"),
+            (NameMode::Encrypted, long.as_str()),
+        ] {
+            vault.config_write().name_mode = mode;
+            let path = vault.dir().join(format!("{name}.txt"));
+            fs::write(&path, b"name me").unwrap();
+            vault.encrypt_file(path.to_str().unwrap(), &None).unwrap();
+            let fc = vault.query_keeper_for_existing_file(path.clone()).unwrap();
+
+            // sealing uses a fresh nonce, so find the name that was written
+            let crypt_path = fs::read_dir(vault.root())
+                .unwrap()
+                .map(|e| e.unwrap().path())
+                .find(|p| p.extension().is_some_and(|e| e == "crypt"))
+                .unwrap();
+            let file_name = crypt_path
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_string();
+            let stem = file_name.strip_suffix(".crypt").unwrap();
+            match mode {
+                NameMode::Plain => assert_eq!(stem, name),
+                NameMode::Uuid => assert_eq!(stem, fc.uuid),
+                NameMode::Encrypted if name == long => assert_eq!(stem, fc.uuid),
+                NameMode::Encrypted => assert_eq!(vault.open_file_name(stem).unwrap(), "notes.txt"),
+            }
+            assert!(file_name.len() + ".rotating".len() - ".crypt".len() <= 255);
+
+            assert_eq!(
+                vault.resolve_crypt_name(&crypt_path),
+                Some(format!("{name}.txt"))
+            );
+            // a listing, e.g. of the cloud, resolves uuid and encrypted names without the file
+            if mode != NameMode::Plain {
+                assert_eq!(
+                    vault.resolve_crypt_name(Path::new("elsewhere").join(&file_name)),
+                    Some(format!("{name}.txt"))
+                );
+            }
+            fs::remove_file(crypt_path).unwrap();
+        }
+
+        // another vault on the same keeper, not unlocked
+        vault.config_write().name_mode = NameMode::Uuid;
+        let path = vault.dir().join("locked.txt");
+        fs::write(&path, b"locked").unwrap();
+        vault.encrypt_file(path.to_str().unwrap(), &None).unwrap();
+        let fc = vault.query_keeper_for_existing_file(path).unwrap();
+        let locked = Vault::new(vault.config(), vault.root()).unwrap();
+        let crypt_path = vault.root().join(format!("{}.crypt", fc.uuid));
+        assert_eq!(locked.resolve_crypt_name(&crypt_path), None);
+        assert_eq!(
+            vault.resolve_crypt_name(&crypt_path),
+            Some("locked.txt".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rotate_file() {
+        let vault = TempVault::unlocked("rotate");
+        let path = vault.dir().join("plans.txt");
+        let contents = b"rotate me".repeat(1000);
+        fs::write(&path, &contents).unwrap();
+        vault.encrypt_file(path.to_str().unwrap(), &None).unwrap();
+        let crypt_path = vault.root().join("plans.crypt");
+        let old = vault.query_keeper_for_existing_file(path.clone()).unwrap();
+
+        let (fc, rotated) = vault.rotate_file(&crypt_path).unwrap();
+        assert_eq!(rotated, crypt_path);
+        assert_ne!(fc.uuid, old.uuid);
+        assert_ne!(fc.key, old.key);
+        assert!(matches!(
+            vault.query_crypt(old.uuid.clone()),
+            Err(Error::DbError(rusqlite::Error::QueryReturnedNoRows))
+        ));
+
+        let mut plain = Vec::new();
+        let opened = vault
+            .decrypt_to_writer(File::open(&rotated).unwrap(), &mut plain, || None)
+            .unwrap();
+        assert_eq!(opened.uuid, fc.uuid);
+        assert_eq!(plain, contents);
+
+        // a payload that fails to decrypt halfway leaves no temporary file and no new row
+        let mut tampered = fs::read(&rotated).unwrap();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        fs::write(&rotated, tampered).unwrap();
+        assert!(vault.rotate_file(&rotated).is_err());
+        assert!(!rotated.with_extension("rotating").exists());
+        assert_eq!(vault.query_keeper_crypt().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_insert_keeps_previous_crypt() {
+        let vault = TempVault::unlocked("settle");
+        let path = vault.dir().join("notes.txt");
+        fs::write(&path, b"first draft").unwrap();
+        vault.encrypt_file(path.to_str().unwrap(), &None).unwrap();
+        let crypt_path = vault.root().join("notes.crypt");
+
+        // the keeper refuses the new nonce of the modified file
+        vault
+            .keeper()
+            .unwrap()
+            .get()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER reject_update BEFORE UPDATE ON crypt
+                 BEGIN SELECT RAISE(ABORT, 'no update'); END",
+            )
+            .unwrap();
+        fs::write(&path, b"second, longer draft").unwrap();
+        assert!(vault.encrypt_file(path.to_str().unwrap(), &None).is_err());
+        assert!(!crypt_path.with_extension("partial").exists());
+
+        let mut plain = Vec::new();
+        vault
+            .decrypt_to_writer(File::open(&crypt_path).unwrap(), &mut plain, || None)
+            .unwrap();
+        assert_eq!(plain, b"first draft");
+    }
+
+    #[test]
+    fn test_insert_batches() {
+        let vault = TempVault::unlocked("batches");
+        // make a single row fail, its batch is retried row by row
+        vault
+            .keeper()
+            .unwrap()
+            .get()
+            .unwrap()
+            .execute_batch(
+                "CREATE TRIGGER reject_bad BEFORE INSERT ON crypt WHEN NEW.filename = 'bad'
+                 BEGIN SELECT RAISE(ABORT, 'bad row'); END",
+            )
+            .unwrap();
+
+        let count = INSERT_BATCH_SIZE + 10;
+        let (sender, receiver) = mpsc::channel();
+        for i in 0..count {
+            let name = if i == 3 { "bad" } else { "good" };
+            let fc = FileCrypt::new(
+                name.to_string(),
+                ".txt".to_string(),
+                "".to_string(),
+                PathBuf::from(format!("/files/{}.txt", i)),
+                [0u8; KEY_SIZE],
+            );
+            let outcome = match i {
+                5 => Err(Error::FcError(error::FcError::NotAnArchive)),
+                7 => Ok((
+                    EncryptStatus::Unchanged,
+                    None,
+                    PendingCrypt::settled(PathBuf::from("7.crypt")),
+                )),
+                _ => Ok((
+                    EncryptStatus::Encrypted,
+                    Some(fc),
+                    PendingCrypt::settled(PathBuf::from("x.crypt")),
+                )),
+            };
+            sender.send((i, outcome)).unwrap();
+        }
+        drop(sender);
+
+        let results = vault.insert_batches(receiver, count);
+        assert_eq!(results.len(), count);
+        for (i, res) in results.iter().enumerate() {
+            assert_eq!(res.is_err(), i == 3 || i == 5, "{i}");
+        }
+        assert_eq!(results[7].as_ref().unwrap().0, EncryptStatus::Unchanged);
+        // both batches are in the keeper, less the failed rows
+        assert_eq!(vault.query_keeper_crypt().unwrap().len(), count - 3);
+    }
+
+    #[test]
+    fn test_writer_round_trip() {
+        let vault = TempVault::unlocked("writer");
+        // spans several chunks
+        let contents: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut crypt = Vec::new();
+        let fc = vault
+            .encrypt_to_writer("dump.sql", contents.as_slice(), &mut crypt)
+            .unwrap();
+        assert_eq!((fc.filename.as_str(), fc.ext.as_str()), ("dump", ".sql"));
+        assert_eq!(vault.query_crypt(fc.uuid.clone()).unwrap().hash, fc.hash);
+
+        let mut plain = Vec::new();
+        let opened = vault
+            .decrypt_to_writer(crypt.as_slice(), &mut plain, || None)
+            .unwrap();
+        assert_eq!(opened.uuid, fc.uuid);
+        assert_eq!(plain, contents);
+
+        let last = crypt.len() - 1;
+        crypt[last] ^= 1;
+        assert!(vault
+            .decrypt_to_writer(crypt.as_slice(), &mut Vec::new(), || None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_derived_key_without_keeper_row() {
+        let vault = TempVault::unlocked("derived");
+        vault.config_write().key_mode = KeyMode::Derived;
+        let path = vault.dir().join("ledger.csv");
+        let contents = b"date,amount\n".repeat(1000);
+        fs::write(&path, &contents).unwrap();
+        vault.encrypt_file(path.to_str().unwrap(), &None).unwrap();
+        let fc = vault.query_keeper_for_existing_file(path).unwrap();
+        assert_eq!(fc.key_mode, KeyMode::Derived);
+
+        vault.delete_crypt(fc.uuid.clone()).unwrap();
+        let crypt_path = vault.root().join("ledger.crypt");
+        let mut plain = Vec::new();
+        let recovered = vault
+            .decrypt_to_writer(File::open(&crypt_path).unwrap(), &mut plain, || None)
+            .unwrap();
+        assert_eq!(plain, contents);
+        assert_eq!(recovered.uuid, fc.uuid);
+        assert_eq!(recovered.key, fc.key);
+        assert_eq!(
+            (recovered.filename.as_str(), recovered.ext.as_str()),
+            ("ledger", ".csv")
+        );
+    }
+
+    #[test]
+    fn test_header_is_authenticated() {
+        let vault = TempVault::unlocked("aad");
+        let contents = b"bound to its header".repeat(1000);
+        let encrypt = |name| {
+            let mut crypt = Vec::new();
+            let fc = vault
+                .encrypt_to_writer(name, contents.as_slice(), &mut crypt)
+                .unwrap();
+            (fc, crypt)
+        };
+        let (a, crypt) = encrypt("a.txt");
+        let (b, _) = encrypt("b.txt");
+        let decrypt = |crypt: &[u8]| vault.decrypt_to_writer(crypt, &mut Vec::new(), || None);
+        assert!(decrypt(&crypt).is_ok());
+
+        // an unused flag bit, the header still parses
+        let flags = crate::header::MAGIC.len() + 3;
+        let mut tampered = crypt.clone();
+        tampered[flags] ^= 1 << 7;
+        assert!(matches!(
+            decrypt(&tampered),
+            Err(Error::FcError(error::FcError::Tampered))
+        ));
+
+        let uuid = flags + 2;
+        let mut swapped = crypt.clone();
+        swapped[uuid..uuid + b.uuid.len()].copy_from_slice(b.uuid.as_bytes());
+        assert!(matches!(
+            decrypt(&swapped),
+            Err(Error::FcError(error::FcError::Tampered))
+        ));
+
+        // even with the right key, the payload is bound to its own uuid
+        let mut reader = crypt.as_slice();
+        let mut header = Header::read_from(&mut reader).unwrap();
+        header.uuid = b.uuid.clone();
+        assert!(matches!(
+            decrypt_payload(&a, &header, None, reader, &mut Vec::new()),
+            Err(Error::FcError(error::FcError::Tampered))
+        ));
+    }
+
+    #[test]
+    fn test_verify_file_statuses() {
+        let vault = TempVault::unlocked("verify");
+        let dir = vault.dir();
+        let contents = b"verify me".repeat(1000);
+        let verify = |path: &Path| vault.verify_file(path, || None).unwrap();
+
+        let tracked = dir.join("tracked.crypt");
+        let fc = vault
+            .encrypt_to_writer(
+                "tracked.txt",
+                contents.as_slice(),
+                File::create(&tracked).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(verify(&tracked), VerifyStatus::Ok);
+
+        let mut tampered = fs::read(&tracked).unwrap();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        fs::write(dir.join("tampered.crypt"), tampered).unwrap();
+        assert_eq!(verify(&dir.join("tampered.crypt")), VerifyStatus::Tampered);
+
+        // sealed files are never in the keeper
+        let (_, sealed) = seal_contents(&contents, "untracked.txt", &vault.config()).unwrap();
+        fs::write(dir.join("untracked.crypt"), sealed).unwrap();
+        assert_eq!(
+            verify(&dir.join("untracked.crypt")),
+            VerifyStatus::MissingKeeperEntry
+        );
+
+        let plain = dir.join("secret.txt");
+        fs::write(&plain, &contents).unwrap();
+        vault
+            .encrypt_file_with_passphrase(plain.to_str().unwrap(), &None, "hunter2")
+            .unwrap();
+        let protected = vault.root().join("secret.crypt");
+        assert_eq!(verify(&protected), VerifyStatus::Skipped);
+        assert_eq!(
+            vault
+                .verify_file(&protected, || Some("hunter2".to_string()))
+                .unwrap(),
+            VerifyStatus::Ok
+        );
+
+        // a keeper key that no longer unwraps
+        vault
+            .keeper()
+            .unwrap()
+            .get()
+            .unwrap()
+            .execute(
+                "UPDATE crypt SET key_seed = ?1 WHERE uuid = ?2",
+                rusqlite::params![vec![0u8; crate::encryption::WRAPPED_KEY_SIZE], fc.uuid],
+            )
+            .unwrap();
+        assert_eq!(verify(&tracked), VerifyStatus::WrongKey);
+    }
+
+    #[test]
+    fn test_seal_open_contents() {
+        let contents = b"in memory, never on disk".repeat(100);
+        let (fc, mut sealed) = seal_contents(&contents, "note.txt", &Config::default()).unwrap();
+        assert_eq!(fc.ext, ".txt");
+        assert_eq!(open_contents(&sealed, &fc).unwrap(), contents);
+
+        let (other, _) = seal_contents(&contents, "note.txt", &Config::default()).unwrap();
+        assert!(matches!(
+            open_contents(&sealed, &other),
+            Err(Error::FcError(error::FcError::FileCryptMismatch))
+        ));
+
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(matches!(
+            open_contents(&sealed, &fc),
+            Err(Error::FcError(error::FcError::Tampered))
+        ));
+    }
 }
@@ -1,32 +1,39 @@
+use serde::Serialize;
 use std::cmp::Ordering;
 use std::ffi::OsStr;
 use std::fs;
 use std::io;
 use std::path::PathBuf;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
 pub enum FileTree {
+    #[serde(rename = "directory")]
     DirNode(Directory),
+    #[serde(rename = "file")]
     FileNode(File),
+    #[serde(rename = "link")]
     LinkNode(Symlink),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Directory {
     pub name: String,
     pub entries: Vec<FileTree>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct File {
     pub name: String,
+    #[serde(skip)]
     pub metadata: fs::Metadata,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Symlink {
     pub name: String,
     pub target: String,
+    #[serde(skip)]
     pub metadata: fs::Metadata,
 }
 
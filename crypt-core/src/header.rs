@@ -0,0 +1,459 @@
+pub use crate::cipher::Cipher;
+use crate::{error, prelude::*};
+use std::io::{Read, Write};
+
+/// Magic bytes found at the start of every `.crypt` file.
+pub const MAGIC: [u8; 8] = *b"BYTECRPT";
+
+/// Current version of the `.crypt` file format.
+///
+/// * `1` - header, payload without associated data.
+/// * `2` - payload authenticates the header and original name (see `Header::associated_data`).
+pub const FORMAT_VERSION: u8 = 2;
+
+/// First format version whose payload is bound to the header as associated data.
+pub const AAD_VERSION: u8 = 2;
+
+/// Version reported for files written before the header existed, which only
+/// start with the 36 byte UUID.
+pub const LEGACY_VERSION: u8 = 0;
+
+/// Length of a hyphenated UUID string.
+pub const UUID_SIZE: usize = 36;
+
+/// Length of the fixed portion of the header (everything before the extension area).
+pub const FIXED_HEADER_SIZE: usize = MAGIC.len() + 1 + 1 + 1 + 2 + UUID_SIZE + 4;
+
+/// Payload is encrypted in `STREAM_CHUNK_SIZE` chunks (see `encryption::encrypt_stream`)
+/// instead of a single AEAD message.
+pub const FLAG_STREAM: u16 = 1 << 0;
+
+/// File key is derived from a passphrase. The KDF parameters, nonce and original name are stored
+/// in the extension area, so the file decrypts without the keeper.
+pub const FLAG_PASSPHRASE: u16 = 1 << 1;
+
+/// `EXT_FILENAME` and `EXT_EXTENSION` hold names sealed with `encryption::seal_name` under the
+/// file key instead of plain text.
+pub const FLAG_SEALED_NAME: u16 = 1 << 2;
+
+/// File key is wrapped to one or more X25519 recipients, one `EXT_RECIPIENT` record each. The
+/// nonce and original name are stored in the extension area, so the file decrypts without the
+/// keeper of the sender.
+pub const FLAG_RECIPIENTS: u16 = 1 << 3;
+
+/// File key is derived from the keeper's key secret and the uuid (`KeyMode::Derived`). The nonce
+/// and original name are stored in the extension area, so the file can be recovered with a
+/// backup of the secret alone.
+pub const FLAG_DERIVED_KEY: u16 = 1 << 4;
+
+/// Payload is padded inside the AEAD to hide its length (see `padding::Padding`). The scheme is
+/// not recorded, the padding is self-delimiting.
+pub const FLAG_PADDED: u16 = 1 << 5;
+
+/// Payload is a directory packed by `archive::pack_directory`. Decrypting it extracts the tree.
+pub const FLAG_ARCHIVE: u16 = 1 << 6;
+
+/// Extension tag: serialized `encryption::KdfParams`.
+pub const EXT_KDF: u8 = 1;
+
+/// Extension tag: nonce used for the payload.
+pub const EXT_NONCE: u8 = 2;
+
+/// Extension tag: original filename (UTF-8, without extension).
+pub const EXT_FILENAME: u8 = 3;
+
+/// Extension tag: original extension (UTF-8, including the leading `.`).
+pub const EXT_EXTENSION: u8 = 4;
+
+/// Extension tag: recipient stanza, see `recipient::RecipientKey::wrap_key`. May repeat.
+pub const EXT_RECIPIENT: u8 = 5;
+
+/// Extension tag: id (u32 LE) of the keeper dictionary used by `Compression::ZstdDictionary`.
+pub const EXT_DICTIONARY: u8 = 6;
+
+/// Compression applied to the plaintext before encryption.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    None = 0,
+    #[default]
+    Zstd = 1,
+    /// zstd with a trained dictionary, see `EXT_DICTIONARY`.
+    ZstdDictionary = 2,
+}
+
+impl TryFrom<u8> for Compression {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Zstd),
+            2 => Ok(Self::ZstdDictionary),
+            _ => Err(Error::FcError(error::FcError::UnknownCompression(value))),
+        }
+    }
+}
+
+/// Self-describing header written at the start of every `.crypt` file.
+///
+/// # Layout
+/// ```text
+/// magic       8 bytes   "BYTECRPT"
+/// version     u8
+/// cipher      u8
+/// compression u8
+/// flags       u16 (LE)
+/// uuid        36 bytes
+/// ext_len     u32 (LE)
+/// extensions  ext_len bytes of (tag u8, len u16 LE, value) records
+/// ```
+///
+/// Files written before the header was introduced only start with the UUID. Those are parsed
+/// as a header with `version == LEGACY_VERSION` so they can still be decrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// format version the file was written with.
+    pub version: u8,
+
+    /// cipher used for the payload.
+    pub cipher: Cipher,
+
+    /// compression applied before encryption.
+    pub compression: Compression,
+
+    /// feature flags, see the `FLAG_*` constants.
+    pub flags: u16,
+
+    /// uuid of the `FileCrypt` entry in the keeper.
+    pub uuid: String,
+
+    /// optional tagged records. Unknown tags are preserved and ignored.
+    pub extensions: Vec<(u8, Vec<u8>)>,
+}
+
+impl Header {
+    /// Creates a header for a new file using the current format version.
+    pub fn new(uuid: &str) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            cipher: Cipher::default(),
+            compression: Compression::default(),
+            flags: 0,
+            uuid: uuid.to_string(),
+            extensions: Vec::new(),
+        }
+    }
+
+    /// returns `true` if the file was written before headers existed.
+    pub fn is_legacy(&self) -> bool {
+        self.version == LEGACY_VERSION
+    }
+
+    /// returns `true` if `flag` is set.
+    pub fn has_flag(&self, flag: u16) -> bool {
+        self.flags & flag == flag
+    }
+
+    /// returns `true` if the file key is not in the keeper, i.e. the header carries everything but
+    /// the key material needed to decrypt it.
+    pub fn is_portable(&self) -> bool {
+        self.flags & (FLAG_PASSPHRASE | FLAG_RECIPIENTS) != 0
+    }
+
+    /// Returns the values of every extension record with the given tag, in order.
+    pub fn extensions_with(&self, tag: u8) -> impl Iterator<Item = &[u8]> {
+        self.extensions
+            .iter()
+            .filter(move |(t, _)| *t == tag)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Returns the value of the first extension record with the given tag.
+    pub fn extension(&self, tag: u8) -> Option<&[u8]> {
+        self.extensions
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    /// Appends an extension record to the header.
+    pub fn push_extension(&mut self, tag: u8, value: Vec<u8>) {
+        self.extensions.push((tag, value));
+    }
+
+    /// Serializes the header. Legacy headers are written back as the bare UUID.
+    ///
+    /// # Errors
+    /// * `FcError::HeaderTooLarge` if an extension value is longer than `u16::MAX` bytes, or the
+    ///   extension area longer than `u32::MAX` bytes, as their lengths would not fit.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        if self.is_legacy() {
+            return Ok(self.uuid.as_bytes().to_vec());
+        }
+
+        let too_large = |len: usize| Error::FcError(error::FcError::HeaderTooLarge(len));
+        let mut ext = Vec::new();
+        for (tag, value) in &self.extensions {
+            let len = u16::try_from(value.len()).map_err(|_| too_large(value.len()))?;
+            ext.push(*tag);
+            ext.extend_from_slice(&len.to_le_bytes());
+            ext.extend_from_slice(value);
+        }
+        let ext_len = u32::try_from(ext.len()).map_err(|_| too_large(ext.len()))?;
+
+        let mut bytes = Vec::with_capacity(FIXED_HEADER_SIZE + ext.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(self.version);
+        bytes.push(self.cipher as u8);
+        bytes.push(self.compression as u8);
+        bytes.extend_from_slice(&self.flags.to_le_bytes());
+        bytes.extend_from_slice(self.uuid.as_bytes());
+        bytes.extend_from_slice(&ext_len.to_le_bytes());
+        bytes.extend_from_slice(&ext);
+        Ok(bytes)
+    }
+
+    /// Associated data authenticated with the payload: the serialized header followed by the
+    /// original filename and extension, each prefixed with its length as a u16 (LE).
+    ///
+    /// Changing any header field, the uuid or the recorded name makes decryption fail. Files
+    /// written before `AAD_VERSION` carry no associated data and get an empty vector.
+    pub fn associated_data(&self, filename: &str, ext: &str) -> Result<Vec<u8>> {
+        if self.version < AAD_VERSION {
+            return Ok(Vec::new());
+        }
+
+        let mut aad = self.to_bytes()?;
+        for field in [filename, ext] {
+            let len = u16::try_from(field.len())
+                .map_err(|_| Error::FcError(error::FcError::HeaderTooLarge(field.len())))?;
+            aad.extend_from_slice(&len.to_le_bytes());
+            aad.extend_from_slice(field.as_bytes());
+        }
+        Ok(aad)
+    }
+
+    /// Writes the serialized header to `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Reads a header from the start of `reader`, leaving the reader positioned at the payload.
+    ///
+    /// # Errors
+    /// * `FcError::NotACryptFile` if the input neither starts with `MAGIC` nor a valid UUID.
+    /// * `FcError::UnsupportedVersion` if the file was written by a newer version of crypt.
+    /// * `FcError::UnknownCipher` / `FcError::UnknownCompression` for unrecognized ids.
+    /// * `FcError::InvalidHeader` if the header is truncated or malformed.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0u8; MAGIC.len()];
+        read_exact(reader, &mut magic)?;
+
+        if magic != MAGIC {
+            return Self::read_legacy(magic, reader);
+        }
+
+        let mut fixed = [0u8; FIXED_HEADER_SIZE - MAGIC.len()];
+        read_exact(reader, &mut fixed)?;
+
+        let version = fixed[0];
+        if version > FORMAT_VERSION || version == LEGACY_VERSION {
+            return Err(Error::FcError(error::FcError::UnsupportedVersion(version)));
+        }
+        let cipher = Cipher::try_from(fixed[1])?;
+        let compression = Compression::try_from(fixed[2])?;
+        let flags = u16::from_le_bytes([fixed[3], fixed[4]]);
+        let uuid = parse_uuid(&fixed[5..5 + UUID_SIZE])?;
+        let ext_len = u32::from_le_bytes(fixed[5 + UUID_SIZE..].try_into().unwrap()) as usize;
+
+        let mut ext = Vec::new();
+        reader.by_ref().take(ext_len as u64).read_to_end(&mut ext)?;
+        if ext.len() != ext_len {
+            return Err(Error::FcError(error::FcError::InvalidHeader(
+                "truncated extension area",
+            )));
+        }
+
+        Ok(Self {
+            version,
+            cipher,
+            compression,
+            flags,
+            uuid,
+            extensions: parse_extensions(&ext)?,
+        })
+    }
+
+    /// Parses a header from the start of `contents`, returning it along with the remaining payload.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use crypt_core::header::Header;
+    /// let header = Header::new("123e4567-e89b-12d3-a456-426614174001");
+    /// let mut contents = header.to_bytes().unwrap();
+    /// contents.extend_from_slice(b"payload");
+    ///
+    /// let (parsed, payload) = Header::from_bytes(&contents).unwrap();
+    /// assert_eq!(parsed, header);
+    /// assert_eq!(payload, b"payload");
+    /// ```
+    pub fn from_bytes(contents: &[u8]) -> Result<(Self, &[u8])> {
+        let mut reader = contents;
+        let header = Self::read_from(&mut reader)?;
+        Ok((header, reader))
+    }
+
+    /// Finishes reading a file that starts with a bare UUID instead of `MAGIC`.
+    fn read_legacy<R: Read>(start: [u8; MAGIC.len()], reader: &mut R) -> Result<Self> {
+        let mut uuid = [0u8; UUID_SIZE];
+        uuid[..start.len()].copy_from_slice(&start);
+        read_exact(reader, &mut uuid[start.len()..])?;
+
+        Ok(Self {
+            version: LEGACY_VERSION,
+            cipher: Cipher::ChaCha20Poly1305,
+            compression: Compression::Zstd,
+            flags: 0,
+            uuid: parse_uuid(&uuid).map_err(|_| Error::FcError(error::FcError::NotACryptFile))?,
+            extensions: Vec::new(),
+        })
+    }
+}
+
+/// `read_exact` that reports a short read as a truncated header.
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => {
+            Error::FcError(error::FcError::InvalidHeader("input too short"))
+        }
+        _ => Error::IoError(e),
+    })
+}
+
+/// Validates that `bytes` hold a hyphenated UUID and returns it as a `String`.
+fn parse_uuid(bytes: &[u8]) -> Result<String> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .filter(|s| uuid::Uuid::try_parse(s).is_ok())
+        .map(str::to_string)
+        .ok_or(Error::FcError(error::FcError::InvalidHeader(
+            "invalid uuid",
+        )))
+}
+
+fn parse_extensions(mut ext: &[u8]) -> Result<Vec<(u8, Vec<u8>)>> {
+    let mut records = Vec::new();
+    while !ext.is_empty() {
+        if ext.len() < 3 {
+            return Err(Error::FcError(error::FcError::InvalidHeader(
+                "malformed extension record",
+            )));
+        }
+        let tag = ext[0];
+        let len = u16::from_le_bytes([ext[1], ext[2]]) as usize;
+        if ext.len() < 3 + len {
+            return Err(Error::FcError(error::FcError::InvalidHeader(
+                "malformed extension record",
+            )));
+        }
+        records.push((tag, ext[3..3 + len].to_vec()));
+        ext = &ext[3 + len..];
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const UUID: &str = "018d1a4e-5c3b-7f2a-9c4e-0123456789ab";
+
+    #[test]
+    fn test_header_round_trip() {
+        let mut header = Header::new(UUID);
+        header.push_extension(7, vec![1, 2, 3]);
+
+        let mut contents = header.to_bytes().unwrap();
+        contents.extend_from_slice(b"payload");
+
+        let (parsed, payload) = Header::from_bytes(&contents).unwrap();
+        assert_eq!(parsed, header);
+        assert_eq!(parsed.extension(7), Some([1u8, 2, 3].as_slice()));
+        assert_eq!(payload, b"payload");
+    }
+
+    #[test]
+    fn test_rejects_oversized_extension() {
+        let mut header = Header::new(UUID);
+        header.push_extension(7, vec![0; u16::MAX as usize]);
+        assert!(header.to_bytes().is_ok());
+
+        header.push_extension(8, vec![0; u16::MAX as usize + 1]);
+        assert!(matches!(
+            header.to_bytes(),
+            Err(Error::FcError(error::FcError::HeaderTooLarge(_)))
+        ));
+        assert!(header.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_legacy_header() {
+        let mut contents = UUID.as_bytes().to_vec();
+        contents.extend_from_slice(b"payload");
+
+        let (parsed, payload) = Header::from_bytes(&contents).unwrap();
+        assert!(parsed.is_legacy());
+        assert_eq!(parsed.uuid, UUID);
+        assert_eq!(payload, b"payload");
+    }
+
+    #[test]
+    fn test_rejects_non_crypt_input() {
+        let contents = b"this is just a plain text file, nothing to see here";
+        assert!(matches!(
+            Header::from_bytes(contents),
+            Err(Error::FcError(error::FcError::NotACryptFile))
+        ));
+
+        assert!(matches!(
+            Header::from_bytes(b"short"),
+            Err(Error::FcError(error::FcError::InvalidHeader(_)))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_newer_version() {
+        let mut contents = Header::new(UUID).to_bytes().unwrap();
+        contents[MAGIC.len()] = FORMAT_VERSION + 1;
+        assert!(matches!(
+            Header::from_bytes(&contents),
+            Err(Error::FcError(error::FcError::UnsupportedVersion(_)))
+        ));
+    }
+
+    #[test]
+    fn test_associated_data() {
+        let header = Header::new(UUID);
+        let aad = |header: &Header, filename, ext| header.associated_data(filename, ext).unwrap();
+        let report = aad(&header, "report", ".pdf");
+        assert!(report.starts_with(&header.to_bytes().unwrap()));
+        assert_ne!(report, aad(&header, "invoice", ".pdf"));
+        assert_ne!(report, aad(&header, "report", ".txt"));
+
+        let mut other = header.clone();
+        other.flags ^= FLAG_STREAM;
+        assert_ne!(report, aad(&other, "report", ".pdf"));
+
+        // older files were encrypted without associated data
+        other.version = 1;
+        assert!(aad(&other, "report", ".pdf").is_empty());
+
+        let long = "x".repeat(u16::MAX as usize + 1);
+        assert!(matches!(
+            header.associated_data(&long, ".pdf"),
+            Err(Error::FcError(error::FcError::HeaderTooLarge(_)))
+        ));
+    }
+}
@@ -1,4 +1,8 @@
 #![allow(clippy::needless_return)]
+pub mod age_file;
+pub mod archive;
+pub mod backup;
+pub mod cipher;
 pub mod common;
 pub mod config;
 pub mod db;
@@ -6,5 +10,9 @@ pub mod encryption;
 pub mod error;
 pub mod filecrypt;
 pub mod filetree;
+pub mod header;
+pub mod padding;
 pub mod prelude;
+pub mod recipient;
 pub mod token;
+pub mod vault;
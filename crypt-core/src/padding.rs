@@ -0,0 +1,282 @@
+use crate::{error, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+/// Byte ending the data of a padded payload, followed by zeros up to the padded length
+/// (ISO/IEC 7816-4 padding).
+const PAD_MARKER: u8 = 0x80;
+
+/// Size of the buffers used to write and strip padding.
+const PAD_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Padding applied to the payload of a `.crypt` file, inside the AEAD, so the ciphertext length
+/// no longer reveals the exact compressed length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Padding {
+    /// no padding.
+    #[default]
+    None,
+
+    /// Padmé: pads to a length whose low bits are zero, at most 12% larger, leaking
+    /// `O(log log n)` bits of the length.
+    Padme,
+
+    /// pads to the next power of two, at most twice as large, leaking `O(log log n)` bits of the
+    /// length with fewer distinct sizes than Padmé.
+    #[serde(rename = "pow2")]
+    PowerOfTwo,
+}
+
+impl Padding {
+    /// Gets the length a payload of `len` bytes, padding marker included, is padded to.
+    pub fn padded_len(&self, len: u64) -> u64 {
+        match self {
+            Padding::None => len,
+            Padding::PowerOfTwo => len.next_power_of_two(),
+            Padding::Padme if len < 2 => len,
+            Padding::Padme => {
+                let e = 63 - len.leading_zeros() as u64;
+                let s = 64 - e.leading_zeros() as u64;
+                let mask = (1u64 << (e - s)) - 1;
+                (len + mask) & !mask
+            }
+        }
+    }
+}
+
+impl fmt::Display for Padding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Padding::None => write!(f, "none"),
+            Padding::Padme => write!(f, "padme"),
+            Padding::PowerOfTwo => write!(f, "pow2"),
+        }
+    }
+}
+
+impl FromStr for Padding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Padding::None),
+            "padme" => Ok(Padding::Padme),
+            "pow2" | "poweroftwo" => Ok(Padding::PowerOfTwo),
+            _ => Err(Error::CommonError(error::CommonError::UnknownPadding(
+                s.to_string(),
+            ))),
+        }
+    }
+}
+
+/// `Write` adapter that pads everything written to it with `padding` once `finish` is called.
+pub(crate) struct PadWriter<W: Write> {
+    inner: W,
+    padding: Padding,
+    written: u64,
+}
+
+impl<W: Write> PadWriter<W> {
+    pub(crate) fn new(padding: Padding, inner: W) -> Self {
+        Self {
+            inner,
+            padding,
+            written: 0,
+        }
+    }
+
+    /// Writes the padding marker and zeros up to the padded length, and returns the inner
+    /// writer. Writes nothing for `Padding::None`.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        if self.padding == Padding::None {
+            return Ok(self.inner);
+        }
+        self.inner.write_all(&[PAD_MARKER])?;
+
+        let len = self.written + 1;
+        let mut zeros = self.padding.padded_len(len) - len;
+        let buffer = [0u8; PAD_BUFFER_SIZE];
+        while zeros > 0 {
+            let n = zeros.min(PAD_BUFFER_SIZE as u64) as usize;
+            self.inner.write_all(&buffer[..n])?;
+            zeros -= n as u64;
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for PadWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `Read` adapter that strips the padding written by `PadWriter`.
+///
+/// A marker and the zeros after it are held back, as a count, until a non-zero byte shows they
+/// were data, so memory use stays bounded however long the padding is.
+pub(crate) struct UnpadReader<R: Read> {
+    inner: R,
+    input: Vec<u8>,
+    pos: usize,
+
+    /// number of zeros seen after a held back marker.
+    held: Option<u64>,
+
+    /// held back bytes that turned out to be data: a marker, then zeros.
+    pending: Option<(bool, u64)>,
+
+    /// the padding has been reached and dropped.
+    finished: bool,
+}
+
+impl<R: Read> UnpadReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            input: Vec::new(),
+            pos: 0,
+            held: None,
+            pending: None,
+            finished: false,
+        }
+    }
+
+    /// Copies pending bytes into `out`.
+    fn drain_pending(&mut self, out: &mut [u8]) -> usize {
+        let Some((marker, zeros)) = self.pending else {
+            return 0;
+        };
+        let mut n = 0;
+        if marker {
+            out[0] = PAD_MARKER;
+            n = 1;
+        }
+        let z = zeros.min((out.len() - n) as u64) as usize;
+        out[n..n + z].fill(0);
+        n += z;
+
+        self.pending = match zeros - z as u64 {
+            0 => None,
+            rest => Some((false, rest)),
+        };
+        n
+    }
+}
+
+impl<R: Read> Read for UnpadReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if out.is_empty() || self.finished {
+            return Ok(0);
+        }
+        loop {
+            if self.pending.is_some() {
+                return Ok(self.drain_pending(out));
+            }
+
+            if self.pos == self.input.len() {
+                self.input.resize(PAD_BUFFER_SIZE, 0);
+                let n = self.inner.read(&mut self.input)?;
+                self.input.truncate(n);
+                self.pos = 0;
+                if n == 0 {
+                    // whatever is held back is the padding
+                    return match self.held.take() {
+                        Some(_) => {
+                            self.finished = true;
+                            Ok(0)
+                        }
+                        None => Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "payload is not padded",
+                        )),
+                    };
+                }
+            }
+
+            let mut written = 0;
+            while self.pos < self.input.len() && written < out.len() {
+                let b = self.input[self.pos];
+                match self.held {
+                    Some(zeros) if b == 0 => self.held = Some(zeros + 1),
+                    Some(zeros) => {
+                        // the held back bytes were data, emit them before `b`
+                        self.held = None;
+                        self.pending = Some((true, zeros));
+                        break;
+                    }
+                    None if b == PAD_MARKER => self.held = Some(0),
+                    None => {
+                        out[written] = b;
+                        written += 1;
+                    }
+                }
+                self.pos += 1;
+            }
+            if written > 0 {
+                return Ok(written);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(padding: Padding, contents: &[u8]) -> Vec<u8> {
+        let mut writer = PadWriter::new(padding, Vec::new());
+        writer.write_all(contents).unwrap();
+        let padded = writer.finish().unwrap();
+        assert_eq!(
+            padded.len() as u64,
+            padding.padded_len(contents.len() as u64 + 1)
+        );
+
+        let mut unpadded = Vec::new();
+        UnpadReader::new(padded.as_slice())
+            .read_to_end(&mut unpadded)
+            .unwrap();
+        unpadded
+    }
+
+    #[test]
+    fn test_padded_len() {
+        assert_eq!(Padding::PowerOfTwo.padded_len(1000), 1024);
+        assert_eq!(Padding::PowerOfTwo.padded_len(1024), 1024);
+        assert_eq!(Padding::Padme.padded_len(1000), 1024);
+        assert_eq!(Padding::Padme.padded_len(1_000_000), 1_015_808);
+
+        // Padmé never adds more than 12%
+        for len in (2..10_000_000).step_by(9_973) {
+            let padded = Padding::Padme.padded_len(len);
+            assert!(padded >= len && padded - len <= len * 12 / 100 + 1);
+        }
+    }
+
+    #[test]
+    fn test_padding_round_trip() {
+        // data that looks like padding must survive
+        let mut contents: Vec<u8> = (0..20_000).map(|i| (i % 251) as u8).collect();
+        contents.extend_from_slice(&[PAD_MARKER, 0, 0, 0, PAD_MARKER]);
+        contents.extend_from_slice(&vec![0u8; PAD_BUFFER_SIZE * 2]);
+        contents.extend_from_slice(&[PAD_MARKER, 0, 1]);
+
+        for padding in [Padding::Padme, Padding::PowerOfTwo] {
+            assert_eq!(round_trip(padding, &contents), contents);
+            assert_eq!(round_trip(padding, &contents[..5]), &contents[..5]);
+            assert_eq!(round_trip(padding, &[]), Vec::<u8>::new());
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    encryption::{unwrap_key, wrap_key, KEY_SIZE, WRAPPED_KEY_SIZE},
+    error,
+    prelude::*,
+};
+use chacha20poly1305::aead::OsRng;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::{fmt, str::FromStr};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// Size of an X25519 public or secret key.
+pub const X25519_KEY_SIZE: usize = 32;
+
+/// Size of a recipient stanza: the ephemeral public key followed by the wrapped file key.
+pub const STANZA_SIZE: usize = X25519_KEY_SIZE + WRAPPED_KEY_SIZE;
+
+/// HKDF info used to derive the key wrapping key from the X25519 shared secret.
+const STANZA_INFO: &[u8] = b"crypt x25519 recipient";
+
+/// Public key of someone a file can be encrypted to.
+///
+/// Printed and parsed as lowercase hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecipientKey([u8; X25519_KEY_SIZE]);
+
+impl RecipientKey {
+    pub fn from_bytes(bytes: [u8; X25519_KEY_SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; X25519_KEY_SIZE] {
+        &self.0
+    }
+
+    /// Wraps a file key so that only the holder of the matching `Identity` can unwrap it.
+    ///
+    /// A fresh ephemeral key is agreed with the recipient key for every call, the shared secret
+    /// is run through HKDF-SHA256 and used with `encryption::wrap_key`. `aad` binds the stanza to
+    /// its file, like the uuid does for keys in the keeper.
+    ///
+    /// # Returns
+    ///
+    /// A `STANZA_SIZE` byte stanza for the file header.
+    pub fn wrap_key(&self, key: &[u8; KEY_SIZE], aad: &[u8]) -> Result<Vec<u8>> {
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral);
+        let shared = ephemeral.diffie_hellman(&PublicKey::from(self.0));
+        if !shared.was_contributory() {
+            return Err(Error::EncryptionError(
+                error::EncryptionError::InvalidRecipient(self.to_string()),
+            ));
+        }
+
+        let kek = stanza_key(shared.as_bytes(), ephemeral_public.as_bytes(), &self.0);
+        let mut stanza = ephemeral_public.as_bytes().to_vec();
+        stanza.extend_from_slice(&wrap_key(&kek, key, aad)?);
+        Ok(stanza)
+    }
+}
+
+impl fmt::Display for RecipientKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl FromStr for RecipientKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        hex::decode(s.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(RecipientKey)
+            .ok_or(Error::EncryptionError(
+                error::EncryptionError::InvalidRecipient(s.to_string()),
+            ))
+    }
+}
+
+/// X25519 key pair files can be encrypted to. The secret key is kept in the keeper, wrapped
+/// under the master key.
+#[derive(Clone)]
+pub struct Identity(StaticSecret);
+
+impl Identity {
+    /// Generates a new random identity.
+    pub fn generate() -> Self {
+        Self(StaticSecret::random_from_rng(OsRng))
+    }
+
+    pub fn from_bytes(bytes: [u8; X25519_KEY_SIZE]) -> Self {
+        Self(StaticSecret::from(bytes))
+    }
+
+    pub fn to_bytes(&self) -> [u8; X25519_KEY_SIZE] {
+        self.0.to_bytes()
+    }
+
+    /// Public key others encrypt to.
+    pub fn recipient(&self) -> RecipientKey {
+        RecipientKey(PublicKey::from(&self.0).to_bytes())
+    }
+
+    /// Unwraps a file key from a stanza produced by `RecipientKey::wrap_key`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EncryptionError::KeyUnwrapError` if the stanza was made for another recipient or
+    /// `aad` does not match.
+    pub fn unwrap_key(&self, stanza: &[u8], aad: &[u8]) -> Result<[u8; KEY_SIZE]> {
+        if stanza.len() != STANZA_SIZE {
+            return Err(Error::EncryptionError(
+                error::EncryptionError::KeyUnwrapError,
+            ));
+        }
+        let (ephemeral_public, wrapped) = stanza.split_at(X25519_KEY_SIZE);
+        let ephemeral_public: [u8; X25519_KEY_SIZE] = ephemeral_public
+            .try_into()
+            .expect("stanza length checked above");
+
+        let shared = self.0.diffie_hellman(&PublicKey::from(ephemeral_public));
+        let kek = stanza_key(
+            shared.as_bytes(),
+            &ephemeral_public,
+            self.recipient().as_bytes(),
+        );
+        unwrap_key(&kek, wrapped, aad)
+    }
+}
+
+/// Derives the key wrapping key for a stanza from the X25519 shared secret.
+fn stanza_key(
+    shared: &[u8; X25519_KEY_SIZE],
+    ephemeral_public: &[u8; X25519_KEY_SIZE],
+    recipient: &[u8; X25519_KEY_SIZE],
+) -> [u8; KEY_SIZE] {
+    let mut salt = ephemeral_public.to_vec();
+    salt.extend_from_slice(recipient);
+
+    let mut kek = [0u8; KEY_SIZE];
+    Hkdf::<Sha256>::new(Some(&salt), shared)
+        .expand(STANZA_INFO, &mut kek)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    kek
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::encryption::generate_seeds;
+
+    #[test]
+    fn test_recipient_wrap_key() {
+        let (key, _) = generate_seeds();
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+
+        let stanza = alice.recipient().wrap_key(&key, b"uuid").unwrap();
+        assert_eq!(stanza.len(), STANZA_SIZE);
+        assert_eq!(alice.unwrap_key(&stanza, b"uuid").unwrap(), key);
+
+        // other identities and other files must not unwrap it
+        assert!(bob.unwrap_key(&stanza, b"uuid").is_err());
+        assert!(alice.unwrap_key(&stanza, b"other").is_err());
+    }
+
+    #[test]
+    fn test_recipient_key_from_str() {
+        let recipient = Identity::generate().recipient();
+        let parsed: RecipientKey = recipient.to_string().parse().unwrap();
+        assert_eq!(parsed, recipient);
+
+        assert!("not a key".parse::<RecipientKey>().is_err());
+        assert!("abcd".parse::<RecipientKey>().is_err());
+    }
+}
@@ -339,7 +339,7 @@ fn save_access_token(user_token: &UserToken) -> Result<()> {
 }
 
 pub fn encrypt_token(user_token: &UserToken) -> Result<Vec<u8>> {
-    let conf = get_config();
+    let conf = get_config()?;
     let mut token = user_token.access_token.as_bytes();
     let compressed_token = compress(token, conf.zstd_level);
     token = compressed_token.as_slice();
@@ -366,7 +366,9 @@ pub fn decrypt_token(user_token: &UserToken, access_token: Vec<u8>) -> String {
 }
 
 pub fn purge_tokens() {
-    let mut path = get_crypt_folder();
+    let Ok(mut path) = get_crypt_folder() else {
+        return;
+    };
     path.push(".config");
 
     path.push(".google");
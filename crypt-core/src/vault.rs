@@ -0,0 +1,275 @@
+use crate::{
+    common::{get_config_folder, home_crypt_folder},
+    config::{load_config, log_file_in, Config, Interface},
+    db::open_keeper,
+    encryption::KEY_SIZE,
+    filecrypt::{self, FileCrypt},
+    prelude::*,
+};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock, RwLockWriteGuard},
+};
+
+/// Vault of the config file and crypt folder in the home folder, see `default_vault`.
+static DEFAULT_VAULT: OnceLock<Vault> = OnceLock::new();
+
+/// Keys of an unlocked keeper, set by `Vault::unlock_keeper`.
+#[derive(Default)]
+pub(crate) struct KeeperKeys {
+    /// master key the file keys are wrapped under.
+    pub(crate) master: Option<[u8; KEY_SIZE]>,
+
+    /// secret the keys of `KeyMode::Derived` files are derived from.
+    pub(crate) secret: Option<[u8; KEY_SIZE]>,
+}
+
+struct VaultState {
+    config: RwLock<Config>,
+    root: PathBuf,
+    log_path: PathBuf,
+    interface: RwLock<Interface>,
+    keeper: OnceLock<Pool<SqliteConnectionManager>>,
+    keys: RwLock<KeeperKeys>,
+}
+
+/// A config, a keeper and a crypt folder, the state every encrypt, decrypt and query works on.
+///
+/// Encrypt, decrypt and query are methods of `Vault`, so several vaults can be used side by side
+/// in one process. The free functions of this crate are thin wrappers over the same methods of
+/// the default vault (see `default_vault`), which loads the config file from the home folder and
+/// keeps `.crypt` files in `~/crypt`.
+///
+/// Cloning a `Vault` is cheap, the clones share their state.
+///
+/// # Example
+///
+/// ```ignore
+/// # use crypt_core::{config::Config, vault::Vault};
+/// let config = Config {
+///     database_path: "/srv/vaults/a/keeper.db".to_string(),
+///     ..Default::default()
+/// };
+/// let vault = Vault::new(config, "/srv/vaults/a/crypt")?;
+/// vault.unlock_keeper("passphrase")?;
+/// vault.encrypt_file("report.pdf", &None)?;
+/// ```
+#[derive(Clone)]
+pub struct Vault(Arc<VaultState>);
+
+impl Vault {
+    /// Opens a vault keeping its keeper at `config.database_path` and its `.crypt` files under
+    /// `root`. Both are created if missing. Logs go to a `logs` folder next to the keeper.
+    ///
+    /// Changes made through `config_write` are saved to `config.path`, see
+    /// `config::load_config_from`. Without one, as for a `Config::default()`, the config is only
+    /// held in memory.
+    pub fn new<T: AsRef<Path>>(config: Config, root: T) -> Result<Self> {
+        let root = root.as_ref();
+        std::fs::create_dir_all(root)?;
+        let keeper = open_keeper(&config.database_path)?;
+
+        let log_dir = Path::new(&config.database_path)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join("logs");
+        let vault = Vault::from_parts(config, root.to_path_buf(), log_file_in(&log_dir));
+        _ = vault.0.keeper.set(keeper);
+        Ok(vault)
+    }
+
+    fn from_parts(config: Config, root: PathBuf, log_path: PathBuf) -> Self {
+        Vault(Arc::new(VaultState {
+            config: RwLock::new(config),
+            root,
+            log_path,
+            interface: RwLock::new(Interface::None),
+            keeper: OnceLock::new(),
+            keys: RwLock::new(KeeperKeys::default()),
+        }))
+    }
+
+    /// Gets a copy of the config of the vault.
+    pub fn config(&self) -> Config {
+        self.0
+            .config
+            .read()
+            .expect("Cannot read config, locked")
+            .clone()
+    }
+
+    /// Gets the config of the vault for writing.
+    pub fn config_write(&self) -> RwLockWriteGuard<'_, Config> {
+        self.0
+            .config
+            .write()
+            .expect("Cannot write to config, locked")
+    }
+
+    /// Gets the crypt folder of the vault, where `.crypt` files go by default.
+    pub fn root(&self) -> &Path {
+        &self.0.root
+    }
+
+    /// Gets the log file of the vault, see `config::init`.
+    pub fn log_path(&self) -> &Path {
+        &self.0.log_path
+    }
+
+    /// Gets the interface the vault is used from.
+    pub fn interface(&self) -> Interface {
+        self.0
+            .interface
+            .read()
+            .expect("Cannot read interface type")
+            .clone()
+    }
+
+    /// Sets the interface the vault is used from.
+    pub fn set_interface(&self, interface: &Interface) {
+        *self
+            .0
+            .interface
+            .write()
+            .expect("Cannot write interface type") = interface.clone();
+    }
+
+    /// Gets the keeper connection pool, opening the keeper on first use.
+    pub(crate) fn keeper(&self) -> Result<Pool<SqliteConnectionManager>> {
+        if let Some(pool) = self.0.keeper.get() {
+            return Ok(pool.clone());
+        }
+        let pool = open_keeper(&self.config().database_path)?;
+        Ok(self.0.keeper.get_or_init(|| pool).clone())
+    }
+
+    /// Gets the keys of the keeper, once unlocked.
+    pub(crate) fn keys(&self) -> &RwLock<KeeperKeys> {
+        &self.0.keys
+    }
+
+    /// Encrypts `contents` in memory with the config of the vault, see `filecrypt::seal_contents`.
+    pub fn seal(&self, contents: &[u8], name: &str) -> Result<(FileCrypt, Vec<u8>)> {
+        filecrypt::seal_contents(contents, name, &self.config())
+    }
+
+    /// Decrypts `.crypt` contents in memory, see `filecrypt::open_contents`.
+    pub fn open(&self, contents: &[u8], fc: &FileCrypt) -> Result<Vec<u8>> {
+        filecrypt::open_contents(contents, fc)
+    }
+}
+
+/// Gets the default vault, see `Vault`. The config file is loaded on first use.
+///
+/// # Errors
+///
+/// Returns the error of `config::load_config` if the config file cannot be loaded. Nothing is
+/// cached then, the next call tries again.
+pub fn default_vault() -> Result<Vault> {
+    if let Some(vault) = DEFAULT_VAULT.get() {
+        return Ok(vault.clone());
+    }
+    let vault = Vault::from_parts(load_config()?, home_crypt_folder(), default_log_path());
+    Ok(DEFAULT_VAULT.get_or_init(|| vault).clone())
+}
+
+/// Gets the log file of the default vault, which is known without loading its config.
+pub(crate) fn default_log_path() -> PathBuf {
+    log_file_in(&get_config_folder().join("logs"))
+}
+
+/// A vault in a fresh temporary folder, for tests. The keeper and the config live in the
+/// folder, the crypt folder is its `crypt` subfolder. The folder is removed on drop.
+#[cfg(test)]
+pub(crate) struct TempVault {
+    vault: Vault,
+    dir: PathBuf,
+}
+
+#[cfg(test)]
+impl TempVault {
+    /// Opens a vault in a fresh temporary folder named after `name`, with a locked keeper.
+    pub(crate) fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("crypt-vault-{}-{}", name, std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let config = Config {
+            database_path: dir.join("keeper.db").display().to_string(),
+            ..Default::default()
+        };
+        let vault = Vault::new(config, dir.join("crypt")).unwrap();
+        TempVault { vault, dir }
+    }
+
+    /// Opens a vault like `TempVault::new`, with its keeper unlocked by `"passphrase"`.
+    pub(crate) fn unlocked(name: &str) -> Self {
+        let temp = Self::new(name);
+        temp.unlock_keeper("passphrase").unwrap();
+        temp
+    }
+
+    /// Gets the temporary folder, the parent of the crypt folder.
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempVault {
+    type Target = Vault;
+
+    fn deref(&self) -> &Vault {
+        &self.vault
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempVault {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::load_config_from, error::DatabaseError};
+
+    #[test]
+    fn test_vaults_are_isolated() {
+        let a = TempVault::new("a");
+        let b = TempVault::new("b");
+        a.config_write().retain = false;
+
+        assert_eq!(a.get_crypt_folder(), a.root());
+        assert_eq!(b.get_crypt_folder(), b.root());
+        assert!(!a.config().retain);
+        assert!(b.config().retain);
+
+        // the setters save to the config file of the vault, if it has one
+        let path = b.dir().join("config.toml");
+        b.config_write().path = Some(path.clone());
+        a.config_write().set_retain(true);
+        b.config_write().set_retain(false);
+        assert!(a.config().retain);
+        assert!(!load_config_from(&path).unwrap().retain);
+
+        b.set_interface(&Interface::CLI);
+        assert!(matches!(b.interface(), Interface::CLI));
+        assert!(matches!(a.interface(), Interface::None));
+
+        // a thread of its own still works on the vault it was handed
+        let initialized = std::thread::spawn({
+            let a = Vault::clone(&a);
+            move || a.keeper_is_initialized().unwrap()
+        });
+        assert!(!initialized.join().unwrap());
+        assert!(matches!(
+            b.query_keeper_crypt(),
+            Err(Error::DatabaseError(DatabaseError::KeeperLocked))
+        ));
+    }
+}
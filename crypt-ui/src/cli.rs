@@ -1,14 +1,20 @@
-use std::path::PathBuf;
+use std::{fmt::Display, path::PathBuf, str::FromStr};
 
 use clap::{Parser, Subcommand};
 use crypt_cloud::crypt_core::{
-    common::{get_machine_name, send_information},
+    common::{build_tree, get_machine_name, json_output, send_information, set_json_output},
     config::{self, ConfigTask, ItemsTask},
-    db::import_keeper,
+    db::{import_keeper, keeper_is_initialized},
+    prelude::Error as CoreError,
+    vault::default_vault,
 };
+use serde::Serialize;
+
+use crate::error::{DirectiveError, EXIT_FAILURE};
+use crate::prelude::*;
 
 use crate::directive::{
-    self, dropbox_download, dropbox_upload, dropbox_view, google_download, google_view,
+    self, dropbox_download, dropbox_upload, dropbox_view, google_download, google_view, Listing,
 };
 // use crate::tui::load_tui;
 
@@ -32,6 +38,11 @@ pub struct CommandLineArgs {
     #[arg(short, default_value_t = false)]
     pub test: bool,
 
+    ///Print results as JSON on stdout, messages go to stderr. Applies to encrypt, decrypt, ls,
+    ///keeper list, cloud view and config
+    #[arg(long, global = true, default_value_t = false)]
+    pub json: bool,
+
     ///Subcommands
     #[command(subcommand)]
     command: Option<Commands>,
@@ -56,26 +67,183 @@ enum Commands {
 
     ///Encrypt file or folder of files
     Encrypt {
-        ///Path to File or Directory
+        ///Path to File or Directory, - reads stdin and writes to stdout unless -o is given
         #[arg(required = true)]
         path: String,
 
-        ///Change the output path
+        ///Change the output path, - writes the .crypt stream to stdout
         #[arg(short = 'o', long, required = false)]
         output: Option<String>,
+
+        ///Name recorded in the keeper for contents read from stdin
+        #[arg(short = 'n', long, required = false)]
+        name: Option<String>,
+
+        ///Protect with a passphrase instead of the keeper (reads CRYPT_PASSPHRASE or prompts)
+        #[arg(short = 'p', long, default_value_t = false)]
+        passphrase: bool,
+
+        ///Encrypt to a recipient (registered name or public key), can be repeated
+        #[arg(short = 'r', long = "recipient", conflicts_with = "passphrase")]
+        recipients: Vec<String>,
+
+        ///Train a zstd dictionary on the directory first, for trees of many small files
+        #[arg(
+            short = 'z',
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["passphrase", "recipients"]
+        )]
+        dictionary: bool,
+
+        ///Pack the directory into a single .crypt archive, hiding its names and structure
+        #[arg(
+            short = 'a',
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["passphrase", "recipients", "dictionary"]
+        )]
+        archive: bool,
+
+        ///Keep the originals, overriding the retain config for this run
+        #[arg(long, default_value_t = false, conflicts_with = "remove")]
+        keep: bool,
+
+        ///Overwrite and delete the originals once their .crypt files verify, overriding the
+        ///retain config for this run
+        #[arg(long, default_value_t = false)]
+        remove: bool,
     },
 
     ///Decrypt file or folder of files
     Decrypt {
-        ///Path to File or Directory
+        ///Path to File or Directory, - reads a .crypt stream from stdin and writes to stdout
+        #[arg(required = false, default_value_t = String::from(""))]
+        path: String,
+
+        ///Change the output path, - writes to stdout (path must then name a .crypt file)
+        #[arg(short = 'o', long, required = false)]
+        output: Option<String>,
+
+        ///Decrypt with a passphrase (reads CRYPT_PASSPHRASE or prompts)
+        #[arg(short = 'p', long, default_value_t = false)]
+        passphrase: bool,
+
+        ///List the members of an archive instead of extracting it
+        #[arg(
+            short = 'l',
+            long,
+            default_value_t = false,
+            conflicts_with = "passphrase"
+        )]
+        list: bool,
+
+        ///Extract only this member of an archive (file or directory), can be repeated
+        #[arg(short = 'm', long = "member", conflicts_with_all = ["passphrase", "list"])]
+        members: Vec<String>,
+
+        ///Keep the .crypt files, overriding the retain config for this run
+        #[arg(long, default_value_t = false, conflicts_with = "remove")]
+        keep: bool,
+
+        ///Delete the .crypt files once decrypted, overriding the retain config for this run
+        #[arg(long, default_value_t = false)]
+        remove: bool,
+    },
+
+    ///Decrypt files back to the location they were encrypted from
+    Restore {
+        ///Name of the .crypt file, or folder of the crypt folder to restore the whole tree of
+        #[arg(required = true)]
+        path: String,
+
+        ///What to do when the location is taken: overwrite, skip or rename
+        #[arg(short = 'c', long, default_value_t = String::from("skip"))]
+        conflict: String,
+
+        ///Restore under this folder instead, e.g. on another machine
+        #[arg(long, required = false)]
+        prefix: Option<String>,
+
+        ///Keep the .crypt files, overriding the retain config for this run
+        #[arg(long, default_value_t = false, conflicts_with = "remove")]
+        keep: bool,
+
+        ///Delete the .crypt files once restored, overriding the retain config for this run
+        #[arg(long, default_value_t = false)]
+        remove: bool,
+    },
+
+    ///Check that every file in the crypt folder decrypts, without writing any plaintext.
+    ///Exits non-zero if any file fails
+    Verify {},
+
+    ///Re-key crypt files with a fresh key, nonce and uuid
+    Rotate {
+        ///Name or path of the .crypt file
+        #[arg(required = false, default_value_t = String::from(""))]
+        path: String,
+
+        ///Rotate every file tracked by the keeper
+        #[arg(short = 'a', long, default_value_t = false, conflicts_with = "path")]
+        all: bool,
+
+        ///Re-upload rotated files that are on Google Drive
+        #[arg(short = 'u', long, default_value_t = false)]
+        upload: bool,
+    },
+
+    ///Re-encrypt a crypt file to an age recipient or passphrase
+    ExportAge {
+        ///Name or path of the .crypt file
         #[arg(required = false, default_value_t = String::from(""))]
         path: String,
 
+        ///age recipient (age1...) to encrypt to
+        #[arg(short = 'r', long, required_unless_present = "passphrase")]
+        recipient: Option<String>,
+
+        ///Encrypt with a passphrase instead (reads CRYPT_PASSPHRASE or prompts)
+        #[arg(
+            short = 'p',
+            long,
+            default_value_t = false,
+            conflicts_with = "recipient"
+        )]
+        passphrase: bool,
+    },
+
+    ///Turn an age file into a crypt file tracked by the keeper
+    ImportAge {
+        ///Path to the .age file
+        #[arg(required = true)]
+        path: String,
+
+        ///age identity file to decrypt with
+        #[arg(short = 'i', long, required_unless_present = "passphrase")]
+        identity: Option<String>,
+
+        ///Decrypt with a passphrase instead (reads CRYPT_PASSPHRASE or prompts)
+        #[arg(
+            short = 'p',
+            long,
+            default_value_t = false,
+            conflicts_with = "identity"
+        )]
+        passphrase: bool,
+
         ///Change the output path
         #[arg(short = 'o', long, required = false)]
         output: Option<String>,
     },
 
+    ///Check the backup copies of crypt files
+    Backup {
+        /// Categories
+        #[command(subcommand)]
+        category: Option<BackupCommand>,
+    },
+
     ///Import | Export | Purge database
     Keeper {
         /// Categories
@@ -83,6 +251,13 @@ enum Commands {
         category: Option<KeeperCommand>,
     },
 
+    ///Manage your identity and the recipients you share files with
+    Recipient {
+        /// Categories
+        #[command(subcommand)]
+        category: Option<RecipientCommand>,
+    },
+
     /// show local / cloud crypt folder
     Ls {
         ///Show all files contained in the local crypt folder
@@ -95,6 +270,27 @@ enum Commands {
     },
 }
 
+impl Commands {
+    /// Returns `true` if the command reads or writes file keys in the keeper.
+    fn uses_keeper(&self) -> bool {
+        match self {
+            Commands::Encrypt { passphrase, .. } => !passphrase,
+            Commands::Decrypt { .. }
+            | Commands::Restore { .. }
+            | Commands::Cloud { .. }
+            | Commands::Recipient { .. }
+            | Commands::Rotate { .. }
+            | Commands::Verify { .. }
+            | Commands::ExportAge { .. }
+            | Commands::ImportAge { .. } => true,
+            Commands::Keeper { category } => !matches!(category, Some(KeeperCommand::Purge { .. })),
+            // hidden names resolve through the keeper, whatever the current name mode is
+            Commands::Ls { .. } => keeper_is_initialized().unwrap_or(false),
+            Commands::Config { .. } | Commands::Backup { .. } => false,
+        }
+    }
+}
+
 ///Subcommands for Upload
 #[derive(Subcommand, Debug)]
 pub enum CloudCommand {
@@ -113,7 +309,6 @@ pub enum CloudCommand {
     },
 }
 
-///
 #[derive(Subcommand, Debug, Clone)]
 pub enum DriveCommand {
     /// Upload a file or folder
@@ -176,6 +371,18 @@ pub enum ConfigCommand {
         item: String,
     },
 
+    /// View or change which file extensions are always stored uncompressed
+    #[command()]
+    NoCompress {
+        /// add or remove, leave empty to view the list
+        #[arg(required = false, default_value_t = String::from(""))]
+        add_remove: String,
+
+        /// extension, e.g. jpg
+        #[arg(required = false, default_value_t = String::from(""))]
+        ext: String,
+    },
+
     /// View or change current pc name associated with the cloud.
     #[command()]
     Hwid {},
@@ -188,6 +395,64 @@ pub enum ConfigCommand {
         level: String,
     },
 
+    /// View or change the cipher used for new files (xchacha20poly1305, chacha20poly1305,
+    /// aes256gcmsiv)
+    #[command()]
+    Cipher {
+        /// value to update config
+        #[arg(required = false, default_value_t = String::from(""))]
+        cipher: String,
+    },
+
+    /// View or change how .crypt files are named (plain, uuid, encrypted)
+    #[command()]
+    NameMode {
+        /// value to update config
+        #[arg(required = false, default_value_t = String::from(""))]
+        name_mode: String,
+    },
+
+    /// View or change how the keys of new files are produced (random, derived)
+    #[command()]
+    KeyMode {
+        /// value to update config
+        #[arg(required = false, default_value_t = String::from(""))]
+        key_mode: String,
+    },
+
+    /// View or change the padding that hides the length of new files (none, padme, pow2)
+    #[command()]
+    Padding {
+        /// value to update config
+        #[arg(required = false, default_value_t = String::from(""))]
+        padding: String,
+    },
+
+    /// View or change whether originals are kept after encryption, and .crypt files after
+    /// decryption (true, false)
+    #[command()]
+    Retain {
+        /// value to update config
+        #[arg(required = false, default_value_t = String::from(""))]
+        retain: String,
+    },
+
+    /// View or change whether crypt files are copied to the backup folder (true, false)
+    #[command()]
+    Backup {
+        /// value to update config
+        #[arg(required = false, default_value_t = String::from(""))]
+        backup: String,
+    },
+
+    /// View or update the backup folder path
+    #[command()]
+    BackupPath {
+        /// Backup path; if empty, prints current path
+        #[arg(required = false, default_value_t = String::from(""))]
+        path: String,
+    },
+
     /// Revert config back to default
     #[command(short_flag = 'l')]
     LoadDefault,
@@ -222,6 +487,55 @@ pub enum KeeperCommand {
     /// List each file in the database
     #[command(short_flag = 'l')]
     List {},
+
+    /// Show the secret derived file keys come from, back it up to recover derived files
+    #[command(short_flag = 's')]
+    Secret {
+        /// Replace the secret with a backed up one (hex)
+        #[arg(long)]
+        restore: Option<String>,
+    },
+}
+
+/// Subcommands for Backup
+#[derive(Subcommand, Debug)]
+pub enum BackupCommand {
+    /// Check that the backup folder matches the crypt folder by hash. Exits non-zero if it does
+    /// not
+    Verify {},
+}
+
+/// Subcommands for Recipient
+#[derive(Subcommand, Debug)]
+pub enum RecipientCommand {
+    /// Show your public key, generating an identity if there is none
+    #[command(short_flag = 'i')]
+    Identity {
+        /// Replace the identity, files encrypted to the old one can no longer be decrypted
+        #[arg(long, default_value_t = false)]
+        regenerate: bool,
+    },
+
+    /// Register the public key of a recipient
+    #[command(short_flag = 'a')]
+    Add {
+        #[arg(required = true)]
+        name: String,
+
+        #[arg(required = true)]
+        key: String,
+    },
+
+    /// Remove a recipient
+    #[command(short_flag = 'r')]
+    Remove {
+        #[arg(required = true)]
+        name: String,
+    },
+
+    /// List each registered recipient
+    #[command(short_flag = 'l')]
+    List {},
 }
 
 /// Subcommands for Keeper
@@ -237,24 +551,27 @@ pub enum KeeperPurgeSubCommand {
 }
 
 impl KeeperCommand {
-    pub fn import(path: &String) {
+    pub fn import(path: &String) -> Result<()> {
         if path.is_empty() {
-            send_information(vec![format!("please add a path to the csv")]);
-            return;
-        }
-        match import_keeper(path) {
-            Ok(_) => (),
-            Err(e) => panic!("problem importing keeper to database! {}", e),
+            return Err(DirectiveError::InvalidInput("please add a path to the csv".into()).into());
         }
+        import_keeper(path)?;
+        Ok(())
     }
 }
 
 /// Runs the CLI and returns a directive to be processed
+///
+/// Exits with `0` on success, otherwise with the exit code of the error category, see
+/// `error::EXIT_FAILURE` and the other `EXIT_` constants.
 pub fn load_cli() {
-    config::init(config::Interface::CLI);
+    if let Err(e) = config::init(config::Interface::CLI) {
+        fail("init", e.into());
+    }
 
     // Run the cli and get responses
     let cli = CommandLineArgs::parse();
+    set_json_output(cli.json);
 
     // Invoked as: `crypt --md > commands.md`
     if cli.md {
@@ -275,25 +592,158 @@ pub fn load_cli() {
         directive::test();
     }
 
+    // file keys in the keeper are wrapped under a master key, unlock it once for the session
+    if cli.command.as_ref().is_some_and(Commands::uses_keeper) {
+        if let Err(e) = directive::unlock_keeper() {
+            fail("unlock keeper", e);
+        }
+    }
+
     // Process the command passed by the user
     match &cli.command {
         // Nothing passed (Help screen printed)
         None => (),
 
         // ls
-        Some(Commands::Ls { local, cloud }) => {
-            directive::ls(local, cloud);
-        }
+        Some(Commands::Ls { local, cloud }) => match directive::ls(local, cloud) {
+            Ok(listing) => report(&listing, Listing::print),
+            Err(e) => fail("ls", e),
+        },
 
         // Encryption
-        Some(Commands::Encrypt { path, output }) => {
-            let res = directive::encrypt(path, output.to_owned());
-            println!("encrypt result: {:?}", res);
+        Some(Commands::Encrypt {
+            path,
+            output,
+            name,
+            passphrase,
+            recipients,
+            dictionary,
+            archive,
+            keep,
+            remove,
+        }) => {
+            // stdout carries the stream, report on stderr only
+            if path == "-" || output.as_deref() == Some("-") {
+                if *passphrase || !recipients.is_empty() || *dictionary || *archive {
+                    fail("encrypt", DirectiveError::StreamUnsupported.into());
+                }
+                if let Err(e) = directive::encrypt_stdio(path, output.to_owned(), name.to_owned()) {
+                    fail("encrypt", e);
+                }
+                return;
+            }
+            override_retain(*keep, *remove);
+            let res = directive::encrypt(
+                path,
+                output.to_owned(),
+                *passphrase,
+                recipients,
+                *dictionary,
+                *archive,
+            );
+            match res {
+                Ok(summary) => {
+                    report(&summary, print_display);
+                    if !summary.failed.is_empty() {
+                        std::process::exit(EXIT_FAILURE);
+                    }
+                }
+                Err(e) => fail("encrypt", e),
+            }
         }
 
         // Decryption
-        Some(Commands::Decrypt { path, output }) => {
-            directive::decrypt(path, output.to_owned());
+        Some(Commands::Decrypt {
+            path,
+            output,
+            passphrase,
+            list,
+            members,
+            keep,
+            remove,
+        }) => {
+            override_retain(*keep, *remove);
+            if path == "-" || output.as_deref() == Some("-") {
+                // stdout carries the plaintext, report on stderr only
+                if let Err(e) = directive::decrypt_stdio(path) {
+                    fail("decrypt", e);
+                }
+            } else if *list {
+                if let Err(e) = directive::list_archive(path) {
+                    fail("list", e);
+                }
+            } else if !members.is_empty() {
+                if let Err(e) = directive::extract_archive(path, output.to_owned(), members) {
+                    fail("extract", e);
+                }
+            } else {
+                match directive::decrypt(path, output.to_owned(), *passphrase) {
+                    Ok(summary) => {
+                        report(&summary, print_display);
+                        if !summary.failed.is_empty() {
+                            std::process::exit(EXIT_FAILURE);
+                        }
+                    }
+                    Err(e) => fail("decrypt", e),
+                }
+            }
+        }
+
+        // Restore
+        Some(Commands::Restore {
+            path,
+            conflict,
+            prefix,
+            keep,
+            remove,
+        }) => {
+            let policy = match conflict.parse() {
+                Ok(policy) => policy,
+                Err(e) => fail("restore", Error::CoreError(e)),
+            };
+            override_retain(*keep, *remove);
+            match directive::restore(path, policy, prefix.to_owned()) {
+                Ok(true) => (),
+                Ok(false) => std::process::exit(EXIT_FAILURE),
+                Err(e) => fail("restore", e),
+            }
+        }
+
+        // Integrity scan
+        Some(Commands::Verify {}) => match directive::verify() {
+            Ok(true) => (),
+            Ok(false) => std::process::exit(EXIT_FAILURE),
+            Err(e) => fail("verify", e),
+        },
+
+        // Key rotation
+        Some(Commands::Rotate { path, all, upload }) => {
+            match directive::rotate(path, *all, *upload) {
+                Ok(true) => (),
+                Ok(false) => std::process::exit(EXIT_FAILURE),
+                Err(e) => fail("rotate", e),
+            }
+        }
+
+        // age interoperability
+        Some(Commands::ExportAge {
+            path,
+            recipient,
+            passphrase: _,
+        }) => {
+            if let Err(e) = directive::export_age(path, recipient.to_owned()) {
+                fail("export", e);
+            }
+        }
+        Some(Commands::ImportAge {
+            path,
+            identity,
+            passphrase: _,
+            output,
+        }) => {
+            if let Err(e) = directive::import_age(path, identity.to_owned(), output.to_owned()) {
+                fail("import", e);
+            }
         }
 
         // Cloud commands - upload | download | view for Google Drive and TODO: Dropbox
@@ -302,19 +752,20 @@ pub fn load_cli() {
             Some(CloudCommand::Google { task }) => {
                 match task {
                     Some(DriveCommand::Upload {}) => {
-                        let response = directive::google_upload();
-                        if let Err(e) = response {
-                            println!("error: {}", e);
+                        if let Err(e) = directive::google_upload() {
+                            fail("upload", e);
                         }
                     }
                     Some(DriveCommand::Download { path }) => {
-                        let response = google_download(path);
-                        if let Err(e) = response {
-                            println!("error: {}", e);
+                        if let Err(e) = google_download(path) {
+                            fail("download", e);
                         }
                     }
-                    Some(DriveCommand::View { path }) => _ = google_view(path),
-                    None => panic!("invalid input"),
+                    Some(DriveCommand::View { path }) => match google_view(path) {
+                        Ok(dir) => report(&dir, |dir| send_information(build_tree(dir))),
+                        Err(e) => fail("view", e),
+                    },
+                    None => fail("cloud", missing_task()),
                 };
             }
 
@@ -325,60 +776,230 @@ pub fn load_cli() {
                     Some(DriveCommand::Upload {}) => dropbox_upload(""),
                     Some(DriveCommand::Download { path }) => dropbox_download(path),
                     Some(DriveCommand::View { path }) => dropbox_view(path),
-                    None => panic!("invalid input"),
+                    None => fail("cloud", missing_task()),
                 };
             }
 
             None => {}
         },
+        // Backup
+        Some(Commands::Backup {
+            category: Some(BackupCommand::Verify {}),
+        }) => match directive::verify_backup() {
+            Ok(true) => (),
+            Ok(false) => std::process::exit(EXIT_FAILURE),
+            Err(e) => fail("backup verify", e),
+        },
+        Some(Commands::Backup { category: None }) => (),
+
         // Keeper
-        Some(Commands::Keeper { category }) => {
-            let kc = category.as_ref().unwrap();
-            directive::keeper(kc);
+        Some(Commands::Keeper {
+            category: Some(KeeperCommand::List {}),
+        }) if cli.json => match directive::keeper_list() {
+            Ok(entries) => report(&entries, |_| ()),
+            Err(e) => fail("keeper", e),
+        },
+        Some(Commands::Keeper { category: Some(kc) }) => {
+            if let Err(e) = directive::keeper(kc) {
+                fail("keeper", e);
+            }
         }
+        Some(Commands::Keeper { category: None }) => (),
+
+        // Recipient
+        Some(Commands::Recipient { category: Some(rc) }) => {
+            if let Err(e) = directive::recipient(rc) {
+                fail("recipient", e);
+            }
+        }
+        Some(Commands::Recipient { category: None }) => (),
 
         // Config
         Some(Commands::Config { category }) => {
-            match category {
-                Some(ConfigCommand::DatabasePath { path }) => {
-                    directive::config(path, ConfigTask::DatabasePath);
+            if let Err(e) = run_config(category) {
+                fail("config", e);
+            }
+            // prints the config as it is now, after any change
+            if cli.json || category.is_none() {
+                match config::get_config() {
+                    Ok(config) => report(&config, print_display),
+                    Err(e) => fail("config", e.into()),
                 }
+            }
+        }
+    }
+}
 
-                Some(ConfigCommand::CryptPath { path }) => {
-                    directive::config(path, ConfigTask::CryptPath);
-                }
+/// Runs a config subcommand.
+fn run_config(category: &Option<ConfigCommand>) -> Result<()> {
+    match category {
+        Some(ConfigCommand::DatabasePath { path }) => {
+            directive::config(path, ConfigTask::DatabasePath)?;
+        }
 
-                // IgnoreItems
-                Some(ConfigCommand::IgnoreItems { add_remove, item }) => {
-                    let add_remove = match add_remove.to_lowercase().as_str() {
-                        "add" | "a" => ItemsTask::Add,
-                        "remove" | "r" => ItemsTask::Remove,
-                        _ => panic!("invalid input"),
-                    };
+        Some(ConfigCommand::CryptPath { path }) => {
+            directive::config(path, ConfigTask::CryptPath)?;
+        }
 
-                    directive::config("", ConfigTask::IgnoreItems(add_remove, item.to_owned()));
+        // IgnoreItems
+        Some(ConfigCommand::IgnoreItems { add_remove, item }) => {
+            match parse_items_task(add_remove)? {
+                Some(add_remove) => {
+                    directive::config("", ConfigTask::IgnoreItems(add_remove, item.to_owned()))?
                 }
+                None => send_information(vec![format!(
+                    "ignored: {:?}",
+                    config::get_config()?.ignore_items
+                )]),
+            }
+        }
 
-                // ZstdLevel
-                Some(ConfigCommand::ZstdLevel { level }) => {
-                    let level: i32 = level.parse().expect("Could not interpret passed value");
-                    directive::config("", ConfigTask::ZstdLevel(level));
+        // NoCompress
+        Some(ConfigCommand::NoCompress { add_remove, ext }) => {
+            match parse_items_task(add_remove)? {
+                Some(add_remove) => {
+                    directive::config("", ConfigTask::NoCompress(add_remove, ext.to_owned()))?
                 }
+                None => send_information(vec![format!(
+                    "always uncompressed: {:?}",
+                    config::get_config()?.get_no_compress()
+                )]),
+            }
+        }
 
-                //Hwid
-                Some(ConfigCommand::Hwid {}) => {
-                    send_information(vec![format!("machine name: {}", get_machine_name())]);
-                }
+        // ZstdLevel
+        Some(ConfigCommand::ZstdLevel { level }) => {
+            let level: i32 = level
+                .parse()
+                .map_err(|_| DirectiveError::InvalidInput(format!("zstd level {level}")))?;
+            directive::config("", ConfigTask::ZstdLevel(level))?;
+        }
 
-                // LoadDefault
-                Some(ConfigCommand::LoadDefault) => {
-                    directive::config("", ConfigTask::LoadDefault);
-                }
+        // Cipher
+        Some(ConfigCommand::Cipher { cipher }) => {
+            let cipher = parse_optional(cipher)?;
+            directive::config("", ConfigTask::Cipher(cipher))?;
+        }
 
-                None => (),
-            }
-            // let config = config::get_config();
-            // println!("{}", config);
+        // NameMode
+        Some(ConfigCommand::NameMode { name_mode }) => {
+            let name_mode = parse_optional(name_mode)?;
+            directive::config("", ConfigTask::NameMode(name_mode))?;
+        }
+
+        // KeyMode
+        Some(ConfigCommand::KeyMode { key_mode }) => {
+            let key_mode = parse_optional(key_mode)?;
+            directive::config("", ConfigTask::KeyMode(key_mode))?;
+        }
+
+        // Padding
+        Some(ConfigCommand::Padding { padding }) => {
+            let padding = parse_optional(padding)?;
+            directive::config("", ConfigTask::Padding(padding))?;
+        }
+
+        // Retain
+        Some(ConfigCommand::Retain { retain }) => {
+            let retain = parse_bool(retain)?;
+            directive::config("", ConfigTask::Retain(retain))?;
+        }
+
+        // Backup
+        Some(ConfigCommand::Backup { backup }) => {
+            let backup = parse_bool(backup)?;
+            directive::config("", ConfigTask::Backup(backup))?;
+        }
+
+        Some(ConfigCommand::BackupPath { path }) => {
+            directive::config(path, ConfigTask::BackupPath)?;
+        }
+
+        //Hwid
+        Some(ConfigCommand::Hwid {}) => {
+            send_information(vec![format!("machine name: {}", get_machine_name())]);
+        }
+
+        // LoadDefault
+        Some(ConfigCommand::LoadDefault) => {
+            directive::config("", ConfigTask::LoadDefault)?;
+        }
+
+        None => (),
+    }
+    Ok(())
+}
+
+/// Parses `add` / `remove` of a config list, `None` if empty to view the list.
+fn parse_items_task(add_remove: &str) -> Result<Option<ItemsTask>> {
+    match add_remove.to_lowercase().as_str() {
+        "add" | "a" => Ok(Some(ItemsTask::Add)),
+        "remove" | "r" => Ok(Some(ItemsTask::Remove)),
+        "" => Ok(None),
+        _ => Err(DirectiveError::InvalidInput("expected add or remove".into()).into()),
+    }
+}
+
+/// Parses a config value, `None` if empty to view the current value.
+fn parse_optional<T: FromStr<Err = CoreError>>(value: &str) -> Result<Option<T>> {
+    match value.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(value.parse()?)),
+    }
+}
+
+/// Parses `true` / `false`, `None` if empty to view the current value.
+fn parse_bool(value: &str) -> Result<Option<bool>> {
+    match value.is_empty() {
+        true => Ok(None),
+        false => value
+            .parse()
+            .map(Some)
+            .map_err(|_| DirectiveError::InvalidInput("expected true or false".into()).into()),
+    }
+}
+
+fn missing_task() -> Error {
+    DirectiveError::InvalidInput("expected upload, download or view".into()).into()
+}
+
+/// Prints `value` as JSON with `--json`, otherwise for the user with `text`.
+fn report<T: Serialize>(value: &T, text: impl FnOnce(&T)) {
+    if !json_output() {
+        return text(value);
+    }
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => fail("print", Error::CoreError(e.into())),
+    }
+}
+
+fn print_display<T: Display>(value: &T) {
+    send_information(vec![value.to_string()]);
+}
+
+/// Reports `err` on `stderr`, or as `{"error": {"code": .., "message": ..}}` on `stdout` with
+/// `--json`, then exits with the exit code of the error, see `Error::exit_code`.
+fn fail(context: &str, err: Error) -> ! {
+    let code = err.exit_code();
+    let message = format!("{} failed: {}", context, err);
+    match json_output() {
+        true => println!(
+            "{}",
+            serde_json::json!({ "error": { "code": code, "message": message } })
+        ),
+        false => eprintln!("{}", message),
+    }
+    std::process::exit(code)
+}
+
+/// Applies `--keep` / `--remove` to `Config::retain` for this run only, the saved config is left
+/// as is.
+fn override_retain(keep: bool, remove: bool) {
+    if keep || remove {
+        match default_vault() {
+            Ok(vault) => vault.config_write().retain = keep,
+            Err(e) => fail("config", e.into()),
         }
     }
 }
@@ -2,34 +2,85 @@ use crate::{
     cli::{
         KeeperCommand,
         KeeperPurgeSubCommand::{Database, Token},
+        RecipientCommand,
     },
-    error,
+    error::{self, DirectiveError, DownloadError},
     prelude::*,
 };
 use crypt_cloud::{
     crypt_core::{
+        age_file::{self, AgeIdentity, AgeTarget},
+        archive::EntryKind,
+        backup,
         common::{
             build_tree, chooser, get_crypt_folder, get_filenames_from_subdirectories,
-            get_full_file_path, send_information, verify_path, walk_crypt_folder, walk_directory,
+            get_full_file_path, parallel_map, send_information, verify_path, walk_crypt_folder,
+            walk_directory, DirInfo, FsNode,
         },
-        config::{self, Config, ConfigTask, ItemsTask},
+        config::{Config, ConfigTask, ItemsTask},
         db::{self, delete_keeper, export_keeper, query_crypt, query_keeper_crypt},
-        filecrypt::{decrypt_contents, decrypt_file, encrypt_file, get_uuid_from_file},
+        error::FcError,
+        filecrypt::{
+            self, decrypt_contents, decrypt_file_with, decrypt_to_writer, encrypt_archive,
+            encrypt_file_to_recipients, encrypt_file_with_passphrase, encrypt_files,
+            encrypt_reader, encrypt_to_writer, get_uuid_from_file, resolve_crypt_name,
+            restore_file, rotate_file, train_file_dictionary, verify_file, ConflictPolicy,
+            Dictionary, EncryptJob, EncryptStatus, FileCrypt, RestoreStatus,
+        },
         filetree::{
-            tree::{dir_walk, is_not_hidden, sort_by_name, Directory},
+            tree::{dir_walk, is_not_hidden, sort_by_name, Directory, FileTree},
             treeprint::print_tree,
         },
+        prelude::Error as CoreError,
+        recipient::{Identity, RecipientKey},
         token::{purge_tokens, UserToken},
+        vault::default_vault,
     },
     drive,
 };
+use serde::Serialize;
 use std::{
-    fs,
+    fmt, fs,
+    io::{self, BufReader, Read},
     path::{Path, PathBuf},
 };
 use tokio::runtime::Runtime;
 // #############################################################################################################################################
 
+/// Environment variable checked for a file passphrase before prompting the user.
+pub const PASSPHRASE_ENV: &str = "CRYPT_PASSPHRASE";
+
+/// Environment variable checked for the keeper passphrase before prompting the user.
+pub const KEEPER_PASSPHRASE_ENV: &str = "CRYPT_KEEPER_PASSPHRASE";
+
+/// Gets a passphrase from the `env` environment variable if set, otherwise by prompting the
+/// user with `prompt`. When `confirm` is set the user has to type the passphrase twice.
+pub fn read_passphrase(env: &str, prompt: &str, confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(env) {
+        return Ok(passphrase);
+    }
+
+    let passphrase = rpassword::prompt_password(prompt)?;
+    if confirm && passphrase != rpassword::prompt_password(format!("confirm {prompt}"))? {
+        return Err(DirectiveError::PassphraseMismatch.into());
+    }
+    Ok(passphrase)
+}
+
+/// Unlocks the keeper for this session. The first run on a new or older database asks the user
+/// to set a keeper passphrase.
+pub fn unlock_keeper() -> Result<()> {
+    let initialized = db::keeper_is_initialized()?;
+    if !initialized {
+        send_information(vec![format!(
+            "set a passphrase to protect the keys stored in the keeper"
+        )]);
+    }
+    let passphrase = read_passphrase(KEEPER_PASSPHRASE_ENV, "keeper passphrase: ", !initialized)?;
+    db::unlock_keeper(&passphrase)?;
+    Ok(())
+}
+
 ///Process the encryption directive
 ///
 /// # Example
@@ -41,20 +92,53 @@ use tokio::runtime::Runtime;
 /// let directive = Directive::new("relevant/file.path".to_string());
 /// directive.encrypt(in_place, output);
 ///```
-///TODO: implement output
-pub fn encrypt(path: &str, output: Option<String>) -> Result<()> {
+pub fn encrypt(
+    path: &str,
+    output: Option<String>,
+    passphrase: bool,
+    recipients: &[String],
+    dictionary: bool,
+    archive: bool,
+) -> Result<EncryptSummary> {
     // verify our path is pointing to a actual dir/file
     if !verify_path(&path) {
-        send_information(vec![format!("could not find path: {}", path)]);
-        return Ok(());
+        return Err(DirectiveError::PathNotFound(path.to_string()).into());
     }
 
+    if archive {
+        if !Path::new(path).is_dir() {
+            return Err(DirectiveError::NotADirectory(path.to_string()).into());
+        }
+        let crypt_path = encrypt_archive(path, &output)?;
+        return Ok(EncryptSummary {
+            encrypted: 1,
+            archive: Some(crypt_path),
+            ..Default::default()
+        });
+    }
+
+    let passphrase = match passphrase {
+        true => Some(read_passphrase(PASSPHRASE_ENV, "passphrase: ", true)?),
+        false => None,
+    };
+    let recipients = resolve_recipients(recipients)?;
+    let user_path: PathBuf = PathBuf::from(path);
+    let dictionary = match dictionary && user_path.is_dir() {
+        true => train_dictionary(path),
+        false => None,
+    };
+    let encrypt = |path: &str, output: &Option<String>| match (&passphrase, &recipients) {
+        (Some(p), _) => encrypt_file_with_passphrase(path, output, p),
+        (_, r) => encrypt_file_to_recipients(path, output, r),
+    };
+
     // get the difference between the user's current working directory, and the path they passed in.
 
     let mut root = PathBuf::new();
-    let user_path: PathBuf = PathBuf::from(path);
 
     //Determine if file or directory
+    let single_file = !user_path.is_dir();
+    let mut jobs = Vec::new();
     match user_path.is_dir() {
         true => {
             if let Ok(directory) = walk_directory(path, false) {
@@ -62,53 +146,504 @@ pub fn encrypt(path: &str, output: Option<String>) -> Result<()> {
                     if path.is_dir() {
                         root.push(path.file_name().unwrap());
                     } else if path.is_file() {
-                        encrypt_file(path.to_str().unwrap(), &Some(root.display().to_string()));
+                        jobs.push(EncryptJob {
+                            path,
+                            output: Some(root.display().to_string()),
+                        });
                     }
                 }
             }
         }
-        false => encrypt_file(path, &output),
+        false => jobs.push(EncryptJob {
+            path: user_path,
+            output,
+        }),
+    }
+
+    // keeper tracked files go through encrypt_files, which skips unchanged files and
+    // batches the keeper inserts
+    let results: Vec<std::result::Result<EncryptStatus, CoreError>> =
+        match passphrase.is_none() && recipients.is_empty() {
+            true => encrypt_files(&jobs, dictionary.as_ref())?
+                .into_iter()
+                .map(|res| res.map(|(status, _)| status))
+                .collect(),
+            false => parallel_map(&jobs, |job| {
+                encrypt(&job.path.to_string_lossy(), &job.output).map(|_| EncryptStatus::Encrypted)
+            }),
+        };
+
+    let mut summary = EncryptSummary::default();
+    for (job, res) in jobs.iter().zip(results) {
+        match res {
+            Ok(EncryptStatus::Encrypted) => summary.encrypted += 1,
+            Ok(EncryptStatus::Updated) => summary.updated += 1,
+            Ok(EncryptStatus::Unchanged) => summary.unchanged += 1,
+            // a single file fails the command like any other error
+            Err(e) if single_file => return Err(e.into()),
+            Err(e) => summary.failed.push(Failure::new(&job.path, e)),
+        }
+    }
+    Ok(summary)
+}
+
+/// A file `encrypt`, `decrypt` or `restore` failed on.
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+impl Failure {
+    fn new<E: std::fmt::Display>(path: &Path, error: E) -> Self {
+        Failure {
+            path: path.to_path_buf(),
+            error: error.to_string(),
+        }
+    }
+}
+
+/// What `encrypt` did, printed by the CLI.
+#[derive(Debug, Default, Serialize)]
+pub struct EncryptSummary {
+    pub encrypted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub failed: Vec<Failure>,
+
+    /// the `.crypt` file written by `--archive`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<PathBuf>,
+}
+
+impl fmt::Display for EncryptSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(archive) = &self.archive {
+            return write!(f, "archived to: {}", archive.display());
+        }
+        for failure in &self.failed {
+            writeln!(
+                f,
+                "failed to encrypt {}: {}",
+                failure.path.display(),
+                failure.error
+            )?;
+        }
+        write!(
+            f,
+            "{} encrypted, {} updated, {} unchanged, {} failed",
+            self.encrypted,
+            self.updated,
+            self.unchanged,
+            self.failed.len()
+        )
+    }
+}
+
+/// Encrypts stdin, or the file at `path`, as a keeper tracked file. The `.crypt` stream goes to
+/// stdout, or into the crypt folder under `output` when stdin is encrypted with `-o <folder>`.
+/// `name` is recorded as the file name, it defaults to `stdin` or the name of the file.
+pub fn encrypt_stdio(path: &str, output: Option<String>, name: Option<String>) -> Result<()> {
+    let name = name.unwrap_or_else(|| match path {
+        "-" => "stdin".to_string(),
+        _ => Path::new(path)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    });
+    let reader: Box<dyn Read> = match path {
+        "-" => Box::new(io::stdin().lock()),
+        _ => Box::new(BufReader::new(fs::File::open(path)?)),
+    };
+
+    match output.as_deref() {
+        None | Some("-") => {
+            encrypt_to_writer(&name, reader, io::stdout().lock())?;
+        }
+        Some(_) => {
+            let crypt_path = encrypt_reader(&name, reader, &output)?;
+            eprintln!("encrypted to: {}", crypt_path.display());
+        }
     }
     Ok(())
 }
 
+/// Trains a zstd dictionary on the files in the directory at `path`. Training fails on too few
+/// or too small files, the directory is then encrypted without a dictionary.
+fn train_dictionary(path: &str) -> Option<Dictionary> {
+    let files: Vec<PathBuf> = walk_directory(path, false)
+        .ok()?
+        .into_iter()
+        .filter(|p| p.is_file())
+        .collect();
+
+    match train_file_dictionary(&files) {
+        Ok(dictionary) => {
+            send_information(vec![format!(
+                "trained dictionary {} on {} files",
+                dictionary.id,
+                files.len()
+            )]);
+            Some(dictionary)
+        }
+        Err(e) => {
+            send_information(vec![format!(
+                "could not train a dictionary, encrypting without one: {}",
+                e
+            )]);
+            None
+        }
+    }
+}
+
+/// Looks up each recipient by name in the keeper, or parses it as a public key. Our own
+/// identity is added when there is at least one recipient, so we can still decrypt the file.
+fn resolve_recipients(recipients: &[String]) -> Result<Vec<RecipientKey>> {
+    if recipients.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut keys = Vec::with_capacity(recipients.len() + 1);
+    for recipient in recipients {
+        let key = match db::query_recipient(recipient) {
+            Ok(key) => key,
+            Err(_) => recipient.parse()?,
+        };
+        keys.push(key);
+    }
+    if let Some(identity) = db::query_identity()? {
+        let me = identity.recipient();
+        if !keys.contains(&me) {
+            keys.push(me);
+        }
+    }
+    Ok(keys)
+}
+
 ///Process the decryption directive
-pub fn decrypt(path: &str, output: Option<String>) {
-    let mut crypt_folder = get_crypt_folder();
+pub fn decrypt(path: &str, output: Option<String>, passphrase: bool) -> Result<DecryptSummary> {
+    let mut passphrase = match passphrase {
+        true => read_passphrase(PASSPHRASE_ENV, "passphrase: ", false).ok(),
+        false => None,
+    };
+    // prompt once the first passphrase protected file turns up, then reuse the answer
+    let mut decrypt = |path: &Path, output: String| {
+        decrypt_file_with(path, output, || {
+            if passphrase.is_none() {
+                passphrase = read_passphrase(PASSPHRASE_ENV, "passphrase: ", false).ok();
+            }
+            passphrase.clone()
+        })
+    };
+
+    let mut crypt_folder = get_crypt_folder()?;
     crypt_folder.push(path);
 
     let mut root = PathBuf::new();
-    println!(
-        "PathBuf::from(path).is_dir(): {}",
-        PathBuf::from(path).is_dir()
-    );
+    let mut summary = DecryptSummary::default();
     //Determine if file or directory
     match PathBuf::from(path).is_dir() {
         //directory
         true => {
             // get vec of dir
-            if let Ok(directory) = walk_directory(crypt_folder, false) { 
+            if let Ok(directory) = walk_directory(crypt_folder, false) {
                 for p in directory {
                     if p.is_dir() {
                         root.push(p.file_name().unwrap());
                     } else if p.is_file() {
                         send_information(vec![format!("Decrypting file: {}", p.display())]);
-                        let _res = decrypt_file(p, root.display().to_string());
+                        match decrypt(&p, root.display().to_string()) {
+                            Ok(_) => summary.decrypted += 1,
+                            Err(e) => summary.failed.push(Failure::new(&p, e)),
+                        }
                     }
                 }
             }
         }
         // file
         false => {
-            let res;
-            if let Some(o) = output {
-                res = decrypt_file(path, o);
-            } else {
-                res = decrypt_file(path, "".to_string());
+            decrypt(Path::new(path), output.unwrap_or_default())?;
+            summary.decrypted += 1;
+        }
+    };
+    Ok(summary)
+}
+
+/// What `decrypt` did, printed by the CLI.
+#[derive(Debug, Default, Serialize)]
+pub struct DecryptSummary {
+    pub decrypted: usize,
+    pub failed: Vec<Failure>,
+}
+
+impl fmt::Display for DecryptSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for failure in &self.failed {
+            writeln!(
+                f,
+                "failed to decrypt {}: {}",
+                failure.path.display(),
+                failure.error
+            )?;
+        }
+        write!(
+            f,
+            "{} decrypted, {} failed",
+            self.decrypted,
+            self.failed.len()
+        )
+    }
+}
+
+/// Restores the chosen `.crypt` file, or every `.crypt` file below a folder of the crypt folder,
+/// to the location it was encrypted from, see `filecrypt::restore_file`.
+///
+/// # Returns
+///
+/// `true` if every file was restored or skipped.
+pub fn restore(path: &str, policy: ConflictPolicy, prefix: Option<String>) -> Result<bool> {
+    let prefix = prefix.map(PathBuf::from);
+    let folder = get_crypt_folder()?.join(path);
+    let files: Vec<PathBuf> = match !path.is_empty() && folder.is_dir() {
+        true => walk_crypt_folder()?
+            .0
+            .into_iter()
+            .filter(|p| p.starts_with(&folder) && p.extension().is_some_and(|e| e == "crypt"))
+            .collect(),
+        false => vec![chooser(path)?],
+    };
+
+    let mut failures = Vec::new();
+    let (mut restored, mut overwritten, mut renamed, mut skipped) = (0, 0, 0, 0);
+    for file in &files {
+        match restore_file(file, policy, prefix.as_deref()) {
+            Ok((status, target)) => {
+                match status {
+                    RestoreStatus::Restored => restored += 1,
+                    RestoreStatus::Overwritten => overwritten += 1,
+                    RestoreStatus::Renamed => renamed += 1,
+                    RestoreStatus::Skipped => skipped += 1,
+                }
+                send_information(vec![format!(
+                    "{:<12} {}",
+                    status.to_string(),
+                    target.display()
+                )]);
             }
-            println!("decrypt result: {:?}", res);
+            Err(e) => failures.push(format!("failed to restore {}: {}", file.display(), e)),
+        }
+    }
+    let failed = failures.len();
+    if !failures.is_empty() {
+        send_information(failures);
+    }
+    send_information(vec![format!(
+        "{} restored, {} overwritten, {} renamed, {} skipped, {} failed",
+        restored, overwritten, renamed, skipped, failed
+    )]);
+    Ok(failed == 0)
+}
+
+/// Decrypts a `.crypt` stream from stdin, or the `.crypt` file at `path`, to stdout. `path` is
+/// looked up in the crypt folder if it is not a file, the chooser would write to stdout.
+pub fn decrypt_stdio(path: &str) -> Result<()> {
+    let passphrase = || read_passphrase(PASSPHRASE_ENV, "passphrase: ", false).ok();
+    let stdout = io::stdout().lock();
+    if path == "-" {
+        decrypt_to_writer(io::stdin().lock(), stdout, passphrase)?;
+        return Ok(());
+    }
+
+    let file = match Path::new(path).is_file() {
+        true => PathBuf::from(path),
+        false => get_crypt_folder()?.join(path),
+    };
+    decrypt_to_writer(fs::File::open(file)?, stdout, passphrase)?;
+    Ok(())
+}
+
+/// Prints the members of the chosen archive.
+pub fn list_archive(path: &str) -> Result<()> {
+    let entries = filecrypt::list_archive(path)?;
+    let lines = entries
+        .iter()
+        .map(|e| {
+            let kind = match e.kind {
+                EntryKind::Dir => "dir",
+                EntryKind::File => "file",
+            };
+            format!("{:<4} {:04o} {:>12} {}", kind, e.mode, e.size, e.path)
+        })
+        .collect();
+    send_information(lines);
+    Ok(())
+}
+
+/// Extracts `members` of the chosen archive.
+pub fn extract_archive(path: &str, output: Option<String>, members: &[String]) -> Result<()> {
+    let dest = filecrypt::extract_archive(path, output.unwrap_or_default(), members)?;
+    send_information(vec![format!("extracted to: {}", dest.display())]);
+    Ok(())
+}
+
+/// Re-encrypts a keeper tracked file to an age `recipient`, or to a passphrase if there is none.
+pub fn export_age(path: &str, recipient: Option<String>) -> Result<()> {
+    let file = chooser(path)?;
+    let target = match recipient {
+        Some(recipient) => AgeTarget::Recipient(recipient),
+        None => AgeTarget::Passphrase(read_passphrase(PASSPHRASE_ENV, "age passphrase: ", true)?),
+    };
+
+    let age_path = age_file::export_age(file, &target)?;
+    send_information(vec![format!("exported to: {}", age_path.display())]);
+    Ok(())
+}
+
+/// Imports an age file into the keeper, decrypting it with the `identity` file, or with a
+/// passphrase if there is none.
+pub fn import_age(path: &str, identity: Option<String>, output: Option<String>) -> Result<()> {
+    let identity = match identity {
+        Some(identity) => AgeIdentity::File(PathBuf::from(identity)),
+        None => {
+            AgeIdentity::Passphrase(read_passphrase(PASSPHRASE_ENV, "age passphrase: ", false)?)
         }
     };
+
+    let crypt_path = age_file::import_age(path, &identity, &output)?;
+    send_information(vec![format!("imported to: {}", crypt_path.display())]);
+    Ok(())
+}
+
+/// Rotates the key of the chosen `.crypt` file, or of every keeper tracked file with `all`.
+/// With `upload`, rotated files that are already on Google Drive are re-uploaded.
+///
+/// A file that fails to rotate or upload is reported and the others are still rotated.
+///
+/// # Returns
+///
+/// `true` if no file failed.
+pub fn rotate(path: &str, all: bool, upload: bool) -> Result<bool> {
+    let files = match all {
+        true => walk_crypt_folder()?
+            .0
+            .into_iter()
+            .filter(|p| p.extension().is_some_and(|e| e == "crypt"))
+            .collect(),
+        false => vec![chooser(path)?],
+    };
+
+    let mut google: Option<Google> = None;
+    let (mut rotated, mut failed) = (0, 0);
+    for file in files {
+        let (mut fc, new_path) = match rotate_file(&file) {
+            Ok(res) => res,
+            // portable files have no key in the keeper to rotate
+            Err(CoreError::FcError(FcError::NotKeeperTracked)) if all => continue,
+            Err(e) => {
+                send_information(vec![format!("failed to rotate {}: {}", file.display(), e)]);
+                failed += 1;
+                continue;
+            }
+        };
+        rotated += 1;
+        send_information(vec![format!("rotated: {}", new_path.display())]);
+
+        if upload && !fc.drive_id.is_empty() {
+            if google.is_none() {
+                google = Some(Google::new()?);
+            }
+            let google = google.as_ref().unwrap();
+            let updated = google.runtime.block_on(drive::g_update(
+                &google.token,
+                &fc.drive_id,
+                &new_path.to_string_lossy(),
+            ));
+            match updated {
+                Ok(drive_id) => {
+                    fc.drive_id = drive_id;
+                    db::insert_crypt(&fc)?;
+                }
+                Err(e) => {
+                    send_information(vec![format!(
+                        "failed to upload {}: {}",
+                        new_path.display(),
+                        e
+                    )]);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    send_information(vec![format!(
+        "{} files rotated, {} failed",
+        rotated, failed
+    )]);
+    Ok(failed == 0)
+}
+
+/// Verifies every `.crypt` file in the crypt folder and reports the status of each one.
+///
+/// Runs unattended, passphrase protected files are only checked if `CRYPT_PASSPHRASE` is set.
+///
+/// # Returns
+///
+/// `true` if no file failed.
+pub fn verify() -> Result<bool> {
+    let files: Vec<PathBuf> = walk_crypt_folder()?
+        .0
+        .into_iter()
+        .filter(|p| p.extension().is_some_and(|e| e == "crypt"))
+        .collect();
+
+    let mut failed = 0;
+    for file in &files {
+        let (status, ok) = match verify_file(file, || std::env::var(PASSPHRASE_ENV).ok()) {
+            Ok(status) => (status.to_string(), !status.is_failure()),
+            // unreadable files count as failures, keep scanning the rest
+            Err(e) => (e.to_string(), false),
+        };
+        if !ok {
+            failed += 1;
+        }
+        send_information(vec![format!("{:<30} {}", status, file.display())]);
+    }
+
+    send_information(vec![format!(
+        "{} files verified, {} failed",
+        files.len(),
+        failed
+    )]);
+    Ok(failed == 0)
+}
+
+/// Compares the backup folder with the crypt folder and prints the status of every file.
+///
+/// # Returns
+///
+/// `true` if every `.crypt` file has a matching backup and no backup is orphaned.
+pub fn verify_backup() -> Result<bool> {
+    let statuses = backup::verify_backup()?;
+
+    let mut failed = 0;
+    for (file, status) in &statuses {
+        if status.is_failure() {
+            failed += 1;
+        }
+        send_information(vec![format!(
+            "{:<30} {}",
+            status.to_string(),
+            file.display()
+        )]);
+    }
+
+    send_information(vec![format!(
+        "{} files checked against {}, {} failed",
+        statuses.len(),
+        backup::get_backup_folder()?.display(),
+        failed
+    )]);
+    Ok(failed == 0)
 }
 
 // ############################################ Cloud Implementation ############################################
@@ -169,8 +704,7 @@ pub fn google_upload() -> Result<()> {
         // 4. show cloud directory
         let cloud_directory = google
             .runtime
-            .block_on(drive::g_walk(&google.token, "Crypt"))
-            .expect("Could not view directory information");
+            .block_on(drive::g_walk(&google.token, "Crypt"))?;
         send_information(build_tree(&cloud_directory));
     } else {
         // get all our file paths from folder
@@ -179,7 +713,7 @@ pub fn google_upload() -> Result<()> {
         for file in files {
             // get FileCrypt information from keeper
             let mut fc = match get_uuid_from_file(file.as_path()) {
-                Ok(uuid) => db::query_crypt(uuid).unwrap(),
+                Ok(uuid) => db::query_crypt(uuid)?,
                 Err(_) => continue,
             };
 
@@ -237,7 +771,9 @@ pub fn google_upload() -> Result<()> {
                     } else {
                         current = google.runtime.block_on(drive::g_upload(
                             &google.token,
-                            file.to_str().unwrap(),
+                            file.to_str().ok_or_else(|| {
+                                DirectiveError::InvalidInput(file.display().to_string())
+                            })?,
                             &current,
                         ))?;
                         fc.drive_id = current.clone();
@@ -257,11 +793,10 @@ pub fn google_upload() -> Result<()> {
 pub fn google_download(path: &str) -> Result<()> {
     let google = Google::new()?;
 
-    let crypt_folder = get_crypt_folder();
+    let crypt_folder = get_crypt_folder()?;
     let (_files, _) = get_filenames_from_subdirectories(crypt_folder)?;
 
     let file_choice = chooser(path)?;
-    dbg!(&file_choice);
 
     if file_choice.is_file() {
         // get uuid from file
@@ -269,7 +804,6 @@ pub fn google_download(path: &str) -> Result<()> {
 
         // Step 1: get path from the user and verify it exists in our database.
         let fc = query_crypt(uuid)?;
-        dbg!(&fc);
 
         // TODO: Step 1.1: if multiple filecrypts exist for the same filename, then perhaps it's just easier
         // if we download the file, and check uuid.
@@ -279,15 +813,10 @@ pub fn google_download(path: &str) -> Result<()> {
 
         let bytes = google
             .runtime
-            .block_on(drive::google_query_file(&google.token, &fc.drive_id))
-            .unwrap_or(vec![]);
+            .block_on(drive::google_query_file(&google.token, &fc.drive_id))?;
 
-        // TODO: if something went wrong, what do?
         if bytes.is_empty() {
-            send_information(vec![format!(
-                "Failed to get contents of cloud file. Please try again."
-            )]);
-            std::process::exit(2);
+            return Err(DownloadError::EmptyFile(file_choice.display().to_string()).into());
         }
 
         // Step 2.5: unzip / decrypt contents / write to file.
@@ -307,16 +836,16 @@ pub fn google_download(path: &str) -> Result<()> {
 
 // ############################################ Cloud View ############################################
 
-pub fn google_view(path: &str) -> Result<()> {
+/// Gets the tree of the Google Drive folder `path`, showing original names of `.crypt` files.
+pub fn google_view(path: &str) -> Result<DirInfo> {
     let google = Google::new()?;
 
-    let cloud_directory = google
+    let mut cloud_directory = google
         .runtime
-        .block_on(drive::g_walk(&google.token, path))
-        .expect("Could not view directory information");
-    send_information(build_tree(&cloud_directory));
+        .block_on(drive::g_walk(&google.token, path))?;
+    reveal_cloud_names(&mut cloud_directory);
 
-    return Ok(());
+    return Ok(cloud_directory);
 }
 
 pub fn dropbox_upload(_path: &str) {}
@@ -334,8 +863,9 @@ pub fn dropbox_view(_path: &str) {}
 /// let directive = Directive::new("relevant/file.path".to_string());
 /// directive.config(add_remove, item);
 ///```
-pub fn config(path: &str, config_task: ConfigTask) {
-    let mut config = config::get_config_write();
+pub fn config(path: &str, config_task: ConfigTask) -> Result<()> {
+    let vault = default_vault()?;
+    let mut config = vault.config_write();
 
     //Process the directive
     match config_task {
@@ -413,6 +943,77 @@ pub fn config(path: &str, config_task: ConfigTask) {
             }
         },
 
+        ConfigTask::NoCompress(option, ext) => match option {
+            ItemsTask::Add => config.append_no_compress(&ext),
+            ItemsTask::Remove => config.remove_no_compress(&ext),
+            ItemsTask::Default => {
+                let default = Config::default();
+                config.set_no_compress(default.no_compress);
+            }
+        },
+
+        ConfigTask::Cipher(cipher) => match cipher {
+            Some(cipher) => {
+                config.set_cipher(cipher);
+                send_information(vec![format!("cipher changed to: {}", cipher)]);
+            }
+            None => send_information(vec![format!("current cipher: {}", config.get_cipher())]),
+        },
+
+        ConfigTask::NameMode(name_mode) => match name_mode {
+            Some(name_mode) => {
+                config.set_name_mode(name_mode);
+                send_information(vec![format!("name mode changed to: {}", name_mode)]);
+            }
+            None => send_information(vec![format!(
+                "current name mode: {}",
+                config.get_name_mode()
+            )]),
+        },
+
+        ConfigTask::KeyMode(key_mode) => match key_mode {
+            Some(key_mode) => {
+                config.set_key_mode(key_mode);
+                send_information(vec![format!("key mode changed to: {}", key_mode)]);
+            }
+            None => send_information(vec![format!("current key mode: {}", config.get_key_mode())]),
+        },
+
+        ConfigTask::Padding(padding) => match padding {
+            Some(padding) => {
+                config.set_padding(padding);
+                send_information(vec![format!("padding changed to: {}", padding)]);
+            }
+            None => send_information(vec![format!("current padding: {}", config.get_padding())]),
+        },
+
+        ConfigTask::Retain(retain) => match retain {
+            Some(retain) => {
+                config.set_retain(retain);
+                send_information(vec![format!("retain changed to: {}", retain)]);
+            }
+            None => send_information(vec![format!("current retain: {}", config.get_retain())]),
+        },
+
+        ConfigTask::Backup(backup) => match backup {
+            Some(backup) => {
+                config.set_backup(backup);
+                send_information(vec![format!("backup changed to: {}", backup)]);
+            }
+            None => send_information(vec![format!("current backup: {}", config.get_backup())]),
+        },
+
+        ConfigTask::BackupPath => match path {
+            "" => {
+                let path = get_full_file_path(config.get_backup_path());
+                send_information(vec![format!("Current backup Path:\n  {}", path.display())]);
+            }
+            _ => {
+                config.set_backup_path(path);
+                send_information(vec![format!("backup path changed to: {}", path)]);
+            }
+        },
+
         ConfigTask::ZstdLevel(level) => match config.set_zstd_level(level) {
             true => send_information(vec![format!("Zstd Level value changed to: {}", level)]),
             false => send_information(vec![format!("Error occured, please verify parameters")]),
@@ -424,7 +1025,7 @@ pub fn config(path: &str, config_task: ConfigTask) {
                 "An error has occured attmepting to load defaults"
             )]),
         },
-        ConfigTask::IgnoreHidden(_) => todo!(),
+        ConfigTask::IgnoreHidden(choice) => config.set_ignore_hidden(choice),
         ConfigTask::Hwid => {
             if path.is_empty() {
                 send_information(vec![format!("{}", config.get_system_name())]);
@@ -434,19 +1035,68 @@ pub fn config(path: &str, config_task: ConfigTask) {
             config.set_system_name(path);
         }
     };
+    Ok(())
+}
+
+/// A keeper entry as listed by `crypt keeper list`, without its key material.
+#[derive(Debug, Serialize)]
+pub struct CryptEntry {
+    pub uuid: String,
+    pub filename: String,
+    pub ext: String,
+    pub drive_id: String,
+    pub full_path: PathBuf,
+    pub cipher: String,
+    pub key_mode: String,
+    pub size: u64,
+    pub mtime: u64,
 }
 
-pub fn keeper(kc: &KeeperCommand) {
+impl From<&FileCrypt> for CryptEntry {
+    fn from(fc: &FileCrypt) -> Self {
+        CryptEntry {
+            uuid: fc.uuid.clone(),
+            filename: fc.filename.clone(),
+            ext: fc.ext.clone(),
+            drive_id: fc.drive_id.clone(),
+            full_path: fc.full_path.clone(),
+            cipher: fc.cipher.to_string(),
+            key_mode: fc.key_mode.to_string(),
+            size: fc.size,
+            mtime: fc.mtime,
+        }
+    }
+}
+
+impl fmt::Display for CryptEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "file: {}{} \nuuid: {}\nfull file path: {}\ncloud location: {}",
+            self.filename,
+            self.ext,
+            self.uuid,
+            self.full_path.display(),
+            self.drive_id,
+        )
+    }
+}
+
+/// Lists every file in the keeper.
+pub fn keeper_list() -> Result<Vec<CryptEntry>> {
+    Ok(query_keeper_crypt()?.iter().map(CryptEntry::from).collect())
+}
+
+pub fn keeper(kc: &KeeperCommand) -> Result<()> {
     match kc {
         KeeperCommand::Import { path } => {
-            KeeperCommand::import(path);
+            KeeperCommand::import(path)?;
         }
         KeeperCommand::Export { alt_path } => {
-            // TODO: Fix this?
             if alt_path.is_empty() {
-                _ = export_keeper(None);
+                export_keeper(None)?;
             } else {
-                _ = export_keeper(Some(alt_path));
+                export_keeper(Some(alt_path))?;
             };
         }
         KeeperCommand::Purge { category } => match category {
@@ -471,28 +1121,62 @@ pub fn keeper(kc: &KeeperCommand) {
                         break;
                     }
                     if phrase.eq("q") {
-                        return;
+                        return Ok(());
                     }
                 }
-                _ = delete_keeper();
+                delete_keeper()?;
                 send_information(vec![format!("database was deleted.")]);
             }
             None => send_information(vec![format!("invalid entry entered.")]),
         },
         //List
         KeeperCommand::List {} => {
-            let fc = query_keeper_crypt().unwrap();
-            for crypt in fc {
-                println!(
-                    "file: {}{} \nfull file path: {}\ncloud location: {}\n",
-                    crypt.filename,
-                    crypt.ext,
-                    crypt.full_path.display(),
-                    crypt.drive_id,
-                );
-            }
+            send_information(keeper_list()?.iter().map(|e| e.to_string()).collect());
+        }
+        KeeperCommand::Secret { restore } => {
+            let res = match restore {
+                Some(secret) => {
+                    db::restore_key_secret(secret).map(|_| "key secret restored".to_string())
+                }
+                None => db::export_key_secret().map(|s| format!("key secret: {}", s)),
+            };
+            send_information(vec![res?]);
+        }
+    }
+    Ok(())
+}
+
+/// Manages our identity and the recipients files can be encrypted to.
+pub fn recipient(rc: &RecipientCommand) -> Result<()> {
+    match rc {
+        RecipientCommand::Identity { regenerate } => {
+            let identity = match db::query_identity()? {
+                Some(identity) if !regenerate => identity,
+                _ => {
+                    let identity = Identity::generate();
+                    db::insert_identity(&identity)?;
+                    identity
+                }
+            };
+            send_information(vec![format!("public key: {}", identity.recipient())]);
+        }
+        RecipientCommand::Add { name, key } => {
+            db::insert_recipient(name, &key.parse()?)?;
+            send_information(vec![format!("added recipient: {}", name)]);
+        }
+        RecipientCommand::Remove { name } => {
+            db::delete_recipient(name)?;
+            send_information(vec![format!("removed recipient: {}", name)]);
+        }
+        RecipientCommand::List {} => {
+            let recipients = db::query_keeper_recipients()?
+                .into_iter()
+                .map(|(name, key)| format!("{}: {}", name, key))
+                .collect();
+            send_information(recipients);
         }
     }
+    Ok(())
 }
 
 // Function to write the file to the base file path
@@ -532,19 +1216,80 @@ pub fn merge_base_with_relative_path(
     Ok(target_path)
 }
 
-pub fn ls(local: &bool, cloud: &bool) {
-    let crypt_root = get_crypt_folder();
+/// The local crypt folder and/or the Google Drive crypt folder, as shown by `crypt ls`.
+#[derive(Debug, Default, Serialize)]
+pub struct Listing {
+    /// path of the local crypt folder.
+    pub root: PathBuf,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub local: Option<Directory>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cloud: Option<DirInfo>,
+}
 
-    let dir: Directory = dir_walk(&crypt_root.clone(), is_not_hidden, sort_by_name).unwrap();
+impl Listing {
+    /// Prints the trees for the user.
+    pub fn print(&self) {
+        if let Some(dir) = &self.local {
+            print_tree(&self.root.to_string_lossy(), dir);
+        }
+        if let Some(cloud) = &self.cloud {
+            send_information(build_tree(cloud));
+        }
+    }
+}
 
-    match (local, cloud) {
-        // display both
-        (true, true) => todo!(),
-        // display local only
-        (_, false) => print_tree(crypt_root.to_str().unwrap(), &dir),
-        // display cloud only
-        (_, true) => _ = google_view("Crypt"),
+/// Lists the local crypt folder, unless only `cloud` is set, and the Google Drive crypt folder
+/// with `cloud`.
+pub fn ls(local: &bool, cloud: &bool) -> Result<Listing> {
+    let mut listing = Listing {
+        root: get_crypt_folder()?,
+        ..Default::default()
     };
+
+    if *local || !*cloud {
+        let mut dir: Directory = dir_walk(&listing.root, is_not_hidden, sort_by_name)?;
+        reveal_local_names(&mut dir, &listing.root);
+        listing.local = Some(dir);
+    }
+    if *cloud {
+        listing.cloud = Some(google_view("Crypt")?);
+    }
+    Ok(listing)
+}
+
+/// Shows the original name next to every `.crypt` file under `dir`, which is located at `path`.
+fn reveal_local_names(dir: &mut Directory, path: &Path) {
+    for entry in dir.entries.iter_mut() {
+        match entry {
+            FileTree::DirNode(sub) => {
+                let sub_path = path.join(&sub.name);
+                reveal_local_names(sub, &sub_path);
+            }
+            FileTree::FileNode(file) => {
+                if let Some(name) = resolve_crypt_name(path.join(&file.name)) {
+                    file.name = format!("{} ({})", name, file.name);
+                }
+            }
+            FileTree::LinkNode(_) => (),
+        }
+    }
+}
+
+/// Shows the original name next to every uuid or encrypted `.crypt` name in a cloud listing.
+fn reveal_cloud_names(dir: &mut DirInfo) {
+    for node in dir.contents.iter_mut() {
+        match node {
+            FsNode::Directory(sub) => reveal_cloud_names(sub),
+            FsNode::File(file) => {
+                if let Some(name) = resolve_crypt_name(&file.name) {
+                    file.name = format!("{} ({})", name, file.name);
+                }
+            }
+        }
+    }
 }
 
 // ===========================================================
@@ -567,10 +1312,39 @@ pub fn test() {
 
     // let res = runtime.block_on(drive::google_query(&user_token, &crypt_folder));
     // println!("{:#?}", res);
-    let crypt = get_crypt_folder();
+    let crypt = get_crypt_folder().unwrap();
     let (mut left, mut right) = get_filenames_from_subdirectories(crypt).unwrap();
     left.append(&mut right);
 
     let res = chooser("");
     println!("{:#?}", res);
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_summary_json() {
+        let mut summary = EncryptSummary {
+            encrypted: 2,
+            unchanged: 1,
+            failed: vec![Failure::new(Path::new("a.txt"), "no such file")],
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "encrypted": 2,
+                "updated": 0,
+                "unchanged": 1,
+                "failed": [{ "path": "a.txt", "error": "no such file" }],
+            })
+        );
+
+        summary.archive = Some(PathBuf::from("dir.crypt"));
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["archive"], "dir.crypt");
+    }
+}
@@ -1,8 +1,35 @@
 use thiserror::Error;
 
+use crypt_cloud::crypt_core::error::{CommonError, DatabaseError, EncryptionError, FcError};
 use crypt_cloud::crypt_core::prelude::Error as core_error;
 use crypt_cloud::prelude::Error as cloud_error;
 
+/// Exit code: the command ran, but some files failed, e.g. `verify` found a tampered file.
+/// Also used for errors that fit no other category.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Exit code: invalid arguments, as for `clap` usage errors.
+pub const EXIT_USAGE: i32 = 2;
+
+/// Exit code: the keeper is locked, the keeper passphrase is wrong, or the database failed.
+pub const EXIT_KEEPER: i32 = 3;
+
+/// Exit code: a file, archive member or crypt folder entry was not found.
+pub const EXIT_NOT_FOUND: i32 = 4;
+
+/// Exit code: a file failed authentication or is not a valid `.crypt` file, e.g. tampered
+/// contents or a wrong file passphrase.
+pub const EXIT_INTEGRITY: i32 = 5;
+
+/// Exit code: reading or writing a file failed.
+pub const EXIT_IO: i32 = 6;
+
+/// Exit code: a cloud provider or its token failed.
+pub const EXIT_CLOUD: i32 = 7;
+
+/// Exit code: the user aborted, e.g. by choosing 0 in the chooser.
+pub const EXIT_ABORTED: i32 = 8;
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error(transparent)]
@@ -17,6 +44,9 @@ pub enum Error {
     #[error(transparent)]
     UploadError(#[from] UploadError),
 
+    #[error(transparent)]
+    DownloadError(#[from] DownloadError),
+
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 
@@ -24,12 +54,103 @@ pub enum Error {
     StdError(#[from] Box<dyn std::error::Error>),
 }
 
+impl Error {
+    /// Gets the exit code of the `crypt` binary for this error, see `EXIT_FAILURE` and the
+    /// other `EXIT_` constants.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::CoreError(e) | Error::CloudError(cloud_error::CoreError(e)) => core_exit_code(e),
+            Error::CloudError(cloud_error::IoError(e)) | Error::IoError(e) => io_exit_code(e),
+            Error::CloudError(_) => EXIT_CLOUD,
+            Error::DirectiveError(e) => match e {
+                DirectiveError::RemoteCryptDirectoryAccessError => EXIT_CLOUD,
+                DirectiveError::PathNotFound(_) => EXIT_NOT_FOUND,
+                DirectiveError::PassphraseMismatch
+                | DirectiveError::StreamUnsupported
+                | DirectiveError::NotADirectory(_)
+                | DirectiveError::InvalidInput(_) => EXIT_USAGE,
+            },
+            Error::UploadError(UploadError::UserAbortedError) => EXIT_ABORTED,
+            Error::UploadError(UploadError::NoCryptFilesFound) => EXIT_NOT_FOUND,
+            Error::DownloadError(_) => EXIT_CLOUD,
+            Error::StdError(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+fn core_exit_code(e: &core_error) -> i32 {
+    match e {
+        core_error::DatabaseError(DatabaseError::UnknownRecipient(_)) => EXIT_NOT_FOUND,
+        core_error::DatabaseError(DatabaseError::InvalidKeySecret) => EXIT_USAGE,
+        core_error::DatabaseError(_)
+        | core_error::DbPoolingError(_)
+        | core_error::DbError(_)
+        | core_error::CsvError(_) => EXIT_KEEPER,
+        core_error::TokenError(_) => EXIT_CLOUD,
+        core_error::FcError(e) => match e {
+            FcError::FileReadError(_) => EXIT_IO,
+            FcError::FileDeletionError(e, _) => io_exit_code(e),
+            FcError::ArchiveMemberNotFound(_) => EXIT_NOT_FOUND,
+            FcError::PassphraseRequired
+            | FcError::ArchiveStream
+            | FcError::ArchiveMemberName(_)
+            | FcError::HeaderTooLarge(_) => EXIT_USAGE,
+            FcError::Unverified(_) => EXIT_FAILURE,
+            _ => EXIT_INTEGRITY,
+        },
+        core_error::EncryptionError(e) => match e {
+            EncryptionError::UnknownCipherName(_)
+            | EncryptionError::InvalidRecipient(_)
+            | EncryptionError::UnknownKeyMode(_) => EXIT_USAGE,
+            _ => EXIT_INTEGRITY,
+        },
+        core_error::CommonError(e) => match e {
+            CommonError::CryptFolderIsEmpty => EXIT_NOT_FOUND,
+            CommonError::UserAbort => EXIT_ABORTED,
+            _ => EXIT_USAGE,
+        },
+        core_error::AgeDecryptError(_) => EXIT_INTEGRITY,
+        core_error::IoError(e) => io_exit_code(e),
+        core_error::WalkDirError(_) => EXIT_IO,
+        _ => EXIT_FAILURE,
+    }
+}
+
+fn io_exit_code(e: &std::io::Error) -> i32 {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => EXIT_NOT_FOUND,
+        _ => EXIT_IO,
+    }
+}
+
 /// Generic Cloud Errors
 #[derive(Debug, Error)]
 pub enum DirectiveError {
     /// Error accessing Crypt "root" folder
     #[error("Error accessing Crypt 'root' folder")]
     RemoteCryptDirectoryAccessError,
+
+    /// The passphrase and its confirmation did not match
+    #[error("passphrases do not match")]
+    PassphraseMismatch,
+
+    /// stdin/stdout streams are keeper tracked files
+    #[error(
+        "- (stdin/stdout) cannot be used with --passphrase, --recipient, --dictionary or --archive"
+    )]
+    StreamUnsupported,
+
+    /// The path given to a command does not exist
+    #[error("could not find path: {0}")]
+    PathNotFound(String),
+
+    /// The path given to a command is not a directory
+    #[error("not a directory: {0}")]
+    NotADirectory(String),
+
+    /// An argument did not parse
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
 }
 
 #[derive(Debug, Error)]
@@ -44,4 +165,85 @@ pub enum UploadError {
 }
 
 #[derive(Debug, Error)]
-pub enum DownloadError {}
+pub enum DownloadError {
+    /// Generated if the cloud copy of a file came back empty.
+    #[error("failed to get the contents of the cloud file: {0}")]
+    EmptyFile(String),
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Error as IoError, ErrorKind};
+
+    use super::*;
+
+    fn core(e: impl Into<core_error>) -> Error {
+        Error::CoreError(e.into())
+    }
+
+    #[test]
+    fn test_core_exit_codes() {
+        assert_eq!(core(FcError::Tampered).exit_code(), EXIT_INTEGRITY);
+        assert_eq!(core(FcError::PassphraseRequired).exit_code(), EXIT_USAGE);
+        assert_eq!(
+            core(FcError::Unverified("file".to_string())).exit_code(),
+            EXIT_FAILURE
+        );
+        assert_eq!(core(DatabaseError::KeeperLocked).exit_code(), EXIT_KEEPER);
+        assert_eq!(
+            core(DatabaseError::UnknownRecipient("bob".to_string())).exit_code(),
+            EXIT_NOT_FOUND
+        );
+        assert_eq!(
+            core(EncryptionError::UnknownCipherName("rot13".to_string())).exit_code(),
+            EXIT_USAGE
+        );
+        assert_eq!(core(CommonError::UserAbort).exit_code(), EXIT_ABORTED);
+        assert_eq!(
+            core(CommonError::CryptFolderIsEmpty).exit_code(),
+            EXIT_NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_io_exit_codes() {
+        let not_found = || IoError::from(ErrorKind::NotFound);
+        assert_eq!(Error::IoError(not_found()).exit_code(), EXIT_NOT_FOUND);
+        assert_eq!(core(not_found()).exit_code(), EXIT_NOT_FOUND);
+        assert_eq!(
+            core(FcError::FileDeletionError(not_found(), "file".to_string())).exit_code(),
+            EXIT_NOT_FOUND
+        );
+        assert_eq!(
+            Error::IoError(IoError::from(ErrorKind::PermissionDenied)).exit_code(),
+            EXIT_IO
+        );
+    }
+
+    #[test]
+    fn test_ui_exit_codes() {
+        // core errors keep their code when they come through the cloud crate
+        let cloud = Error::CloudError(cloud_error::CoreError(FcError::Tampered.into()));
+        assert_eq!(cloud.exit_code(), EXIT_INTEGRITY);
+        assert_eq!(
+            Error::CloudError(cloud_error::FileIdError).exit_code(),
+            EXIT_CLOUD
+        );
+        assert_eq!(
+            Error::from(DirectiveError::PathNotFound("file".to_string())).exit_code(),
+            EXIT_NOT_FOUND
+        );
+        assert_eq!(
+            Error::from(DirectiveError::InvalidInput("x".to_string())).exit_code(),
+            EXIT_USAGE
+        );
+        assert_eq!(
+            Error::from(UploadError::UserAbortedError).exit_code(),
+            EXIT_ABORTED
+        );
+        assert_eq!(
+            Error::from(DownloadError::EmptyFile("file".to_string())).exit_code(),
+            EXIT_CLOUD
+        );
+    }
+}